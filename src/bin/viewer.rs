@@ -0,0 +1,116 @@
+use iced::{
+    slider, Alignment, Column, Container, Element, Length, Sandbox, Settings,
+    Slider, Text, Image, image::Handle,
+};
+
+use mini3d_rust::{Material, Mesh, Renderer, Texture, Triangle};
+
+#[path = "../demo.rs"]
+mod demo;
+
+use demo::{build_rasterizer, load_scene};
+
+pub fn main() -> iced::Result {
+    SoftRender::run(Settings::default())
+}
+
+struct SoftRender {
+    radius: f32,
+    slider: slider::State,
+    texture: Vec<Texture>,
+    materials: Vec<Material>,
+    meshes: Vec<Mesh>,
+    /// 生成阴影贴图用
+    t: Vec<Triangle>
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Message {
+    RadiusChanged(f32),
+}
+
+impl Sandbox for SoftRender {
+    type Message = Message;
+
+    fn new() -> SoftRender {
+        let scene = match load_scene() {
+            Ok(scene) => scene,
+            Err(err) => panic!("failed to load model: {}", err),
+        };
+        let t = scene.meshes.iter().flat_map(|m| m.triangles()).collect();
+        SoftRender {
+            radius: 50.0,
+            slider: slider::State::new(),
+            texture: scene.textures,
+            materials: scene.materials,
+            meshes: scene.meshes,
+            t
+        }
+    }
+
+    fn title(&self) -> String {
+        String::from("mini3d-rs")
+    }
+
+    fn update(&mut self, message: Message) {
+        match message {
+            Message::RadiusChanged(radius) => {
+                self.radius = radius;
+            }
+        }
+    }
+
+    fn view(&mut self) -> Element<'_, Message> {
+        let mut rasterizer = build_rasterizer((self.radius - 50.0) * 180.0 / 50.0, 1.0);
+        rasterizer.set_materials(self.materials.clone());
+        rasterizer.update_shadow_maps(&mut self.t);
+        let mut renderer = Renderer::new(512, 512);
+        for mesh in self.meshes.iter() {
+            renderer.draw_mesh(&rasterizer, mesh, &self.texture);
+        }
+
+        let handle = Handle::from_pixels(512, 512, renderer.to_bgra());
+        let content = Column::new()
+            .padding(20)
+            .spacing(20)
+            .max_width(500)
+            .align_items(Alignment::Center)
+            .push(Text::new(format!("Radius: {:.2}", self.radius)))
+			.push(Image::new(handle).width(Length::Fill).height(Length::Fill))
+            .push(
+                Slider::new(
+                    &mut self.slider,
+                    1.0..=100.0,
+                    self.radius,
+                    Message::RadiusChanged,
+                )
+                .step(0.01),
+            );
+
+        Container::new(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .center_x()
+            .center_y()
+            .into()
+    }
+
+    fn background_color(&self) -> iced::Color {
+        iced::Color::WHITE
+    }
+
+    fn scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    fn should_exit(&self) -> bool {
+        false
+    }
+
+    fn run(settings: Settings<()>) -> Result<(), iced::Error>
+    where
+        Self: 'static + Sized,
+    {
+        <Self as iced::Application>::run(settings)
+    }
+}
@@ -0,0 +1,411 @@
+use std::f32::consts::PI;
+
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::Vector3f;
+
+use super::pbr::{distribution_ggx, geometry_schlick_ggx};
+
+/// 等距柱状投影(equirectangular)的HDR环境贴图，线性空间RGB
+/// 方向和贴图的对应：+y朝上，-z在u=0.5处，v=0是正上方
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    data: Vec<[f32; 3]>,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: u32, height: u32, data: Vec<[f32; 3]>) -> EnvironmentMap {
+        assert_eq!(data.len(), (width * height) as usize);
+        EnvironmentMap { width, height, data }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [f32; 3] {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// 双线性采样，u方向循环，v方向截断
+    pub fn sample(&self, dir: &[f32; 3]) -> [f32; 3] {
+        let (u, v) = direction_to_uv(dir);
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let w = self.width as i32;
+        let x0 = (x0 as i32).rem_euclid(w) as u32;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y0 as u32;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let mut r = [0.0; 3];
+        let (c00, c10) = (self.get_pixel(x0, y0), self.get_pixel(x1, y0));
+        let (c01, c11) = (self.get_pixel(x0, y1), self.get_pixel(x1, y1));
+        for k in 0..3 {
+            let top = c00[k] + (c10[k] - c00[k]) * fx;
+            let bottom = c01[k] + (c11[k] - c01[k]) * fx;
+            r[k] = top + (bottom - top) * fy;
+        }
+        r
+    }
+
+    /// 宽高减半的box filter，最小1x1
+    pub fn downsample(&self) -> EnvironmentMap {
+        let w = (self.width / 2).max(1);
+        let h = (self.height / 2).max(1);
+        let mut data = Vec::with_capacity((w * h) as usize);
+        for y in 0..h {
+            for x in 0..w {
+                let mut sum = [0.0; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(self.width - 1);
+                    let sy = (y * 2 + dy).min(self.height - 1);
+                    let p = self.get_pixel(sx, sy);
+                    for k in 0..3 {
+                        sum[k] += p[k] / 4.0;
+                    }
+                }
+                data.push(sum);
+            }
+        }
+        EnvironmentMap::new(w, h, data)
+    }
+
+    /// 第y行像素中心的方向和它占的立体角
+    fn texel(&self, x: u32, y: u32) -> ([f32; 3], f32) {
+        let u = (x as f32 + 0.5) / self.width as f32;
+        let v = (y as f32 + 0.5) / self.height as f32;
+        let d = uv_to_direction(u, v);
+        let solid_angle = (2.0 * PI / self.width as f32) * (PI / self.height as f32) * (v * PI).sin();
+        (d, solid_angle)
+    }
+}
+
+/// 单位方向转成贴图坐标(0~1)
+pub fn direction_to_uv(dir: &[f32; 3]) -> (f32, f32) {
+    let u = dir[0].atan2(-dir[2]) / (2.0 * PI) + 0.5;
+    let v = dir[1].clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+pub fn uv_to_direction(u: f32, v: f32) -> [f32; 3] {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    [theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()]
+}
+
+/// split-sum近似里的BRDF积分，下标是(n_dot_v, roughness)，值是F0的缩放和偏移
+pub struct BrdfLut {
+    size: u32,
+    data: Vec<[f32; 2]>,
+}
+
+impl BrdfLut {
+    pub fn new(size: u32, samples: u32) -> BrdfLut {
+        let mut data = Vec::with_capacity((size * size) as usize);
+        for y in 0..size {
+            let roughness = (y as f32 + 0.5) / size as f32;
+            for x in 0..size {
+                let n_dot_v = (x as f32 + 0.5) / size as f32;
+                data.push(integrate_brdf(n_dot_v, roughness, samples));
+            }
+        }
+        BrdfLut { size, data }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// 双线性查表，返回(scale, bias)，镜面反射 = prefiltered * (F0 * scale + bias)
+    pub fn lookup(&self, n_dot_v: f32, roughness: f32) -> (f32, f32) {
+        let max = (self.size - 1) as f32;
+        let x = (n_dot_v * self.size as f32 - 0.5).clamp(0.0, max);
+        let y = (roughness * self.size as f32 - 0.5).clamp(0.0, max);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: u32, y: u32| self.data[(y * self.size + x) as usize];
+
+        let mut r = [0.0; 2];
+        for (k, v) in r.iter_mut().enumerate() {
+            let top = at(x0, y0)[k] + (at(x1, y0)[k] - at(x0, y0)[k]) * fx;
+            let bottom = at(x0, y1)[k] + (at(x1, y1)[k] - at(x0, y1)[k]) * fx;
+            *v = top + (bottom - top) * fy;
+        }
+        (r[0], r[1])
+    }
+}
+
+/// 预计算好的基于图像的光照，采样方向在世界空间中
+/// 着色在相机空间进行，采样前用view_to_world把法线和反射方向转换到世界空间
+pub struct Environment {
+    /// radiance[0]是原图，后面是逐级缩小的mipmap
+    radiance: Vec<EnvironmentMap>,
+    /// 漫反射辐照度，已经除过π，漫反射 = irradiance * albedo
+    irradiance: EnvironmentMap,
+    /// 按roughness从0到1均匀分布的预过滤镜面反射
+    specular: Vec<EnvironmentMap>,
+    brdf_lut: BrdfLut,
+    pub intensity: f32,
+}
+
+const IRRADIANCE_WIDTH: u32 = 32;
+const SPECULAR_WIDTH: u32 = 256;
+const SPECULAR_LEVELS: usize = 6;
+const SPECULAR_SAMPLES: u32 = 64;
+const BRDF_LUT_SIZE: u32 = 32;
+const BRDF_LUT_SAMPLES: u32 = 256;
+
+impl Environment {
+    /// 在CPU上预计算辐照度、预过滤镜面反射和BRDF查找表
+    pub fn new(radiance: EnvironmentMap) -> Environment {
+        let mut levels = vec![radiance];
+        while levels.last().unwrap().width() > 1 || levels.last().unwrap().height() > 1 {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+
+        let irradiance = compute_irradiance(&levels);
+        let specular = (0..SPECULAR_LEVELS)
+            .map(|i| {
+                let roughness = i as f32 / (SPECULAR_LEVELS - 1) as f32;
+                let width = (SPECULAR_WIDTH >> i).max(8);
+                prefilter_specular(&levels, roughness, width)
+            })
+            .collect();
+
+        Environment {
+            radiance: levels,
+            irradiance,
+            specular,
+            brdf_lut: BrdfLut::new(BRDF_LUT_SIZE, BRDF_LUT_SAMPLES),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    pub fn get_radiance(&self) -> &EnvironmentMap {
+        &self.radiance[0]
+    }
+
+    /// 直接看到的环境颜色
+    pub fn background(&self, dir: &Vector3f) -> Vector3f {
+        self.scaled(self.radiance[0].sample(&normalized(dir)))
+    }
+
+    /// 法线方向上的漫反射辐照度(除过π)
+    pub fn irradiance(&self, normal: &Vector3f) -> Vector3f {
+        self.scaled(self.irradiance.sample(&normalized(normal)))
+    }
+
+    /// 反射方向上按roughness预过滤的环境光，在相邻两级之间插值
+    pub fn specular(&self, reflected: &Vector3f, roughness: f32) -> Vector3f {
+        let d = normalized(reflected);
+        let level = roughness.clamp(0.0, 1.0) * (self.specular.len() - 1) as f32;
+        let l0 = level.floor() as usize;
+        let l1 = (l0 + 1).min(self.specular.len() - 1);
+        let t = level - l0 as f32;
+        let (c0, c1) = (self.specular[l0].sample(&d), self.specular[l1].sample(&d));
+        self.scaled([
+            c0[0] + (c1[0] - c0[0]) * t,
+            c0[1] + (c1[1] - c0[1]) * t,
+            c0[2] + (c1[2] - c0[2]) * t,
+        ])
+    }
+
+    pub fn brdf(&self, n_dot_v: f32, roughness: f32) -> (f32, f32) {
+        self.brdf_lut.lookup(n_dot_v, roughness)
+    }
+
+    fn scaled(&self, c: [f32; 3]) -> Vector3f {
+        Vector3f::new_3(c[0] * self.intensity, c[1] * self.intensity, c[2] * self.intensity)
+    }
+}
+
+/// 相机空间的方向转换到世界空间，乘view的转置
+/// 对法线这就是view_inverse的逆转置；view是刚体变换时也等于view_inverse
+pub fn view_to_world(view: &Mat4x4f, d: &Vector3f) -> Vector3f {
+    let v = &view.m;
+    Vector3f::new_3(
+        v[0][0] * d.x() + v[1][0] * d.y() + v[2][0] * d.z(),
+        v[0][1] * d.x() + v[1][1] * d.y() + v[2][1] * d.z(),
+        v[0][2] * d.x() + v[1][2] * d.y() + v[2][2] * d.z(),
+    )
+}
+
+fn normalized(v: &Vector3f) -> [f32; 3] {
+    let l = v.length();
+    if l > 0.0 { [v.x() / l, v.y() / l, v.z() / l] } else { [0.0, 1.0, 0.0] }
+}
+
+fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// 对每个输出方向在半球上按cos加权积分，源图先缩小到64像素宽
+fn compute_irradiance(levels: &[EnvironmentMap]) -> EnvironmentMap {
+    let source = levels.iter().find(|l| l.width() <= 64).unwrap_or(levels.last().unwrap());
+    let texels: Vec<([f32; 3], f32, [f32; 3])> = (0..source.height())
+        .flat_map(|y| (0..source.width()).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (d, sa) = source.texel(x, y);
+            (d, sa, source.get_pixel(x, y))
+        })
+        .collect();
+
+    let w = IRRADIANCE_WIDTH;
+    let h = w / 2;
+    let out = EnvironmentMap::new(w, h, vec![[0.0; 3]; (w * h) as usize]);
+    let mut data = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let (n, _) = out.texel(x, y);
+            let mut sum = [0.0; 3];
+            for (d, sa, c) in texels.iter() {
+                let cos = dot(&n, d);
+                if cos > 0.0 {
+                    for k in 0..3 {
+                        sum[k] += c[k] * cos * sa;
+                    }
+                }
+            }
+            data.push([sum[0] / PI, sum[1] / PI, sum[2] / PI]);
+        }
+    }
+    EnvironmentMap::new(w, h, data)
+}
+
+/// GGX重要性采样预过滤，假设n = v = r，按采样的立体角选择源图的mipmap等级来减少噪点
+fn prefilter_specular(levels: &[EnvironmentMap], roughness: f32, width: u32) -> EnvironmentMap {
+    let h = (width / 2).max(1);
+    let out = EnvironmentMap::new(width, h, vec![[0.0; 3]; (width * h) as usize]);
+    if roughness == 0.0 {
+        let mut data = Vec::with_capacity((width * h) as usize);
+        for y in 0..h {
+            for x in 0..width {
+                data.push(levels[0].sample(&out.texel(x, y).0));
+            }
+        }
+        return EnvironmentMap::new(width, h, data);
+    }
+
+    let base = &levels[0];
+    let texel_solid_angle = 4.0 * PI / (base.width() * base.height()) as f32;
+    let mut data = Vec::with_capacity((width * h) as usize);
+    for y in 0..h {
+        for x in 0..width {
+            let (n, _) = out.texel(x, y);
+            let mut sum = [0.0; 3];
+            let mut weight = 0.0;
+            for i in 0..SPECULAR_SAMPLES {
+                let xi = hammersley(i, SPECULAR_SAMPLES);
+                let hv = importance_sample_ggx(xi, &n, roughness);
+                let v_dot_h = dot(&n, &hv);
+                let l = [
+                    2.0 * v_dot_h * hv[0] - n[0],
+                    2.0 * v_dot_h * hv[1] - n[1],
+                    2.0 * v_dot_h * hv[2] - n[2],
+                ];
+                let n_dot_l = dot(&n, &l);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+
+                //n = v时pdf = D / 4
+                let n_dot_h = v_dot_h.max(0.0);
+                let pdf = distribution_ggx(n_dot_h, roughness) / 4.0 + 1e-4;
+                let sample_solid_angle = 1.0 / (SPECULAR_SAMPLES as f32 * pdf);
+                let lod = (0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0).max(0.0);
+                let c = sample_levels(levels, &l, lod);
+                for k in 0..3 {
+                    sum[k] += c[k] * n_dot_l;
+                }
+                weight += n_dot_l;
+            }
+            data.push([sum[0] / weight, sum[1] / weight, sum[2] / weight]);
+        }
+    }
+    EnvironmentMap::new(width, h, data)
+}
+
+fn sample_levels(levels: &[EnvironmentMap], dir: &[f32; 3], lod: f32) -> [f32; 3] {
+    let lod = lod.min((levels.len() - 1) as f32);
+    let l0 = lod.floor() as usize;
+    let l1 = (l0 + 1).min(levels.len() - 1);
+    let t = lod - l0 as f32;
+    let (c0, c1) = (levels[l0].sample(dir), levels[l1].sample(dir));
+    [c0[0] + (c1[0] - c0[0]) * t, c0[1] + (c1[1] - c0[1]) * t, c0[2] + (c1[2] - c0[2]) * t]
+}
+
+/// 低差异序列
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    (i as f32 / n as f32, i.reverse_bits() as f32 * 2.328_306_4e-10)
+}
+
+/// 按GGX分布采样半程向量，返回n所在切线空间转换后的方向
+fn importance_sample_ggx(xi: (f32, f32), n: &[f32; 3], roughness: f32) -> [f32; 3] {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.0;
+    let cos_theta = ((1.0 - xi.1) / (1.0 + (a * a - 1.0) * xi.1)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let h = [phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta];
+
+    let up = if n[2].abs() < 0.999 { [0.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0] };
+    let tangent = normalize3(cross3(&up, n));
+    let bitangent = cross3(n, &tangent);
+    [
+        tangent[0] * h[0] + bitangent[0] * h[1] + n[0] * h[2],
+        tangent[1] * h[0] + bitangent[1] * h[1] + n[1] * h[2],
+        tangent[2] * h[0] + bitangent[2] * h[1] + n[2] * h[2],
+    ]
+}
+
+fn cross3(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize3(v: [f32; 3]) -> [f32; 3] {
+    let l = dot(&v, &v).sqrt();
+    [v[0] / l, v[1] / l, v[2] / l]
+}
+
+/// n固定为(0,0,1)，积分得到F0的缩放和偏移，IBL的几何项k = r^2/2
+fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> [f32; 2] {
+    let v = [(1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v];
+    let n = [0.0, 0.0, 1.0];
+    let k = roughness * roughness / 2.0;
+    let (mut a, mut b) = (0.0, 0.0);
+    for i in 0..samples {
+        let h = importance_sample_ggx(hammersley(i, samples), &n, roughness);
+        let v_dot_h = dot(&v, &h);
+        let l = [
+            2.0 * v_dot_h * h[0] - v[0],
+            2.0 * v_dot_h * h[1] - v[1],
+            2.0 * v_dot_h * h[2] - v[2],
+        ];
+        let n_dot_l = l[2].max(0.0);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let n_dot_h = h[2].max(0.0);
+        let v_dot_h = v_dot_h.max(0.0);
+        let g = geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+        let g_vis = g * v_dot_h / (n_dot_h * n_dot_v).max(1e-6);
+        let fc = (1.0 - v_dot_h).powi(5);
+        a += (1.0 - fc) * g_vis;
+        b += fc * g_vis;
+    }
+    [a / samples as f32, b / samples as f32]
+}
@@ -1,52 +1,265 @@
-use crate::math::vector::{Vector3f, Vector4f};
+use crate::math::vector::Vector3f;
 
+use super::shadow::{CubeShadowMap, ShadowMap, ShadowSettings};
+
+/// 点光源和聚光灯的衰减 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Attenuation {
+        Attenuation { constant, linear, quadratic }
+    }
+
+    /// 物理上的平方反比衰减
+    pub fn inverse_square() -> Attenuation {
+        Attenuation::new(0.0, 0.0, 1.0)
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        let d = self.constant + self.linear * distance + self.quadratic * distance * distance;
+        if d > 0.0 { 1.0 / d } else { 1.0 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LightType {
+    /// 平行光，direction是光线照射的方向
+    Directional {
+        direction: Vector3f,
+    },
+    /// range之外没有光照，到range时平滑衰减到0
+    Point {
+        position: Vector3f,
+        attenuation: Attenuation,
+        range: f32,
+    },
+    /// 聚光灯，角度是和direction的夹角(度)，inner以内全亮，outer以外没有光照
+    Spot {
+        position: Vector3f,
+        direction: Vector3f,
+        attenuation: Attenuation,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Clone)]
 pub struct Light {
-    position: Vector3f,
-    intensity: Vector3f
+    pub light_type: LightType,
+    pub intensity: Vector3f,
+    /// 为Some时投射阴影
+    pub shadow_settings: Option<ShadowSettings>,
+    /// 由Rasterizer::update_shadow_maps生成，平行光和聚光灯使用
+    pub shadow_map: Option<ShadowMap>,
+    /// 点光源使用
+    pub cube_shadow_map: Option<CubeShadowMap>
 }
 
 impl Light {
+    /// 平方反比衰减、没有范围限制的点光源
     pub fn new(p: Vector3f, i: Vector3f) -> Light {
+        Light::point(p, i, Attenuation::inverse_square(), f32::INFINITY)
+    }
+
+    pub fn directional(direction: Vector3f, intensity: Vector3f) -> Light {
+        let mut direction = direction;
+        direction.normlize();
         Light {
-            position: p,
-            intensity: i
+            light_type: LightType::Directional { direction },
+            intensity,
+            shadow_settings: None,
+            shadow_map: None,
+            cube_shadow_map: None
+        }
+    }
+
+    pub fn point(position: Vector3f, intensity: Vector3f, attenuation: Attenuation, range: f32) -> Light {
+        Light {
+            light_type: LightType::Point { position, attenuation, range },
+            intensity,
+            shadow_settings: None,
+            shadow_map: None,
+            cube_shadow_map: None
+        }
+    }
+
+    pub fn spot(position: Vector3f, direction: Vector3f, intensity: Vector3f, inner_angle: f32, outer_angle: f32) -> Light {
+        let mut direction = direction;
+        direction.normlize();
+        Light {
+            light_type: LightType::Spot {
+                position,
+                direction,
+                attenuation: Attenuation::inverse_square(),
+                range: f32::INFINITY,
+                inner_angle,
+                outer_angle: outer_angle.max(inner_angle),
+            },
+            intensity,
+            shadow_settings: None,
+            shadow_map: None,
+            cube_shadow_map: None
+        }
+    }
+
+    pub fn enable_shadows(&mut self, settings: ShadowSettings) {
+        self.shadow_settings = Some(settings);
+    }
+
+    pub fn disable_shadows(&mut self) {
+        self.shadow_settings = None;
+        self.shadow_map = None;
+        self.cube_shadow_map = None;
+    }
+
+    /// pos处没有被遮挡的比例，没有阴影贴图时是1
+    pub fn shadow_visibility(&self, pos: &Vector3f, n_dot_l: f32) -> f32 {
+        if let Some(map) = &self.shadow_map {
+            return map.visibility(pos, n_dot_l);
+        }
+        if let Some(map) = &self.cube_shadow_map {
+            return map.visibility(pos, n_dot_l);
+        }
+        1.0
+    }
+
+    /// 修改点光源或聚光灯的衰减，平行光忽略
+    pub fn set_attenuation(&mut self, attenuation: Attenuation, range: f32) {
+        match &mut self.light_type {
+            LightType::Directional { .. } => {}
+            LightType::Point { attenuation: a, range: r, .. } | LightType::Spot { attenuation: a, range: r, .. } => {
+                *a = attenuation;
+                *r = range;
+            }
+        }
+    }
+
+    /// 返回pos处指向光源的单位向量和光照强度的缩放
+    pub fn incident(&self, pos: &Vector3f) -> (Vector3f, f32) {
+        match &self.light_type {
+            LightType::Directional { direction } => {
+                let mut l = Vector3f::new_3(-direction.x(), -direction.y(), -direction.z());
+                l.normlize();
+                (l, 1.0)
+            }
+            LightType::Point { position, attenuation, range } => {
+                let mut l = position.sub(pos);
+                let distance = l.length();
+                l.normlize();
+                (l, attenuation.factor(distance) * range_window(distance, *range))
+            }
+            LightType::Spot { position, direction, attenuation, range, inner_angle, outer_angle } => {
+                let mut l = position.sub(pos);
+                let distance = l.length();
+                l.normlize();
+
+                let cos_theta = -l.dot_product(direction);
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                let cone = if cos_inner - cos_outer > 1e-6 {
+                    smoothstep(cos_outer, cos_inner, cos_theta)
+                } else if cos_theta >= cos_outer {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                (l, attenuation.factor(distance) * range_window(distance, *range) * cone)
+            }
         }
-    } 
+    }
 }
 
-pub fn compute_light(pos: &Vector3f, normal: &Vector3f, lights: &Vec<Light>, 
-    ka: &Vector3f, kd: &Vector3f, ks: &Vector3f, 
-    eye_pos: &Vector3f, 
+/// 距离接近range时平滑衰减到0
+fn range_window(distance: f32, range: f32) -> f32 {
+    if !range.is_finite() {
+        return 1.0;
+    }
+
+    let x = (distance / range).powi(4);
+    let w = (1.0 - x).clamp(0.0, 1.0);
+    w * w
+}
+
+fn smoothstep(e0: f32, e1: f32, x: f32) -> f32 {
+    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// 着色点的Blinn-Phong参数，都已经乘过对应的纹理
+pub struct PhongSurface {
+    pub ambient: Vector3f,
+    pub diffuse: Vector3f,
+    pub specular: Vector3f,
+    pub shininess: f32,
+}
+
+/// Blinn-Phong光照
+pub fn compute_light(pos: &Vector3f, normal: &Vector3f, lights: &Vec<Light>,
+    amb_light_intensity: &Vector3f, surface: &PhongSurface,
+    eye_pos: &Vector3f,
 ) -> Vector3f {
 
-    let p = 150;
+    let ka = &surface.ambient;
+    let kd = &surface.diffuse;
+    let ks = &surface.specular;
+    //环境光和光源数量无关，只加一次
     let mut r = Vector3f::new();
-    let view_dir = eye_pos.sub(pos);
-    let amb_light_intensity = Vector3f::new_3(10.0, 10.0, 10.0);
+    for i in 0..3 {
+        r.v[i] = amb_light_intensity.v[i] * ka.v[i];
+    }
+    let mut view_dir = eye_pos.sub(pos);
+    view_dir.normlize();
 
     for l in lights {
-        let distance = l.position.sub(pos).length();
         let mut diffuse = Vector3f::new();
         let mut specular = Vector3f::new();
-        let mut ambient = Vector3f::new();
 
-        let mut light_dir = l.position.sub(pos);
-        light_dir.normlize();
-        // println!("{:?}", light_dir);
+        let (light_dir, scale) = l.incident(pos);
         let mut half = view_dir.add(&light_dir);
         half.normlize();
+        let n_dot_l = normal.dot_product(&light_dir);
+        let visibility = if scale > 0.0 && n_dot_l > 0.0 { l.shadow_visibility(pos, n_dot_l) } else { 1.0 };
         for i in 0..3 {
-            let intensity = l.intensity.v[i]/(distance * distance);
+            let intensity = l.intensity.v[i] * scale * visibility;
 
-            diffuse.v[i] = kd.v[i] * intensity * normal.dot_product(&light_dir).max(0.0);
-            specular.v[i] = ks.v[i] * intensity * normal.dot_product(&half).max(0.0).powi(p);
-            ambient.v[i] = amb_light_intensity.v[i] * ka.v[i];
+            diffuse.v[i] = kd.v[i] * intensity * n_dot_l.max(0.0);
+            specular.v[i] = ks.v[i] * intensity * normal.dot_product(&half).max(0.0).powf(surface.shininess);
 
             r.v[i] += diffuse.v[i];
             r.v[i] += specular.v[i];
-            r.v[i] += ambient.v[i];
         }
     }
 
     r
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ambient_is_added_once() {
+        let surface = PhongSurface {
+            ambient: Vector3f::new_3(0.5, 0.25, 1.0),
+            diffuse: Vector3f::new(),
+            specular: Vector3f::new(),
+            shininess: 1.0,
+        };
+        let amb = Vector3f::new_3(0.2, 0.2, 0.2);
+        let pos = Vector3f::new();
+        let normal = Vector3f::new_3(0.0, 0.0, 1.0);
+        let eye = Vector3f::new_3(0.0, 0.0, 5.0);
+        let light = Light::directional(Vector3f::new_3(0.0, 0.0, -1.0), Vector3f::new_3(1.0, 1.0, 1.0));
+        for count in 0..3 {
+            let r = compute_light(&pos, &normal, &vec![light.clone(); count], &amb, &surface, &eye);
+            assert_eq!(r.v, [0.1, 0.05, 0.2], "{} lights", count);
+        }
+    }
+}
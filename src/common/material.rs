@@ -0,0 +1,134 @@
+use crate::math::vector::Color3f;
+
+use super::sampler::Sampler;
+
+/// 材质上的纹理槽位
+/// 和glTF一样，Roughness读G通道，Metallic读B通道，Occlusion读R通道，
+/// 所以metallicRoughness贴图可以同时绑定到两个槽位上
+/// Ambient/Specular和Albedo一样乘对应的颜色，Shininess和Opacity是标量贴图，读R通道
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum TextureSlot {
+    Albedo,
+    Normal,
+    Roughness,
+    Emissive,
+    Metallic,
+    Occlusion,
+    Ambient,
+    Specular,
+    Shininess,
+    Opacity,
+}
+
+impl TextureSlot {
+    /// 按index排列的所有槽位
+    pub const ALL: [TextureSlot; 10] = [
+        TextureSlot::Albedo,
+        TextureSlot::Normal,
+        TextureSlot::Roughness,
+        TextureSlot::Emissive,
+        TextureSlot::Metallic,
+        TextureSlot::Occlusion,
+        TextureSlot::Ambient,
+        TextureSlot::Specular,
+        TextureSlot::Shininess,
+        TextureSlot::Opacity,
+    ];
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+pub const TEXTURE_SLOT_COUNT: usize = TextureSlot::ALL.len();
+
+/// 光照模型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadingModel {
+    /// 使用ambient/specular/shininess
+    BlinnPhong,
+    /// Cook-Torrance，使用metallic/roughness
+    Pbr,
+    /// 不计算光照，直接输出漫反射颜色(mtl的illum 0)
+    Unlit,
+}
+
+/// 绑定到槽位上的纹理，texture是Texture的id
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureBinding {
+    pub texture: i32,
+    pub sampler: Sampler,
+}
+
+/// 三角形通过Triangle.material引用Rasterizer里的材质
+/// 绑定了纹理的槽位，纹理颜色会乘上对应的diffuse/emissive
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Color3f,
+    pub diffuse: Color3f,
+    pub specular: Color3f,
+    pub emissive: Color3f,
+    pub shininess: f32,
+    pub opacity: f32,
+    pub shading: ShadingModel,
+    /// PBR参数，贴图的值会乘上它们
+    pub metallic: f32,
+    pub roughness: f32,
+    textures: [Option<TextureBinding>; TEXTURE_SLOT_COUNT],
+}
+
+impl Material {
+    /// 默认值是spot模型原来使用的参数
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            ambient: Color3f::new_3(0.005, 0.005, 0.005),
+            diffuse: Color3f::new_3(1.0, 1.0, 1.0),
+            specular: Color3f::new_3(0.7937, 0.7937, 0.7937),
+            emissive: Color3f::new_3(0.0, 0.0, 0.0),
+            shininess: 150.0,
+            opacity: 1.0,
+            shading: ShadingModel::BlinnPhong,
+            metallic: 0.0,
+            roughness: 0.5,
+            textures: [None; TEXTURE_SLOT_COUNT],
+        }
+    }
+
+    /// 金属度/粗糙度工作流的材质
+    pub fn pbr(name: &str, metallic: f32, roughness: f32) -> Material {
+        Material {
+            shading: ShadingModel::Pbr,
+            metallic,
+            roughness,
+            ..Material::new(name)
+        }
+    }
+
+    pub fn bind_texture(&mut self, slot: TextureSlot, texture: i32, sampler: Sampler) {
+        self.textures[slot.index()] = Some(TextureBinding { texture, sampler });
+    }
+
+    pub fn unbind_texture(&mut self, slot: TextureSlot) {
+        self.textures[slot.index()] = None;
+    }
+
+    pub fn get_texture(&self, slot: TextureSlot) -> Option<&TextureBinding> {
+        self.textures[slot.index()].as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_index_matches_all() {
+        for (i, slot) in TextureSlot::ALL.iter().enumerate() {
+            assert_eq!(slot.index(), i);
+        }
+        assert_eq!(TextureSlot::Opacity.index(), TEXTURE_SLOT_COUNT - 1);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use super::triangle::{RenderType, Triangle, Vertex};
+
+/// 位置、颜色、纹理坐标、法线、切线的位模式
+type VertexKey = [u32; 16];
+
+/// 索引网格：共享的顶点只存一份，每3个索引组成一个三角形
+#[derive(Clone)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub render: RenderType,
+    /// Rasterizer材质列表里的下标，整个网格共用
+    pub material: Option<usize>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Mesh {
+        assert_eq!(indices.len() % 3, 0);
+        Mesh {
+            vertices,
+            indices,
+            render: RenderType::COLOR,
+            material: None,
+        }
+    }
+
+    /// 合并所有属性完全相同的顶点，所有三角形的render和material必须相同
+    /// 混合了不同材质的三角形列表用group_triangles拆成多个网格
+    pub fn from_triangles(triangles: &[Triangle]) -> Mesh {
+        if let Some(first) = triangles.first() {
+            assert!(
+                triangles.iter().all(|t| t.render == first.render && t.material == first.material),
+                "triangles in a mesh must share render type and material, use Mesh::group_triangles"
+            );
+        }
+
+
+        let mut lookup: HashMap<VertexKey, u32> = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+        for t in triangles {
+            for v in t.vertexs.iter() {
+                let idx = *lookup.entry(vertex_key(v)).or_insert_with(|| {
+                    vertices.push(v.clone());
+                    (vertices.len() - 1) as u32
+                });
+                indices.push(idx);
+            }
+        }
+
+        let mut mesh = Mesh::new(vertices, indices);
+        if let Some(t) = triangles.first() {
+            mesh.render = t.render.clone();
+            mesh.material = t.material;
+        }
+        mesh
+    }
+
+    /// 按render和material分组，每组生成一个网格，顺序和每组第一次出现的顺序相同
+    pub fn group_triangles(triangles: &[Triangle]) -> Vec<Mesh> {
+        let mut lookup: HashMap<(RenderType, Option<usize>), usize> = HashMap::new();
+        let mut groups: Vec<Vec<Triangle>> = Vec::new();
+        for t in triangles {
+            let idx = *lookup.entry((t.render.clone(), t.material)).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[idx].push(t.clone());
+        }
+        groups.iter().map(|g| Mesh::from_triangles(g)).collect()
+    }
+
+    pub fn set_render_type(&mut self, t: RenderType) {
+        self.render = t;
+    }
+
+    pub fn set_material(&mut self, material: Option<usize>) {
+        self.material = material;
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// 第i个三角形的顶点下标
+    pub fn triangle_indices(&self, i: usize) -> [usize; 3] {
+        let f = &self.indices[i * 3..i * 3 + 3];
+        [f[0] as usize, f[1] as usize, f[2] as usize]
+    }
+
+    /// 图元装配：按索引从vertices中取出三个顶点
+    pub fn assemble(&self, vertices: &[Vertex], i: usize) -> Triangle {
+        let mut t = Triangle::new();
+        t.vertexs = self.triangle_indices(i).iter().map(|&k| vertices[k].clone()).collect();
+        t.set_render_type(self.render.clone());
+        t.set_material(self.material);
+        t
+    }
+
+    /// 展开成独立的三角形，阴影等只接受三角形列表的地方使用
+    pub fn triangles(&self) -> Vec<Triangle> {
+        (0..self.triangle_count()).map(|i| self.assemble(&self.vertices, i)).collect()
+    }
+}
+
+fn vertex_key(v: &Vertex) -> VertexKey {
+    let mut key = [0; 16];
+    let values = v.origin_v.v.iter()
+        .chain(v.color.v.iter())
+        .chain(v.tex_coords.v.iter())
+        .chain(v.normal.v.iter())
+        .chain(v.tangent.v.iter());
+    for (k, f) in key.iter_mut().zip(values) {
+        *k = f.to_bits();
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    fn triangle(offset: f32, material: Option<usize>) -> Triangle {
+        let mut t = Triangle::new();
+        t.set_origin_vertexs(vec![
+            Vector4f::new_4(0.0, 0.0, 0.0, 1.0),
+            Vector4f::new_4(1.0, 0.0, 0.0, 1.0),
+            Vector4f::new_4(offset, 1.0, 0.0, 1.0),
+        ]);
+        t.set_material(material);
+        t
+    }
+
+    #[test]
+    fn from_triangles_shares_vertices() {
+        let mesh = Mesh::from_triangles(&[triangle(0.0, Some(1)), triangle(1.0, Some(1))]);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 1, 3]);
+        assert_eq!(mesh.material, Some(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_triangles_rejects_mixed_materials() {
+        Mesh::from_triangles(&[triangle(0.0, Some(0)), triangle(1.0, Some(1))]);
+    }
+
+    #[test]
+    fn group_triangles_splits_per_material() {
+        let mut textured = triangle(0.5, Some(0));
+        textured.set_render_type(RenderType::TEXTURE);
+        let triangles = [triangle(0.0, Some(0)), triangle(1.0, Some(1)), textured, triangle(2.0, Some(0))];
+
+        let meshes = Mesh::group_triangles(&triangles);
+        let summary: Vec<_> = meshes.iter().map(|m| (m.material, m.render.clone(), m.triangle_count())).collect();
+        assert_eq!(summary, vec![
+            (Some(0), RenderType::COLOR, 2),
+            (Some(1), RenderType::COLOR, 1),
+            (Some(0), RenderType::TEXTURE, 1),
+        ]);
+    }
+
+    #[test]
+    fn from_triangles_compares_every_attribute() {
+        //位置相同，但切线方向或法线不同的顶点不能合并
+        let mut a = triangle(0.0, None);
+        let mut b = triangle(0.0, None);
+        b.vertexs[0].tangent.v[3] = -1.0;
+        b.vertexs[1].normal.v[2] = 1.0;
+        let mesh = Mesh::from_triangles(&[a.clone(), b]);
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 3, 4, 2]);
+
+        a.vertexs[2].color.v[0] = 0.5;
+        let mesh = Mesh::from_triangles(&[triangle(0.0, None), a]);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn group_order_is_first_seen() {
+        let materials = [None, Some(3), Some(1), None, Some(2), Some(1), Some(3)];
+        let triangles: Vec<Triangle> = materials.iter().map(|m| triangle(0.0, *m)).collect();
+        let meshes = Mesh::group_triangles(&triangles);
+        let summary: Vec<_> = meshes.iter().map(|m| (m.material, m.triangle_count())).collect();
+        assert_eq!(summary, vec![(None, 2), (Some(3), 2), (Some(1), 2), (Some(2), 1)]);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod triangle;
 pub mod texture;
-pub mod light;
\ No newline at end of file
+pub mod light;
+pub mod sampler;
+pub mod material;
+pub mod shadow;
+pub mod pbr;pub mod environment;
+pub mod skybox;
+pub mod color;
+pub mod tangent;
+pub mod mesh;
@@ -0,0 +1,151 @@
+use std::f32::consts::PI;
+
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::Vector3f;
+
+use super::environment::{view_to_world, Environment};
+use super::light::Light;
+
+/// 着色点的PBR参数，颜色都在线性空间
+pub struct PbrSurface {
+    pub base_color: Vector3f,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// 环境光遮蔽，只影响环境光
+    pub ao: f32,
+}
+
+impl PbrSurface {
+    /// 非金属的F0固定为0.04，金属用base_color
+    pub fn f0(&self) -> Vector3f {
+        let m = self.metallic;
+        let c = &self.base_color;
+        Vector3f::new_3(
+            0.04 * (1.0 - m) + c.r() * m,
+            0.04 * (1.0 - m) + c.g() * m,
+            0.04 * (1.0 - m) + c.b() * m,
+        )
+    }
+}
+
+/// GGX/Trowbridge-Reitz法线分布，alpha = roughness^2
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d).max(1e-7)
+}
+
+pub fn geometry_schlick_ggx(n_dot_v: f32, k: f32) -> f32 {
+    n_dot_v / (n_dot_v * (1.0 - k) + k)
+}
+
+/// Smith几何遮蔽，k按直接光照取(r+1)^2/8
+pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k)
+}
+
+pub fn fresnel_schlick(cos_theta: f32, f0: &Vector3f) -> Vector3f {
+    let t = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    Vector3f::new_3(
+        f0.r() + (1.0 - f0.r()) * t,
+        f0.g() + (1.0 - f0.g()) * t,
+        f0.b() + (1.0 - f0.b()) * t,
+    )
+}
+
+/// Cook-Torrance BRDF的直接光照，加上按ao衰减的环境光 ambient * base_color
+pub fn compute_pbr_light(pos: &Vector3f, normal: &Vector3f, lights: &Vec<Light>,
+    ambient: &Vector3f, surface: &PbrSurface, eye_pos: &Vector3f,
+) -> Vector3f {
+    let mut n = *normal;
+    n.normlize();
+    let mut v = eye_pos.sub(pos);
+    v.normlize();
+
+    //roughness太小时高光会变成一个点
+    let roughness = surface.roughness.clamp(0.04, 1.0);
+    let f0 = surface.f0();
+    let n_dot_v = n.dot_product(&v).max(1e-4);
+    let c = &surface.base_color;
+
+    let mut r = Vector3f::new_3(
+        ambient.r() * c.r() * surface.ao,
+        ambient.g() * c.g() * surface.ao,
+        ambient.b() * c.b() * surface.ao,
+    );
+
+    for light in lights {
+        let (l, scale) = light.incident(pos);
+        let n_dot_l = n.dot_product(&l);
+        if scale <= 0.0 || n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let visibility = light.shadow_visibility(pos, n_dot_l);
+        if visibility <= 0.0 {
+            continue;
+        }
+
+        let mut h = v.add(&l);
+        h.normlize();
+        let n_dot_h = n.dot_product(&h).max(0.0);
+        let h_dot_v = h.dot_product(&v).max(0.0);
+
+        let d = distribution_ggx(n_dot_h, roughness);
+        let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+        let f = fresnel_schlick(h_dot_v, &f0);
+        let denom = 4.0 * n_dot_v * n_dot_l;
+
+        for i in 0..3 {
+            let specular = d * g * f.v[i] / denom.max(1e-4);
+            //菲涅尔反射掉的能量不再参与漫反射，金属没有漫反射
+            let kd = (1.0 - f.v[i]) * (1.0 - surface.metallic);
+            let radiance = light.intensity.v[i] * scale * visibility;
+            r.v[i] += (kd * c.v[i] / PI + specular) * radiance * n_dot_l;
+        }
+    }
+
+    r
+}
+
+/// 考虑粗糙度的Fresnel，用于环境光
+pub fn fresnel_schlick_roughness(cos_theta: f32, f0: &Vector3f, roughness: f32) -> Vector3f {
+    let t = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    let f = |f0: f32| f0 + ((1.0 - roughness).max(f0) - f0) * t;
+    Vector3f::new_3(f(f0.r()), f(f0.g()), f(f0.b()))
+}
+
+/// 基于图像的环境光：漫反射用辐照度，镜面反射用预过滤贴图和BRDF查找表(split-sum)，都乘ao
+/// pos、normal、eye_pos和直接光照一样在相机空间，只有采样环境贴图时用view转换到世界空间
+pub fn compute_ibl(pos: &Vector3f, normal: &Vector3f, surface: &PbrSurface,
+    environment: &Environment, eye_pos: &Vector3f, view: &Mat4x4f,
+) -> Vector3f {
+    let mut n = *normal;
+    n.normlize();
+    let mut v = eye_pos.sub(pos);
+    v.normlize();
+
+    let n_dot_v = n.dot_product(&v).max(1e-4);
+    let roughness = surface.roughness.clamp(0.0, 1.0);
+    let f = fresnel_schlick_roughness(n_dot_v, &surface.f0(), roughness);
+    let (scale, bias) = environment.brdf(n_dot_v, roughness);
+
+    //r = 2(n·v)n - v
+    let d = 2.0 * n.dot_product(&v);
+    let r = Vector3f::new_3(d * n.x() - v.x(), d * n.y() - v.y(), d * n.z() - v.z());
+    let irradiance = environment.irradiance(&view_to_world(view, &n));
+    let prefiltered = environment.specular(&view_to_world(view, &r), roughness);
+
+    let c = &surface.base_color;
+    let mut result = Vector3f::new();
+    for i in 0..3 {
+        let kd = (1.0 - f.v[i]) * (1.0 - surface.metallic);
+        let diffuse = kd * irradiance.v[i] * c.v[i];
+        let specular = prefiltered.v[i] * (f.v[i] * scale + bias);
+        result.v[i] = (diffuse + specular) * surface.ao;
+    }
+    result
+}
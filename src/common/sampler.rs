@@ -0,0 +1,176 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+/// 纹理坐标超出0~1时的处理方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    /// 超出范围的像素使用border_color
+    ClampToBorder,
+}
+
+impl WrapMode {
+    /// 把像素下标映射到0~size-1，返回None表示取边框颜色
+    pub fn apply(&self, i: i32, size: i32) -> Option<i32> {
+        match self {
+            WrapMode::Repeat => Some(i.rem_euclid(size)),
+            WrapMode::MirroredRepeat => {
+                let i = i.rem_euclid(size * 2);
+                Some(if i < size { i } else { size * 2 - 1 - i })
+            }
+            WrapMode::ClampToEdge => Some(i.clamp(0, size - 1)),
+            WrapMode::ClampToBorder => {
+                if i < 0 || i >= size { None } else { Some(i) }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipmapMode {
+    /// 只使用第0级
+    None,
+    Nearest,
+    Linear,
+}
+
+/// 纹理采样方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sampler {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap: MipmapMode,
+    /// 1表示关闭各向异性过滤
+    pub max_anisotropy: u32,
+    pub lod_bias: f32,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    /// rgba(0~1)
+    pub border_color: [f32; 4],
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler {
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap: MipmapMode::None,
+            max_anisotropy: 1,
+            lod_bias: 0.0,
+            wrap_u: WrapMode::ClampToEdge,
+            wrap_v: WrapMode::ClampToEdge,
+            border_color: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    pub fn bilinear() -> Sampler {
+        Sampler {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            ..Sampler::new()
+        }
+    }
+
+    pub fn trilinear() -> Sampler {
+        Sampler {
+            mipmap: MipmapMode::Linear,
+            ..Sampler::bilinear()
+        }
+    }
+
+    pub fn anisotropic(max_anisotropy: u32) -> Sampler {
+        Sampler {
+            max_anisotropy: max_anisotropy.max(1),
+            ..Sampler::trilinear()
+        }
+    }
+
+    pub fn set_wrap(&mut self, wrap_u: WrapMode, wrap_v: WrapMode) {
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::common::texture::Texture;
+
+    #[test]
+    fn wrap_mode_table() {
+        //size=4时下标-5..=8的映射结果
+        let table: [(WrapMode, [Option<i32>; 14]); 4] = [
+            (WrapMode::Repeat, [3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0].map(Some)),
+            (WrapMode::MirroredRepeat, [3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0].map(Some)),
+            (WrapMode::ClampToEdge, [0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3].map(Some)),
+            (
+                WrapMode::ClampToBorder,
+                [None, None, None, None, None, Some(0), Some(1), Some(2), Some(3), None, None, None, None, None],
+            ),
+        ];
+        for (mode, expected) in table {
+            let r: Vec<Option<i32>> = (-5..=8).map(|i| mode.apply(i, 4)).collect();
+            assert_eq!(r, expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn wrap_mode_size_one() {
+        for i in [-3, -1, 0, 1, 2] {
+            assert_eq!(WrapMode::Repeat.apply(i, 1), Some(0));
+            assert_eq!(WrapMode::MirroredRepeat.apply(i, 1), Some(0));
+            assert_eq!(WrapMode::ClampToEdge.apply(i, 1), Some(0));
+        }
+        assert_eq!(WrapMode::ClampToBorder.apply(-1, 1), None);
+        assert_eq!(WrapMode::ClampToBorder.apply(1, 1), None);
+    }
+
+    #[test]
+    fn sample_outside_texture() {
+        //左黑右白的2x1纹理
+        let t = Texture::from_image(0, RgbaImage::from_fn(2, 1, |x, _| {
+            let c = if x == 0 { 0 } else { 255 };
+            Rgba([c, c, c, 255])
+        }));
+        let sample = |wrap: WrapMode, u: f32| {
+            let mut s = Sampler::new();
+            s.set_wrap(wrap, wrap);
+            s.border_color = [0.25, 0.5, 0.75, 1.0];
+            t.sample_lod(&s, u, 0.5, 0.0)
+        };
+
+        assert_eq!(sample(WrapMode::Repeat, -0.25).x(), 1.0);
+        assert_eq!(sample(WrapMode::Repeat, 1.25).x(), 0.0);
+        //镜像：刚越过边界时取边上的像素，再往外一个像素换成另一侧
+        assert_eq!(sample(WrapMode::MirroredRepeat, -0.25).x(), 0.0);
+        assert_eq!(sample(WrapMode::MirroredRepeat, -0.75).x(), 1.0);
+        assert_eq!(sample(WrapMode::MirroredRepeat, 1.25).x(), 1.0);
+        assert_eq!(sample(WrapMode::MirroredRepeat, 1.75).x(), 0.0);
+        assert_eq!(sample(WrapMode::ClampToEdge, -3.0).x(), 0.0);
+        assert_eq!(sample(WrapMode::ClampToEdge, 3.0).x(), 1.0);
+
+        let border = sample(WrapMode::ClampToBorder, -0.25);
+        assert_eq!(border.v, [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(sample(WrapMode::ClampToBorder, 1.25).v, border.v);
+        assert_eq!(sample(WrapMode::ClampToBorder, 0.75).x(), 1.0);
+
+        //线性过滤在边上和边框颜色混合
+        let mut s = Sampler::bilinear();
+        s.set_wrap(WrapMode::ClampToBorder, WrapMode::ClampToEdge);
+        s.border_color = [1.0, 1.0, 1.0, 1.0];
+        assert!((t.sample_lod(&s, 0.0, 0.5, 0.0).x() - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::{Vector3f, Vector4f};
+
+/// 光源投射阴影的参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// 阴影贴图的宽高
+    pub resolution: i32,
+    /// 深度比较时的常量偏移，单位和场景坐标一致
+    pub bias: f32,
+    /// 按表面和光线的夹角增加的偏移，乘以tan(夹角)
+    pub slope_bias: f32,
+    /// PCF的半径，采样(2r+1)^2个点
+    pub pcf_radius: i32,
+}
+
+impl ShadowSettings {
+    pub fn new() -> ShadowSettings {
+        ShadowSettings {
+            resolution: 1024,
+            bias: 0.01,
+            slope_bias: 0.01,
+            pcf_radius: 1,
+        }
+    }
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 从光源渲染的深度图，light_view/light_projection把光照空间的坐标变换到光源的裁剪空间
+/// 保存的是到光源的线性距离(光源视图空间的-z)，透视投影的NDC深度精度不够做比较
+#[derive(Clone)]
+pub struct ShadowMap {
+    width: i32,
+    height: i32,
+    depth: Vec<f32>,
+    light_view: Mat4x4f,
+    light_vp: Mat4x4f,
+    settings: ShadowSettings,
+}
+
+impl ShadowMap {
+    /// depth是深度缓冲里的NDC深度
+    pub fn new(width: i32, height: i32, depth: Vec<f32>, light_view: Mat4x4f, light_projection: &Mat4x4f, settings: ShadowSettings) -> ShadowMap {
+        let p = &light_projection.m;
+        //z_ndc = (p22 * z + p23) / (p32 * z + p33)，解出视图空间的z
+        let depth = depth.iter()
+            .map(|d| -(p[2][3] - d * p[3][3]) / (d * p[3][2] - p[2][2]))
+            .collect();
+        let light_vp = light_projection.mul(&light_view);
+        ShadowMap { width, height, depth, light_view, light_vp, settings }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn get_light_view(&self) -> &Mat4x4f {
+        &self.light_view
+    }
+
+    pub fn get_light_vp(&self) -> &Mat4x4f {
+        &self.light_vp
+    }
+
+    pub fn read_depth(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        self.depth[(y * self.width + x) as usize]
+    }
+
+    /// pos处的可见度(0~1)，1表示完全被照亮，n_dot_l用于计算坡度偏移
+    pub fn visibility(&self, pos: &Vector3f, n_dot_l: f32) -> f32 {
+        let pos = Vector4f::new_4(pos.x(), pos.y(), pos.z(), 1.0);
+        let distance = -self.light_view.apply(&pos).z();
+        let mut p = self.light_vp.apply(&pos);
+        if p.w() <= 1e-5 {
+            return 1.0;
+        }
+        p.divide_w();
+
+        //超出阴影贴图范围的点不在阴影里
+        if p.x() < -1.0 || p.x() > 1.0 || p.y() < -1.0 || p.y() > 1.0 || p.z() < -1.0 || p.z() > 0.0 {
+            return 1.0;
+        }
+
+        let x = ((p.x() + 1.0) * 0.5 * self.width as f32).floor() as i32;
+        let y = ((1.0 - p.y()) * 0.5 * self.height as f32).floor() as i32;
+
+        let n_dot_l = n_dot_l.clamp(0.05, 1.0);
+        let tan = (1.0 - n_dot_l * n_dot_l).sqrt() / n_dot_l;
+        let d = distance - self.settings.bias - self.settings.slope_bias * tan.min(10.0);
+
+        let r = self.settings.pcf_radius.max(0);
+        let mut lit = 0;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if d <= self.read_depth(x + dx, y + dy) {
+                    lit += 1;
+                }
+            }
+        }
+
+        lit as f32 / ((2 * r + 1) * (2 * r + 1)) as f32
+    }
+}
+
+/// 点光源的立方体阴影贴图，6个面按+X,-X,+Y,-Y,+Z,-Z排列
+/// 每个面保存到光源的距离，查询时按方向选择面
+#[derive(Clone)]
+pub struct CubeShadowMap {
+    size: i32,
+    position: Vector3f,
+    faces: Vec<Vec<f32>>,
+    face_vp: Vec<Mat4x4f>,
+    settings: ShadowSettings,
+}
+
+impl CubeShadowMap {
+    pub fn new(size: i32, position: Vector3f, settings: ShadowSettings) -> CubeShadowMap {
+        CubeShadowMap {
+            size,
+            position,
+            faces: vec![vec![f32::MAX; (size * size) as usize]; 6],
+            face_vp: vec![Mat4x4f::identity(); 6],
+            settings,
+        }
+    }
+
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    /// d的主轴对应的面
+    pub fn face_index(d: &Vector3f) -> usize {
+        let (ax, ay, az) = (d.x().abs(), d.y().abs(), d.z().abs());
+        if ax >= ay && ax >= az {
+            if d.x() >= 0.0 { 0 } else { 1 }
+        } else if ay >= az {
+            if d.y() >= 0.0 { 2 } else { 3 }
+        } else if d.z() >= 0.0 {
+            4
+        } else {
+            5
+        }
+    }
+
+    /// depth是用90度透视投影渲染的NDC深度，转换成到光源的距离后保存
+    pub fn set_face(&mut self, face: usize, depth: &[f32], view: &Mat4x4f, projection: &Mat4x4f) {
+        let p = &projection.m;
+        let size = self.size;
+        self.faces[face] = depth.iter().enumerate()
+            .map(|(i, d)| {
+                let z = -(p[2][3] - d * p[3][3]) / (d * p[3][2] - p[2][2]);
+                let sx = ((i as i32 % size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let sy = 1.0 - ((i as i32 / size) as f32 + 0.5) / size as f32 * 2.0;
+                z * (1.0 + sx * sx + sy * sy).sqrt()
+            })
+            .collect();
+        self.face_vp[face] = projection.mul(view);
+    }
+
+    pub fn read_distance(&self, face: usize, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.size - 1);
+        let y = y.clamp(0, self.size - 1);
+        self.faces[face][(y * self.size + x) as usize]
+    }
+
+    /// pos处的可见度(0~1)，比较pos到光源的距离和贴图里保存的最近距离
+    pub fn visibility(&self, pos: &Vector3f, n_dot_l: f32) -> f32 {
+        let d = pos.sub(&self.position);
+        let face = CubeShadowMap::face_index(&d);
+        let mut p = self.face_vp[face].apply(&Vector4f::new_4(pos.x(), pos.y(), pos.z(), 1.0));
+        if p.w() <= 1e-5 {
+            return 1.0;
+        }
+        p.divide_w();
+
+        let x = ((p.x() + 1.0) * 0.5 * self.size as f32).floor() as i32;
+        let y = ((1.0 - p.y()) * 0.5 * self.size as f32).floor() as i32;
+
+        let n_dot_l = n_dot_l.clamp(0.05, 1.0);
+        let tan = (1.0 - n_dot_l * n_dot_l).sqrt() / n_dot_l;
+        let distance = d.length() - self.settings.bias - self.settings.slope_bias * tan.min(10.0);
+
+        let r = self.settings.pcf_radius.max(0);
+        let mut lit = 0;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if distance <= self.read_distance(face, x + dx, y + dy) {
+                    lit += 1;
+                }
+            }
+        }
+
+        lit as f32 / ((2 * r + 1) * (2 * r + 1)) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_pipeline::rasterizer::get_ortho_projection_matrix;
+
+    /// 4x4的贴图覆盖x,y∈[-2,2]，左边两列在距离2处有遮挡物，右边两列是距离10的背景
+    fn half_occluded(settings: ShadowSettings) -> ShadowMap {
+        let projection = get_ortho_projection_matrix(-2.0, 2.0, 2.0, -2.0, -0.5, -10.5);
+        let ndc = |distance: f32| projection.apply(&Vector4f::new_4(0.0, 0.0, -distance, 1.0)).z();
+        let depth = (0..16).map(|i| if i % 4 < 2 { ndc(2.0) } else { ndc(10.0) }).collect();
+        ShadowMap::new(4, 4, depth, Mat4x4f::identity(), &projection, settings)
+    }
+
+    fn settings(bias: f32, slope_bias: f32, pcf_radius: i32) -> ShadowSettings {
+        ShadowSettings { resolution: 4, bias, slope_bias, pcf_radius }
+    }
+
+    #[test]
+    fn depth_is_stored_as_distance() {
+        let map = half_occluded(settings(0.0, 0.0, 0));
+        assert!((map.read_depth(0, 0) - 2.0).abs() < 1e-4);
+        assert!((map.read_depth(3, 3) - 10.0).abs() < 1e-4);
+        //越界时取边上的值
+        assert_eq!(map.read_depth(-1, 5), map.read_depth(0, 3));
+    }
+
+    #[test]
+    fn occluded_and_lit() {
+        let map = half_occluded(settings(0.1, 0.0, 0));
+        //遮挡物后面
+        assert_eq!(map.visibility(&Vector3f::new_3(-0.5, 0.5, -5.0), 1.0), 0.0);
+        //遮挡物前面
+        assert_eq!(map.visibility(&Vector3f::new_3(-0.5, 0.5, -1.5), 1.0), 1.0);
+        //没有遮挡的一侧
+        assert_eq!(map.visibility(&Vector3f::new_3(0.5, 0.5, -5.0), 1.0), 1.0);
+        //贴图范围以外不在阴影里
+        assert_eq!(map.visibility(&Vector3f::new_3(-3.0, 0.5, -5.0), 1.0), 1.0);
+        assert_eq!(map.visibility(&Vector3f::new_3(-0.5, 0.5, -20.0), 1.0), 1.0);
+    }
+
+    #[test]
+    fn bias() {
+        //比遮挡物远0.05的点，偏移大于0.05时被照亮
+        let p = Vector3f::new_3(-0.5, 0.5, -2.05);
+        assert_eq!(half_occluded(settings(0.01, 0.0, 0)).visibility(&p, 1.0), 0.0);
+        assert_eq!(half_occluded(settings(0.1, 0.0, 0)).visibility(&p, 1.0), 1.0);
+
+        //坡度偏移乘tan(夹角)，正对光源时是0
+        let map = half_occluded(settings(0.0, 0.1, 0));
+        assert_eq!(map.visibility(&p, 1.0), 0.0);
+        assert_eq!(map.visibility(&p, 0.5), 1.0);
+        //tan最多取10
+        let p = Vector3f::new_3(-0.5, 0.5, -3.5);
+        assert_eq!(map.visibility(&p, 0.0), 0.0);
+        assert_eq!(half_occluded(settings(0.0, 0.2, 0)).visibility(&p, 0.0), 1.0);
+    }
+
+    #[test]
+    fn pcf_averages_kernel() {
+        let map = half_occluded(settings(0.1, 0.0, 1));
+        //3x3的核里没有遮挡的列数分别是0、1、2、3
+        let v = |x: f32| map.visibility(&Vector3f::new_3(x, 0.5, -5.0), 1.0);
+        assert_eq!(v(-1.5), 0.0);
+        assert!((v(-0.5) - 3.0 / 9.0).abs() < 1e-6);
+        assert!((v(0.5) - 6.0 / 9.0).abs() < 1e-6);
+        assert_eq!(v(1.5), 1.0);
+
+        //半径2时是5x5的核
+        let map = half_occluded(settings(0.1, 0.0, 2));
+        let v = map.visibility(&Vector3f::new_3(-0.5, 0.5, -5.0), 1.0);
+        assert!((v - 10.0 / 25.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cube_face_index() {
+        let cases = [
+            ((1.0, 0.0, 0.0), 0),
+            ((-1.0, 0.0, 0.0), 1),
+            ((0.0, 1.0, 0.0), 2),
+            ((0.0, -1.0, 0.0), 3),
+            ((0.0, 0.0, 1.0), 4),
+            ((0.0, 0.0, -1.0), 5),
+            //按绝对值最大的分量选择
+            ((-0.2, 0.9, -0.5), 2),
+            ((0.3, -0.4, -0.6), 5),
+            ((-5.0, 4.0, 4.9), 1),
+            //相等时按x、y、z的顺序
+            ((1.0, -1.0, 1.0), 0),
+            ((0.0, -2.0, 2.0), 3),
+            ((0.0, 0.0, 0.0), 0),
+        ];
+        for ((x, y, z), face) in cases {
+            assert_eq!(CubeShadowMap::face_index(&Vector3f::new_3(x, y, z)), face, "({}, {}, {})", x, y, z);
+        }
+    }
+
+    #[test]
+    fn new_cube_map_is_unoccluded() {
+        let map = CubeShadowMap::new(8, Vector3f::new_3(1.0, 2.0, 3.0), ShadowSettings::new());
+        assert_eq!(map.size(), 8);
+        for face in 0..6 {
+            assert_eq!(map.read_distance(face, 0, 0), f32::MAX);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::math::vector::Vector3f;
+
+use super::environment::EnvironmentMap;
+
+/// 立方体贴图，面的顺序是+X,-X,+Y,-Y,+Z,-Z，每个面的图片原点在左上角(和OpenGL的约定一致)
+#[derive(Clone)]
+pub struct CubeMap {
+    size: u32,
+    faces: Vec<Vec<[f32; 3]>>,
+}
+
+impl CubeMap {
+    pub fn new(size: u32, faces: Vec<Vec<[f32; 3]>>) -> CubeMap {
+        assert_eq!(faces.len(), 6);
+        assert!(faces.iter().all(|f| f.len() == (size * size) as usize));
+        CubeMap { size, faces }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// 返回面的下标和面上的坐标(0~1)
+    pub fn face_uv(dir: &[f32; 3]) -> (usize, f32, f32) {
+        let (x, y, z) = (dir[0], dir[1], dir[2]);
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+            if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+        } else if ay >= az {
+            if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+        } else if z > 0.0 {
+            (4, x, -y, az)
+        } else {
+            (5, -x, -y, az)
+        };
+        (face, (sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0)
+    }
+
+    /// 在对应的面上双线性采样，边缘截断
+    pub fn sample(&self, dir: &[f32; 3]) -> [f32; 3] {
+        let (face, u, v) = CubeMap::face_uv(dir);
+        let max = (self.size - 1) as f32;
+        let x = (u * self.size as f32 - 0.5).clamp(0.0, max);
+        let y = (v * self.size as f32 - 0.5).clamp(0.0, max);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let data = &self.faces[face];
+        let at = |x: u32, y: u32| data[(y * self.size + x) as usize];
+
+        let mut r = [0.0; 3];
+        for (k, c) in r.iter_mut().enumerate() {
+            let top = at(x0, y0)[k] + (at(x1, y0)[k] - at(x0, y0)[k]) * fx;
+            let bottom = at(x0, y1)[k] + (at(x1, y1)[k] - at(x0, y1)[k]) * fx;
+            *c = top + (bottom - top) * fy;
+        }
+        r
+    }
+}
+
+/// 画在所有物体后面的背景，方向是世界空间的
+pub enum Skybox {
+    CubeMap(CubeMap),
+    Equirect(EnvironmentMap),
+}
+
+impl Skybox {
+    pub fn sample(&self, dir: &Vector3f) -> Vector3f {
+        let l = dir.length();
+        if l <= 0.0 {
+            return Vector3f::new();
+        }
+
+        let d = [dir.x() / l, dir.y() / l, dir.z() / l];
+        let c = match self {
+            Skybox::CubeMap(map) => map.sample(&d),
+            Skybox::Equirect(map) => map.sample(&d),
+        };
+        Vector3f::new_3(c[0], c[1], c[2])
+    }
+}
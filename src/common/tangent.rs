@@ -0,0 +1,167 @@
+use bevy_mikktspace::Geometry;
+
+use crate::math::vector::Vector4f;
+
+use super::triangle::Triangle;
+
+/// 把三角形列表交给MikkTSpace，每个三角形是一个面
+struct MikkTriangles<'a> {
+    triangles: &'a mut [Triangle],
+}
+
+impl Geometry for MikkTriangles<'_> {
+    fn num_faces(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let p = &self.triangles[face].vertexs[vert].origin_v;
+        [p.x(), p.y(), p.z()]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let n = &self.triangles[face].vertexs[vert].normal;
+        [n.x(), n.y(), n.z()]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = &self.triangles[face].vertexs[vert].tex_coords;
+        [uv.u(), uv.v()]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.triangles[face].vertexs[vert].tangent = Vector4f::new_4(tangent[0], tangent[1], tangent[2], tangent[3]);
+    }
+}
+
+/// 用MikkTSpace生成切线，和Blender、Substance等烘焙法线贴图的工具结果一致
+/// 位置、法线、纹理坐标都相同的顶点看作同一个顶点，uv镜像的两边分开计算
+/// tangent.w是副切线的方向，bitangent = w * cross(normal, tangent)
+pub fn generate_tangents(triangles: &mut [Triangle]) {
+    if triangles.is_empty() {
+        return;
+    }
+    bevy_mikktspace::generate_tangents(&mut MikkTriangles { triangles });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::{Vector2f, Vector3f};
+
+    /// z=0平面上的三角形，法线朝+z，uv由位置计算
+    fn triangle(p: [[f32; 2]; 3], uv: impl Fn(f32, f32) -> (f32, f32)) -> Triangle {
+        let mut t = Triangle::new();
+        t.set_origin_vertexs(p.iter().map(|p| Vector4f::new_4(p[0], p[1], 0.0, 1.0)).collect());
+        t.set_normal(vec![Vector3f::new_3(0.0, 0.0, 1.0); 3]);
+        t.set_tex_coords(p.iter().map(|p| {
+            let (u, v) = uv(p[0], p[1]);
+            Vector2f::new_2(u, v)
+        }).collect());
+        t
+    }
+
+    /// [x0, x0+1]x[0, 1]的正方形
+    fn quad(x0: f32, uv: impl Fn(f32, f32) -> (f32, f32) + Copy) -> Vec<Triangle> {
+        vec![
+            triangle([[x0, 0.0], [x0 + 1.0, 0.0], [x0 + 1.0, 1.0]], uv),
+            triangle([[x0, 0.0], [x0 + 1.0, 1.0], [x0, 1.0]], uv),
+        ]
+    }
+
+    fn assert_tangent(t: &Vector4f, expected: [f32; 4]) {
+        for k in 0..4 {
+            assert!((t.v[k] - expected[k]).abs() < 1e-5, "{:?} != {:?}", t.v, expected);
+        }
+    }
+
+    fn assert_all(triangles: &[Triangle], expected: [f32; 4]) {
+        for t in triangles {
+            for v in t.vertexs.iter() {
+                assert_tangent(&v.tangent, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn quad_tangents() {
+        let mut q = quad(0.0, |x, y| (x, y));
+        generate_tangents(&mut q);
+        assert_all(&q, [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn mirrored_uv_quad() {
+        //u镜像：切线反向，副切线仍然是+y，所以w=-1
+        let mut q = quad(0.0, |x, y| (1.0 - x, y));
+        generate_tangents(&mut q);
+        assert_all(&q, [-1.0, 0.0, 0.0, -1.0]);
+
+        //v镜像：切线不变，副切线是-y
+        let mut q = quad(0.0, |x, y| (x, 1.0 - y));
+        generate_tangents(&mut q);
+        assert_all(&q, [1.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn mirrored_seam_is_split() {
+        //x=1处的顶点位置、法线、uv都相同，两边的uv互为镜像
+        let mut triangles = quad(0.0, |x, y| (x, y));
+        triangles.extend(quad(1.0, |x, y| (2.0 - x, y)));
+        generate_tangents(&mut triangles);
+
+        //接缝上的顶点不会被两边平均掉
+        assert_all(&triangles[..2], [1.0, 0.0, 0.0, 1.0]);
+        assert_all(&triangles[2..], [-1.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn shared_vertex_seam() {
+        //沿x=0折起来的两个面，接缝上的法线是两边的平均，uv连续
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let p = |x: f32, y: f32| {
+            if x <= 0.0 { Vector4f::new_4(x, y, 0.0, 1.0) } else { Vector4f::new_4(0.0, y, -x, 1.0) }
+        };
+        let n = |x: f32| {
+            if x < 0.0 {
+                Vector3f::new_3(0.0, 0.0, 1.0)
+            } else if x > 0.0 {
+                Vector3f::new_3(1.0, 0.0, 0.0)
+            } else {
+                Vector3f::new_3(s, 0.0, s)
+            }
+        };
+        let face = |points: [[f32; 2]; 3]| {
+            let mut t = Triangle::new();
+            t.set_origin_vertexs(points.iter().map(|q| p(q[0], q[1])).collect());
+            t.set_normal(points.iter().map(|q| n(q[0])).collect());
+            t.set_tex_coords(points.iter().map(|q| Vector2f::new_2(q[0] + 1.0, q[1])).collect());
+            t
+        };
+        let mut triangles = vec![
+            face([[-1.0, 0.0], [0.0, 0.0], [0.0, 1.0]]),
+            face([[-1.0, 0.0], [0.0, 1.0], [-1.0, 1.0]]),
+            face([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]),
+            face([[0.0, 0.0], [1.0, 1.0], [0.0, 1.0]]),
+        ];
+        generate_tangents(&mut triangles);
+
+        //远离接缝的顶点沿各自的面
+        assert_tangent(&triangles[0].vertexs[0].tangent, [1.0, 0.0, 0.0, 1.0]);
+        assert_tangent(&triangles[2].vertexs[1].tangent, [0.0, 0.0, -1.0, 1.0]);
+        //接缝上的顶点被两边共享，切线相同并且和平均法线正交
+        let seam = [&triangles[0].vertexs[1], &triangles[0].vertexs[2], &triangles[2].vertexs[0], &triangles[3].vertexs[2]];
+        for v in seam {
+            assert_tangent(&v.tangent, [s, 0.0, -s, 1.0]);
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        generate_tangents(&mut []);
+    }
+}
@@ -1,30 +1,346 @@
-use image::{RgbaImage, Pixel};
+use image::{Pixel, Rgba, Rgba32FImage, RgbaImage};
 
-use crate::math::utils::clamp;
+use crate::math::utils::{clamp, interpolation};
+use crate::math::vector::{Vector2f, Vector4f};
+
+use super::color::srgb_to_linear;
+use super::sampler::{Filter, MipmapMode, Sampler};
+
+/// 纹理数据的颜色空间，颜色贴图一般是Srgb，法线、粗糙度等数据贴图是Linear
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
 
 pub struct Texture {
     id: i32,
     image: RgbaImage,
     width: u32,
-    height: u32
+    height: u32,
+    color_space: ColorSpace,
+    /// 线性空间的mipmap，第0级就是原图，采样结果直接是线性值
+    levels: Vec<Rgba32FImage>
 }
 
 impl Texture {
     pub fn new(id: i32, path: &str)-> Texture {
         let r = image::open(path).unwrap().to_rgba8();
+        Texture::from_image(id, r)
+    }
+
+    /// 像素按线性值处理
+    pub fn from_image(id: i32, image: RgbaImage) -> Texture {
+        Texture::with_color_space(id, image, ColorSpace::Linear)
+    }
+
+    /// 加载时把rgb从sRGB解码到线性空间，alpha不变
+    pub fn from_srgb_image(id: i32, image: RgbaImage) -> Texture {
+        Texture::with_color_space(id, image, ColorSpace::Srgb)
+    }
+
+    pub fn with_color_space(id: i32, image: RgbaImage, color_space: ColorSpace) -> Texture {
+        let levels = build_mipmaps(&decode(&image, color_space));
         Texture { 
-            id: id,
-            width: r.width(), 
-            height: r.height(),
-            image: r, 
+            id,
+            width: image.width(), 
+            height: image.height(),
+            image, 
+            color_space,
+            levels
         }
     }
 
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// 原始的像素字节，没有做颜色空间转换
     pub fn get_color(&self, u: f32, v: f32) -> (u8, u8, u8) {
+        let (r, g, b, _) = self.get_rgba(u, v);
+        (r, g, b)
+    }
+
+    pub fn get_rgba(&self, u: f32, v: f32) -> (u8, u8, u8, u8) {
         let x = clamp((u * self.width as f32).round() as u32, 0, self.width-1);
         let y = clamp((v * self.height as f32).round() as u32, 0, self.height-1);
 
         let r = self.image.get_pixel(x, y).to_rgba();
-        (r.0[0], r.0[1], r.0[2])
+        (r.0[0], r.0[1], r.0[2], r.0[3])
+    }
+
+    /// 按sampler采样，ddx/ddy是纹理坐标在屏幕x/y方向上的导数，用于选择mipmap等级
+    pub fn sample(&self, sampler: &Sampler, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Vector4f {
+        let (w, h) = (self.width as f32, self.height as f32);
+        let dx = Vector2f::new_2(ddx.u() * w, ddx.v() * h);
+        let dy = Vector2f::new_2(ddy.u() * w, ddy.v() * h);
+        let (lx, ly) = (dx.length(), dy.length());
+        let (major, rho_max, rho_min) = if lx >= ly { (dx, lx, ly) } else { (dy, ly, lx) };
+
+        //各向异性：沿长轴方向多次采样，lod按短轴计算
+        let mut samples = 1;
+        let mut rho = rho_max;
+        if sampler.max_anisotropy > 1 && rho_min > 0.0 {
+            samples = ((rho_max / rho_min).ceil() as u32).clamp(1, sampler.max_anisotropy);
+            rho = rho_max / samples as f32;
+        }
+
+        let lod = if rho > 0.0 { rho.log2() + sampler.lod_bias } else { sampler.lod_bias };
+        if samples == 1 {
+            return self.sample_lod(sampler, uv.u(), uv.v(), lod);
+        }
+
+        let mut r = Vector4f::new();
+        for i in 0..samples {
+            let t = (i as f32 + 0.5) / samples as f32 - 0.5;
+            let u = uv.u() + major.u() / w * t;
+            let v = uv.v() + major.v() / h * t;
+            let c = self.sample_lod(sampler, u, v, lod);
+            for k in 0..4 {
+                r.v[k] += c.v[k] / samples as f32;
+            }
+        }
+        r
+    }
+
+    /// 在指定的lod上采样，lod<=0时是放大
+    pub fn sample_lod(&self, sampler: &Sampler, u: f32, v: f32, lod: f32) -> Vector4f {
+        if lod <= 0.0 || sampler.mipmap == MipmapMode::None {
+            let filter = if lod <= 0.0 { sampler.mag_filter } else { sampler.min_filter };
+            return self.sample_level(sampler, filter, 0, u, v);
+        }
+
+        let max_level = (self.levels.len() - 1) as f32;
+        let lod = lod.min(max_level);
+        match sampler.mipmap {
+            MipmapMode::Nearest => self.sample_level(sampler, sampler.min_filter, lod.round() as usize, u, v),
+            _ => {
+                let l0 = lod.floor() as usize;
+                let l1 = (l0 + 1).min(self.levels.len() - 1);
+                let t = lod - l0 as f32;
+                let c0 = self.sample_level(sampler, sampler.min_filter, l0, u, v);
+                let c1 = self.sample_level(sampler, sampler.min_filter, l1, u, v);
+                lerp(&c0, &c1, t)
+            }
+        }
+    }
+
+    fn sample_level(&self, sampler: &Sampler, filter: Filter, level: usize, u: f32, v: f32) -> Vector4f {
+        let img = &self.levels[level];
+        let x = u * img.width() as f32;
+        let y = v * img.height() as f32;
+        match filter {
+            Filter::Nearest => self.texel(sampler, level, x.floor() as i32, y.floor() as i32),
+            Filter::Linear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+                let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+                let top = lerp(&self.texel(sampler, level, x0, y0), &self.texel(sampler, level, x0 + 1, y0), fx);
+                let bottom = lerp(&self.texel(sampler, level, x0, y0 + 1), &self.texel(sampler, level, x0 + 1, y0 + 1), fx);
+                lerp(&top, &bottom, fy)
+            }
+        }
     }
-}
\ No newline at end of file
+
+    /// 取某一级mipmap上的像素，超出范围时按sampler的wrap处理
+    fn texel(&self, sampler: &Sampler, level: usize, x: i32, y: i32) -> Vector4f {
+        let img = &self.levels[level];
+        let x = sampler.wrap_u.apply(x, img.width() as i32);
+        let y = sampler.wrap_v.apply(y, img.height() as i32);
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) => (x as u32, y as u32),
+            _ => {
+                let c = sampler.border_color;
+                return Vector4f::new_4(c[0], c[1], c[2], c[3]);
+            }
+        };
+        let p = img.get_pixel(x, y).0;
+        Vector4f::new_4(p[0], p[1], p[2], p[3])
+    }
+}
+
+fn lerp(a: &Vector4f, b: &Vector4f, t: f32) -> Vector4f {
+    Vector4f::new_4(
+        interpolation(a.x(), b.x(), t),
+        interpolation(a.y(), b.y(), t),
+        interpolation(a.z(), b.z(), t),
+        interpolation(a.w(), b.w(), t),
+    )
+}
+
+/// 转换成0~1的浮点数，Srgb时rgb解码到线性空间
+fn decode(image: &RgbaImage, color_space: ColorSpace) -> Rgba32FImage {
+    let mut lut = [0.0; 256];
+    for (i, v) in lut.iter_mut().enumerate() {
+        let c = i as f32 / 255.0;
+        *v = match color_space {
+            ColorSpace::Linear => c,
+            ColorSpace::Srgb => srgb_to_linear(c),
+        };
+    }
+
+    Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y).0;
+        Rgba([lut[p[0] as usize], lut[p[1] as usize], lut[p[2] as usize], p[3] as f32 / 255.0])
+    })
+}
+
+/// 在线性空间做2x2 box filter，逐级缩小直到1x1
+fn build_mipmaps(image: &Rgba32FImage) -> Vec<Rgba32FImage> {
+    let mut levels = vec![image.clone()];
+    while levels.last().unwrap().width() > 1 || levels.last().unwrap().height() > 1 {
+        let prev = levels.last().unwrap();
+        let w = (prev.width() / 2).max(1);
+        let h = (prev.height() / 2).max(1);
+        let next = Rgba32FImage::from_fn(w, h, |x, y| {
+            let mut sum = [0.0; 4];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = (x * 2 + dx).min(prev.width() - 1);
+                let sy = (y * 2 + dy).min(prev.height() - 1);
+                let p = prev.get_pixel(sx, sy).0;
+                for k in 0..4 {
+                    sum[k] += p[k] / 4.0;
+                }
+            }
+            Rgba(sum)
+        });
+        levels.push(next);
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按函数生成灰度纹理，值是0~255
+    fn gray_texture(w: u32, h: u32, f: impl Fn(u32, u32) -> u8) -> Texture {
+        Texture::from_image(0, RgbaImage::from_fn(w, h, |x, y| {
+            let c = f(x, y);
+            Rgba([c, c, c, 255])
+        }))
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn mip_chain_sizes() {
+        let sizes = |w, h| -> Vec<(u32, u32)> {
+            gray_texture(w, h, |_, _| 0).levels.iter().map(|l| l.dimensions()).collect()
+        };
+        assert_eq!(sizes(8, 4), vec![(8, 4), (4, 2), (2, 1), (1, 1)]);
+        assert_eq!(sizes(1, 4), vec![(1, 4), (1, 2), (1, 1)]);
+        assert_eq!(sizes(3, 3), vec![(3, 3), (1, 1)]);
+        assert_eq!(sizes(1, 1), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn mip_box_filter_values() {
+        let values = [[0, 51], [102, 255]];
+        let t = gray_texture(2, 2, |x, y| values[y as usize][x as usize]);
+        assert_eq!(t.level_count(), 2);
+        let p = t.levels[1].get_pixel(0, 0).0;
+        assert!(close(p[0], (0.0 + 0.2 + 0.4 + 1.0) / 4.0));
+        assert!(close(p[3], 1.0));
+
+        //奇数尺寸时越界的像素取边上的值
+        let t = gray_texture(3, 1, |x, _| [0, 255, 255][x as usize]);
+        assert!(close(t.levels[1].get_pixel(0, 0).0[0], 0.5));
+    }
+
+    #[test]
+    fn bilinear_filter() {
+        let t = gray_texture(2, 1, |x, _| if x == 0 { 0 } else { 255 });
+        let s = Sampler::bilinear();
+        //像素中心取原值，两个中心之间线性插值
+        assert!(close(t.sample_lod(&s, 0.25, 0.5, 0.0).x(), 0.0));
+        assert!(close(t.sample_lod(&s, 0.75, 0.5, 0.0).x(), 1.0));
+        assert!(close(t.sample_lod(&s, 0.5, 0.5, 0.0).x(), 0.5));
+        assert!(close(t.sample_lod(&s, 0.375, 0.5, 0.0).x(), 0.25));
+        //nearest取所在像素
+        assert!(close(t.sample_lod(&Sampler::new(), 0.45, 0.5, 0.0).x(), 0.0));
+    }
+
+    /// 每一级的值都不同：第0级是1，往下box filter后逐级变化
+    fn level_texture() -> Texture {
+        gray_texture(4, 4, |x, y| if (x + y) % 2 == 0 { 255 } else { 0 })
+    }
+
+    #[test]
+    fn trilinear_lod_selection() {
+        let t = level_texture();
+        let s = Sampler::trilinear();
+        let (u, v) = (0.125, 0.125);
+        let level0 = t.sample_level(&s, Filter::Linear, 0, u, v).x();
+        let level1 = t.sample_level(&s, Filter::Linear, 1, u, v).x();
+        assert!(close(level0, 1.0));
+        assert!(close(level1, 0.5));
+
+        //lod在两级之间线性插值，超过最后一级时截断
+        assert!(close(t.sample_lod(&s, u, v, 0.25).x(), 0.875));
+        assert!(close(t.sample_lod(&s, u, v, 10.0).x(), 0.5));
+        let mut nearest = s;
+        nearest.mipmap = MipmapMode::Nearest;
+        assert!(close(t.sample_lod(&nearest, u, v, 0.4).x(), level0));
+        assert!(close(t.sample_lod(&nearest, u, v, 0.6).x(), level1));
+
+        //导数决定lod：每个屏幕像素走1个纹素是第0级，走2个纹素是第1级
+        let uv = Vector2f::new_2(u, v);
+        let d = |texels: f32| (Vector2f::new_2(texels / 4.0, 0.0), Vector2f::new_2(0.0, texels / 4.0));
+        let (dx, dy) = d(1.0);
+        assert!(close(t.sample(&s, &uv, &dx, &dy).x(), level0));
+        let (dx, dy) = d(2.0);
+        assert!(close(t.sample(&s, &uv, &dx, &dy).x(), level1));
+        let (dx, dy) = d(2.0_f32.sqrt());
+        assert!(close(t.sample(&s, &uv, &dx, &dy).x(), 0.75));
+
+        //lod_bias加在计算出的lod上
+        let mut biased = s;
+        biased.lod_bias = 1.0;
+        let (dx, dy) = d(1.0);
+        assert!(close(t.sample(&biased, &uv, &dx, &dy).x(), level1));
+    }
+
+    #[test]
+    fn anisotropic_sampling() {
+        //横条纹：奇数行白色，偶数行黑色
+        let t = gray_texture(8, 8, |_, y| if y % 2 == 1 { 255 } else { 0 });
+        let uv = Vector2f::new_2(0.5, 1.5 / 8.0);
+        //沿u方向拉长4倍的足迹
+        let dx = Vector2f::new_2(4.0 / 8.0, 0.0);
+        let dy = Vector2f::new_2(0.0, 1.0 / 8.0);
+
+        //只看长轴时lod=2，条纹被平均掉
+        assert!(close(t.sample(&Sampler::trilinear(), &uv, &dx, &dy).x(), 0.5));
+        //各向异性沿u方向采样4次，lod按短轴算，保留v方向的细节
+        assert!(close(t.sample(&Sampler::anisotropic(16), &uv, &dx, &dy).x(), 1.0));
+        assert!(close(t.sample(&Sampler::anisotropic(4), &uv, &dx, &dy).x(), 1.0));
+        //采样次数被max_anisotropy限制，lod=1时条纹同样被平均
+        assert!(close(t.sample(&Sampler::anisotropic(2), &uv, &dx, &dy).x(), 0.5));
+
+        //沿长轴的多次采样取平均
+        let t = gray_texture(8, 8, |x, _| (x * 32) as u8);
+        let uv = Vector2f::new_2(0.5, 0.5);
+        let r = t.sample(&Sampler::anisotropic(4), &uv, &dx, &dy).x();
+        assert!(close(r, (2.0 + 3.0 + 4.0 + 5.0) / 4.0 * 32.0 / 255.0));
+    }
+}
@@ -1,9 +1,7 @@
 use crate::math::{utils::interpolation};
 use crate::math::vector::{Vector4f, Color3f, Point2f, Point3f, vector4f_interpolation};
 
-use super::texture::Texture;
-
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RenderType {
     COLOR,
     TEXTURE
@@ -11,7 +9,9 @@ pub enum RenderType {
 #[derive(Clone)]
 pub struct Triangle {
     pub vertexs: Vec<Vertex>,
-    pub render: RenderType
+    pub render: RenderType,
+    /// Rasterizer材质列表里的下标
+    pub material: Option<usize>
 }
 
 #[derive(Clone)]
@@ -22,7 +22,10 @@ pub struct Vertex {
     pub color: Color3f,
     pub tex_coords: Point2f,
     pub normal: Point3f,
-    pub rhw: f32
+    /// xyz是切线，w是副切线的方向(±1)，见tangent::generate_tangents
+    pub tangent: Vector4f,
+    pub rhw: f32,
+    pub varyings: Vec<f32>
 }
 
 pub fn vertex_interp(v1: &Vertex, v2: &Vertex, t: f32) -> Vertex {
@@ -44,64 +47,79 @@ pub fn vertex_interp(v1: &Vertex, v2: &Vertex, t: f32) -> Vertex {
             interpolation(v1.normal.y(), v2.normal.y(), t),
             interpolation(v1.normal.z(), v2.normal.z(), t),
         ),
-        rhw: interpolation(v1.rhw, v2.rhw, t)
+        tangent: vector4f_interpolation(&v1.tangent, &v2.tangent, t),
+        rhw: interpolation(v1.rhw, v2.rhw, t),
+        varyings: v1.varyings.iter().zip(v2.varyings.iter())
+            .map(|(a, b)| interpolation(*a, *b, t))
+            .collect()
     }
 }
 
 impl Default for Vertex {
     fn default() -> Self {
-        Self { origin_v: Vector4f::new(), tv: Vector4f::new(), v: Vector4f::new(), color: Color3f::new(), tex_coords: Point2f::new(), normal: Point3f::new(), rhw: 1.0 }
+        Self { origin_v: Vector4f::new(), tv: Vector4f::new(), v: Vector4f::new(), color: Color3f::new(), tex_coords: Point2f::new(), normal: Point3f::new(), tangent: Vector4f::new(), rhw: 1.0, varyings: Vec::new() }
     }
 }
 
 
-impl<'a> Triangle {
+impl Triangle {
     pub fn new() -> Triangle {
         Triangle {
             vertexs: vec![Vertex::default(), Vertex::default(), Vertex::default()],
             render: RenderType::COLOR,
+            material: None,
         }
     }
 
+    pub fn set_material(&mut self, material: Option<usize>) {
+        self.material = material;
+    }
+
     pub fn set_render_type(&mut self, t: RenderType) {
         self.render = t;
     }
 
     pub fn set_origin_vertexs(&mut self, v: Vec<Vector4f>) {
-        for i in 0..v.len() {
-            self.vertexs[i].origin_v = v[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(v) {
+            vertex.origin_v = x;
         }
     }
 
     pub fn set_tvetexs(&mut self, v: Vec<Vector4f>) {
-        for i in 0..v.len() {
-            self.vertexs[i].tv = v[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(v) {
+            vertex.tv = x;
         }
     }
 
     pub fn set_vertexs(&mut self, mut v: Vec<Vector4f>) {
-        for i in 0..v.len() {
-            self.vertexs[i].rhw =  1.0 / v[i].w();
-            v[i].divide_w();
-            self.vertexs[i].v = v[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(v.iter_mut()) {
+            vertex.rhw = 1.0 / x.w();
+            x.divide_w();
+            vertex.v = *x;
         }
     }
 
     pub fn set_colors(&mut self, c: Vec<Color3f>) {
-        for i in 0..c.len() {
-            self.vertexs[i].color = c[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(c) {
+            vertex.color = x;
         }
     }
 
     pub fn set_tex_coords(&mut self, v: Vec<Point2f>) {
-        for i in 0..v.len() {
-            self.vertexs[i].tex_coords = v[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(v) {
+            vertex.tex_coords = x;
         }
     }
 
     pub fn set_normal(&mut self, c: Vec<Point3f>) {
-        for i in 0..c.len() {
-            self.vertexs[i].normal = c[i].clone();
+        for (vertex, x) in self.vertexs.iter_mut().zip(c) {
+            vertex.normal = x;
+        }
+    }
+
+    pub fn set_tangents(&mut self, t: Vec<Vector4f>) {
+        for (v, t) in self.vertexs.iter_mut().zip(t) {
+            v.tangent = t;
         }
     }
 }
@@ -0,0 +1,71 @@
+// viewer和无窗口渲染共用的示例场景，两个程序各自作为子模块引入，不属于库的公开接口
+
+use mini3d_rust::common::light::Light;
+use mini3d_rust::common::material::Material;
+use mini3d_rust::common::mesh::Mesh;
+use mini3d_rust::common::texture::Texture;
+use mini3d_rust::fixed_pipeline::culling::{CullMode, FrontFace};
+use mini3d_rust::fixed_pipeline::rasterizer::{get_model_matrix, get_presp_projection_matrix, get_view_matrix, Rasterizer};
+use mini3d_rust::loader::error::LoadError;
+use mini3d_rust::loader::material::load_materials;
+use mini3d_rust::loader::obj::load_obj;
+use mini3d_rust::math::vector::{Vector3f, Vector4f};
+
+/// spot示例场景
+pub struct DemoScene {
+    pub meshes: Vec<Mesh>,
+    pub textures: Vec<Texture>,
+    /// 需要通过Rasterizer::set_materials设置，三角形按下标引用
+    pub materials: Vec<Material>,
+}
+
+pub fn load_scene() -> Result<DemoScene, LoadError> {
+    let model = load_obj("./spot.obj")?;
+
+    let mut meshes = model.meshes();
+    let mut textures = Vec::new();
+    let materials = load_materials(&model.materials, &mut textures)?;
+
+    //翻转模型z值，法线和切线一起翻转，镜像后副切线的方向也相反
+    for m in meshes.iter_mut() {
+        for v in m.vertices.iter_mut() {
+            v.origin_v.v[2] = -v.origin_v.v[2];
+            v.normal.v[2] = -v.normal.v[2];
+            v.tangent.v[2] = -v.tangent.v[2];
+            v.tangent.v[3] = -v.tangent.v[3];
+        }
+    }
+
+    Ok(DemoScene { meshes, textures, materials })
+}
+
+pub fn build_rasterizer(angle: f32, aspect_ratio: f32) -> Rasterizer {
+    let mut rasterizer = Rasterizer::new();
+    rasterizer.set_model(get_model_matrix(angle));
+
+    rasterizer.set_view(get_view_matrix(
+        Vector4f::new_4(0.0, 0.0, 2.0, 1.0),
+        Vector4f::new_4(0.0, 0.0, 0.0, 1.0),
+        Vector4f::new_4(0.0, 1.0, 0.0, 1.0)
+    ));
+
+    rasterizer.set_projection(get_presp_projection_matrix(60.0, aspect_ratio, -0.1, -50.0));
+    rasterizer.compute_mvp();
+    //模型z值翻转过，正面在屏幕上变成了顺时针
+    rasterizer.set_cull_mode(CullMode::Back);
+    rasterizer.set_front_face(FrontFace::Clockwise);
+    rasterizer.set_eye_pos(Vector3f::new_3(0.0, 0.0, 2.0));
+    rasterizer.set_lights(vec![
+        Light::new(            
+            Vector3f::new_3(20.0, 20.0, 20.0),
+            Vector3f::new_3(500.0, 500.0, 500.0)
+        ),
+        Light::new(            
+            Vector3f::new_3(20.0, -20.0, 0.0),
+            Vector3f::new_3(500.0, 500.0, 500.0)
+        ),
+        ]
+    );
+
+    rasterizer
+}
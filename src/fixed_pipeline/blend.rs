@@ -0,0 +1,156 @@
+use crate::math::vector::Vector4f;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendFactor {
+    /// 第i个通道的混合因子
+    fn factor(&self, src: &Vector4f, dst: &Vector4f, i: usize) -> f32 {
+        match self {
+            BlendFactor::Zero => 0.0,
+            BlendFactor::One => 1.0,
+            BlendFactor::SrcColor => src.v[i],
+            BlendFactor::OneMinusSrcColor => 1.0 - src.v[i],
+            BlendFactor::DstColor => dst.v[i],
+            BlendFactor::OneMinusDstColor => 1.0 - dst.v[i],
+            BlendFactor::SrcAlpha => src.w(),
+            BlendFactor::OneMinusSrcAlpha => 1.0 - src.w(),
+            BlendFactor::DstAlpha => dst.w(),
+            BlendFactor::OneMinusDstAlpha => 1.0 - dst.w(),
+        }
+    }
+}
+
+impl BlendOp {
+    fn apply(&self, s: f32, d: f32, sf: f32, df: f32) -> f32 {
+        match self {
+            BlendOp::Add => s * sf + d * df,
+            BlendOp::Subtract => s * sf - d * df,
+            BlendOp::ReverseSubtract => d * df - s * sf,
+            //和OpenGL一样，min/max忽略混合因子
+            BlendOp::Min => s.min(d),
+            BlendOp::Max => s.max(d),
+        }
+    }
+}
+
+/// 每次绘制的混合状态
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlendState {
+    pub enabled: bool,
+    pub src_color: BlendFactor,
+    pub dst_color: BlendFactor,
+    pub color_op: BlendOp,
+    pub src_alpha: BlendFactor,
+    pub dst_alpha: BlendFactor,
+    pub alpha_op: BlendOp,
+    /// 用alpha决定4倍msaa的覆盖数
+    pub alpha_to_coverage: bool,
+    /// alpha小于这个值的片元被丢弃
+    pub alpha_cutoff: Option<f32>,
+}
+
+impl BlendState {
+    /// 不混合，直接覆盖
+    pub fn new() -> BlendState {
+        BlendState {
+            enabled: false,
+            src_color: BlendFactor::One,
+            dst_color: BlendFactor::Zero,
+            color_op: BlendOp::Add,
+            src_alpha: BlendFactor::One,
+            dst_alpha: BlendFactor::Zero,
+            alpha_op: BlendOp::Add,
+            alpha_to_coverage: false,
+            alpha_cutoff: None,
+        }
+    }
+
+    /// 常规的半透明混合 src * a + dst * (1 - a)
+    pub fn alpha_blending() -> BlendState {
+        BlendState {
+            enabled: true,
+            src_color: BlendFactor::SrcAlpha,
+            dst_color: BlendFactor::OneMinusSrcAlpha,
+            color_op: BlendOp::Add,
+            src_alpha: BlendFactor::One,
+            dst_alpha: BlendFactor::OneMinusSrcAlpha,
+            alpha_op: BlendOp::Add,
+            ..BlendState::new()
+        }
+    }
+
+    pub fn additive() -> BlendState {
+        BlendState {
+            enabled: true,
+            src_color: BlendFactor::One,
+            dst_color: BlendFactor::One,
+            src_alpha: BlendFactor::One,
+            dst_alpha: BlendFactor::One,
+            ..BlendState::new()
+        }
+    }
+
+    /// 片元是否因为alpha测试被丢弃
+    pub fn discard(&self, color: &Vector4f) -> bool {
+        match self.alpha_cutoff {
+            Some(cutoff) => color.w() < cutoff,
+            None => false,
+        }
+    }
+
+    /// 返回最终的覆盖率(0~1)，开启alpha_to_coverage时按alpha减少覆盖的采样点，并把alpha置为1
+    pub fn resolve_coverage(&self, color: &mut Vector4f, coverage: f32) -> f32 {
+        if !self.alpha_to_coverage {
+            return coverage;
+        }
+
+        let samples = (color.w().clamp(0.0, 1.0) * 4.0).round();
+        color.v[3] = 1.0;
+        coverage.min(samples / 4.0)
+    }
+
+    pub fn blend(&self, src: &Vector4f, dst: &Vector4f) -> Vector4f {
+        if !self.enabled {
+            return *src;
+        }
+
+        let mut r = Vector4f::new();
+        for i in 0..3 {
+            let sf = self.src_color.factor(src, dst, i);
+            let df = self.dst_color.factor(src, dst, i);
+            r.v[i] = self.color_op.apply(src.v[i], dst.v[i], sf, df);
+        }
+
+        let sf = self.src_alpha.factor(src, dst, 3);
+        let df = self.dst_alpha.factor(src, dst, 3);
+        r.v[3] = self.alpha_op.apply(src.w(), dst.w(), sf, df);
+        r
+    }
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,203 @@
+use crate::common::triangle::{vertex_interp, Triangle, Vertex};
+use crate::math::vector::Vector4f;
+
+/// w 过小时透视除法会溢出，裁掉这部分
+const W_EPSILON: f32 = 1e-5;
+
+/// 齐次裁剪空间的平面，可见区域为 -w<=x<=w, -w<=y<=w, -w<=z<=0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipPlane {
+    W,
+    Near,
+    Far,
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+pub const CLIP_PLANES: [ClipPlane; 7] = [
+    ClipPlane::W,
+    ClipPlane::Near,
+    ClipPlane::Far,
+    ClipPlane::Left,
+    ClipPlane::Right,
+    ClipPlane::Bottom,
+    ClipPlane::Top,
+];
+
+impl ClipPlane {
+    /// 点到平面的有向距离，>=0 表示在可见一侧
+    pub fn distance(&self, p: &Vector4f) -> f32 {
+        match self {
+            ClipPlane::W => p.w() - W_EPSILON,
+            ClipPlane::Near => -p.z(),
+            ClipPlane::Far => p.z() + p.w(),
+            ClipPlane::Left => p.x() + p.w(),
+            ClipPlane::Right => p.w() - p.x(),
+            ClipPlane::Bottom => p.y() + p.w(),
+            ClipPlane::Top => p.w() - p.y(),
+        }
+    }
+}
+
+/// Sutherland–Hodgman 裁剪一个凸多边形，vertex.v 存放裁剪空间坐标
+pub fn clip_polygon(polygon: Vec<Vertex>, plane: ClipPlane) -> Vec<Vertex> {
+    let mut result = Vec::new();
+    if polygon.is_empty() {
+        return result;
+    }
+
+    for i in 0..polygon.len() {
+        let cur = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d1 = plane.distance(&cur.v);
+        let d2 = plane.distance(&next.v);
+
+        if d1 >= 0.0 {
+            result.push(cur.clone());
+        }
+
+        if (d1 >= 0.0) != (d2 >= 0.0) {
+            result.push(vertex_interp(cur, next, d1 / (d1 - d2)));
+        }
+    }
+
+    result
+}
+
+/// 把裁剪空间中的三角形裁剪成若干个三角形(扇形)，完全在视锥内时原样返回
+pub fn clip_triangle(triangle: &Triangle) -> Vec<Triangle> {
+    let mut inside = true;
+    for plane in CLIP_PLANES {
+        let d: Vec<f32> = triangle.vertexs.iter().map(|v| plane.distance(&v.v)).collect();
+        if d.iter().all(|d| *d < 0.0) {
+            return Vec::new();
+        }
+        if d.iter().any(|d| *d < 0.0) {
+            inside = false;
+        }
+    }
+
+    if inside {
+        return vec![triangle.clone()];
+    }
+
+    let mut polygon = triangle.vertexs.clone();
+    for plane in CLIP_PLANES {
+        polygon = clip_polygon(polygon, plane);
+    }
+
+    let mut result = Vec::new();
+    for i in 1..polygon.len().saturating_sub(1) {
+        let mut t = triangle.clone();
+        t.vertexs = vec![polygon[0].clone(), polygon[i].clone(), polygon[i + 1].clone()];
+        result.push(t);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Color3f;
+
+    fn vertex(x: f32, y: f32, z: f32, w: f32) -> Vertex {
+        Vertex { v: Vector4f::new_4(x, y, z, w), ..Vertex::default() }
+    }
+
+    /// 每个平面外侧距离为-1的点
+    fn outside(plane: ClipPlane) -> Vertex {
+        match plane {
+            ClipPlane::W => vertex(0.0, 0.0, 0.0, -1.0),
+            ClipPlane::Near => vertex(0.0, 0.0, 1.0, 1.0),
+            ClipPlane::Far => vertex(0.0, 0.0, -2.0, 1.0),
+            ClipPlane::Left => vertex(-2.0, 0.0, -0.5, 1.0),
+            ClipPlane::Right => vertex(2.0, 0.0, -0.5, 1.0),
+            ClipPlane::Bottom => vertex(0.0, -2.0, -0.5, 1.0),
+            ClipPlane::Top => vertex(0.0, 2.0, -0.5, 1.0),
+        }
+    }
+
+    /// 在所有平面内侧的两个点
+    fn inside() -> [Vertex; 2] {
+        [vertex(0.5, 0.25, -0.5, 1.0), vertex(-0.25, -0.5, -0.25, 1.0)]
+    }
+
+    #[test]
+    fn one_vertex_outside_each_plane() {
+        for plane in CLIP_PLANES {
+            let [a, b] = inside();
+            let mut o = outside(plane);
+            o.color = Color3f::new_3(1.0, 0.0, 0.0);
+            let r = clip_polygon(vec![o.clone(), a.clone(), b.clone()], plane);
+
+            //外侧的点换成两条边上的交点
+            assert_eq!(r.len(), 4, "{:?}", plane);
+            assert_eq!((r[1].v, r[2].v), (a.v, b.v), "{:?}", plane);
+            for x in [&r[0], &r[3]] {
+                assert!(plane.distance(&x.v).abs() < 1e-5, "{:?}: {:?}", plane, x.v);
+            }
+
+            //属性和位置用同一个比例插值，r[3]在b到o的边上
+            let t = plane.distance(&b.v) / (plane.distance(&b.v) - plane.distance(&o.v));
+            assert!((r[3].color.r() - t).abs() < 1e-6, "{:?}", plane);
+        }
+    }
+
+    #[test]
+    fn two_vertices_outside_each_plane() {
+        for plane in CLIP_PLANES {
+            let [a, _] = inside();
+            let r = clip_polygon(vec![outside(plane), a.clone(), outside(plane)], plane);
+            assert_eq!(r.len(), 3, "{:?}", plane);
+            assert_eq!(r[1].v, a.v);
+            assert!([&r[0], &r[2]].iter().all(|x| plane.distance(&x.v).abs() < 1e-5), "{:?}", plane);
+        }
+    }
+
+    #[test]
+    fn polygon_fully_inside_or_outside() {
+        for plane in CLIP_PLANES {
+            let [a, b] = inside();
+            let c = vertex(0.0, 0.5, -0.75, 1.0);
+            let r = clip_polygon(vec![a.clone(), b.clone(), c.clone()], plane);
+            let v: Vec<Vector4f> = r.iter().map(|x| x.v).collect();
+            assert_eq!(v, vec![a.v, b.v, c.v], "{:?}", plane);
+
+            assert!(clip_polygon(vec![outside(plane); 3], plane).is_empty(), "{:?}", plane);
+        }
+        assert!(clip_polygon(Vec::new(), ClipPlane::Near).is_empty());
+    }
+
+    #[test]
+    fn clip_triangle_stays_inside_frustum() {
+        let mut t = Triangle::new();
+        t.vertexs = vec![vertex(0.0, 0.0, -0.5, 1.0), vertex(3.0, 0.0, -0.5, 1.0), vertex(0.0, 3.0, -0.5, 1.0)];
+        let r = clip_triangle(&t);
+
+        //被Right和Top裁成单位正方形，按扇形拆成2个三角形
+        assert_eq!(r.len(), 2);
+        for x in r.iter().flat_map(|t| t.vertexs.iter()) {
+            for plane in CLIP_PLANES {
+                assert!(plane.distance(&x.v) > -1e-5, "{:?}: {:?}", plane, x.v);
+            }
+        }
+    }
+
+    #[test]
+    fn clip_triangle_trivial_cases() {
+        let mut t = Triangle::new();
+        let [a, b] = inside();
+        t.vertexs = vec![a, b, vertex(0.0, 0.5, -0.75, 1.0)];
+        let r = clip_triangle(&t);
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].vertexs[2].v, t.vertexs[2].v);
+
+        for plane in CLIP_PLANES {
+            t.vertexs = vec![outside(plane); 3];
+            assert!(clip_triangle(&t).is_empty(), "{:?}", plane);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::common::triangle::Triangle;
+
+/// 面积小于这个值(像素^2)的三角形视为退化三角形
+const DEGENERATE_AREA: f32 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+/// 屏幕上看到的正面顶点顺序(y轴朝上)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// 视口变换之后的有向面积，屏幕坐标y轴朝下，所以看上去逆时针的三角形面积为负
+pub fn signed_area(triangle: &Triangle) -> f32 {
+    let a = &triangle.vertexs[0].v;
+    let b = &triangle.vertexs[1].v;
+    let c = &triangle.vertexs[2].v;
+    0.5 * ((b.x() - a.x()) * (c.y() - a.y()) - (c.x() - a.x()) * (b.y() - a.y()))
+}
+
+pub fn is_front_facing(triangle: &Triangle, front_face: FrontFace) -> bool {
+    let ccw = signed_area(triangle) < 0.0;
+    match front_face {
+        FrontFace::CounterClockwise => ccw,
+        FrontFace::Clockwise => !ccw,
+    }
+}
+
+pub fn should_cull(triangle: &Triangle, mode: CullMode, front_face: FrontFace) -> bool {
+    let area = signed_area(triangle);
+    if area.is_nan() || area.abs() < DEGENERATE_AREA {
+        return true;
+    }
+
+    match mode {
+        CullMode::None => false,
+        CullMode::Back => !is_front_facing(triangle, front_face),
+        CullMode::Front => is_front_facing(triangle, front_face),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    /// 屏幕坐标的三角形
+    fn screen_triangle(p: [(f32, f32); 3]) -> Triangle {
+        let mut t = Triangle::new();
+        for (v, (x, y)) in t.vertexs.iter_mut().zip(p) {
+            v.v = Vector4f::new_4(x, y, 0.0, 1.0);
+        }
+        t
+    }
+
+    //y轴朝下：先向右再向下，屏幕上看是顺时针
+    fn cw() -> Triangle {
+        screen_triangle([(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)])
+    }
+
+    fn ccw() -> Triangle {
+        screen_triangle([(0.0, 0.0), (0.0, 10.0), (10.0, 0.0)])
+    }
+
+    #[test]
+    fn signed_area_sign() {
+        assert_eq!(signed_area(&cw()), 50.0);
+        assert_eq!(signed_area(&ccw()), -50.0);
+        assert!(is_front_facing(&cw(), FrontFace::Clockwise));
+        assert!(!is_front_facing(&cw(), FrontFace::CounterClockwise));
+        assert!(is_front_facing(&ccw(), FrontFace::CounterClockwise));
+    }
+
+    #[test]
+    fn cull_table() {
+        //(三角形是否顺时针, 正面, 模式, 是否剔除)
+        let cases = [
+            (true, FrontFace::Clockwise, CullMode::Back, false),
+            (false, FrontFace::Clockwise, CullMode::Back, true),
+            (true, FrontFace::CounterClockwise, CullMode::Back, true),
+            (false, FrontFace::CounterClockwise, CullMode::Back, false),
+            (true, FrontFace::Clockwise, CullMode::Front, true),
+            (false, FrontFace::Clockwise, CullMode::Front, false),
+            (true, FrontFace::CounterClockwise, CullMode::Front, false),
+            (false, FrontFace::CounterClockwise, CullMode::Front, true),
+            (true, FrontFace::Clockwise, CullMode::None, false),
+            (false, FrontFace::Clockwise, CullMode::None, false),
+            (true, FrontFace::CounterClockwise, CullMode::None, false),
+            (false, FrontFace::CounterClockwise, CullMode::None, false),
+        ];
+        for (is_cw, front, mode, expected) in cases {
+            let t = if is_cw { cw() } else { ccw() };
+            assert_eq!(should_cull(&t, mode, front), expected, "cw={} {:?} {:?}", is_cw, front, mode);
+        }
+    }
+
+    #[test]
+    fn degenerate_and_nan_are_always_culled() {
+        let collinear = screen_triangle([(0.0, 0.0), (5.0, 5.0), (10.0, 10.0)]);
+        let point = screen_triangle([(3.0, 3.0); 3]);
+        let tiny = screen_triangle([(0.0, 0.0), (1e-4, 0.0), (0.0, 1e-4)]);
+        let nan = screen_triangle([(0.0, 0.0), (f32::NAN, 0.0), (0.0, 10.0)]);
+        for t in [collinear, point, tiny, nan] {
+            for front in [FrontFace::Clockwise, FrontFace::CounterClockwise] {
+                for mode in [CullMode::None, CullMode::Back, CullMode::Front] {
+                    assert!(should_cull(&t, mode, front), "{:?} {:?}", front, mode);
+                }
+            }
+        }
+
+        //面积大于阈值的小三角形不算退化
+        let small = screen_triangle([(0.0, 0.0), (1e-2, 0.0), (0.0, 1e-2)]);
+        assert!(!should_cull(&small, CullMode::None, FrontFace::Clockwise));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::math::matrix::Mat4x4f;
+
+use super::framebuffer::CompareFunc;
+
+/// 深度测试状态，每次绘制可以不同
+/// 默认深度范围near为0，far为-1，越大越近；reversed_z时near为-1，far为0，越小越近
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthState {
+    pub compare: CompareFunc,
+    pub write: bool,
+    pub reversed_z: bool,
+}
+
+impl DepthState {
+    pub fn new() -> DepthState {
+        DepthState {
+            compare: CompareFunc::GreaterEqual,
+            write: true,
+            reversed_z: false,
+        }
+    }
+
+    pub fn new_reversed_z() -> DepthState {
+        DepthState {
+            compare: CompareFunc::LessEqual,
+            write: true,
+            reversed_z: true,
+        }
+    }
+
+    pub fn test(&self, z: f32, stored: f32) -> bool {
+        self.compare.test(z, stored)
+    }
+
+    /// far平面的深度，用作深度缓冲的清除值
+    pub fn far_value(&self) -> f32 {
+        if self.reversed_z {
+            0.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 把ndc的z从(-1,0)翻转为-1-z，在裁剪空间里就是z' = -z - w
+pub fn get_reversed_z_matrix() -> Mat4x4f {
+    Mat4x4f::from_array([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, -1.0, -1.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
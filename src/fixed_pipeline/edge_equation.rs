@@ -1,10 +1,9 @@
-use crate::common::light::compute_light;
 use crate::common::texture::Texture;
 use crate::common::triangle::{RenderType, Triangle};
-use crate::math::utils::{interpolate_vector2f, interpolate_vector3f, interpolate_vector4f};
-use crate::math::vector::Vector3f;
 use crate::math::{utils::clamp, vector::Vector4f};
+use crate::shader::program::{interpolate_varyings, Fragment, Uniforms};
 
+use super::framebuffer::Framebuffer;
 use super::rasterizer::{Rasterizer, RenderResult};
 
 pub fn draw_trangle_edge_equation_result(
@@ -14,160 +13,108 @@ pub fn draw_trangle_edge_equation_result(
     triangle: &Triangle,
     textures: &Vec<Texture>
 ) -> Vec<RenderResult> {
-    let mut l = std::f32::MAX;
-    let mut r = std::f32::MIN;
-    let mut t = std::f32::MIN;
-    let mut b = std::f32::MAX;
+    let uniforms = rasterizer.uniforms(textures, triangle.material);
+    let blend_state = rasterizer.get_blend_state();
+    let mut result = Vec::new();
 
+    rasterize_quads(width, height, triangle, |fragment, coverage| {
+        let mut color = match shade_fragment(rasterizer, &uniforms, &triangle.render, fragment) {
+            Some(c) => c,
+            None => return,
+        };
 
-    for i in 0..triangle.vertexs.len() {
-        l = clamp(triangle.vertexs[i].v.x().min(l), 0.0, (width - 1) as f32);
-        r = clamp(triangle.vertexs[i].v.x().max(r), 0.0, (width - 1) as f32);
-        t = clamp(triangle.vertexs[i].v.y().max(t), 0.0, (height - 1) as f32);
-        b = clamp(triangle.vertexs[i].v.y().min(b), 0.0, (height - 1) as f32);
-    }
+        let coverage = blend_state.resolve_coverage(&mut color, coverage);
+        if coverage <= 0.0 {
+            return;
+        }
 
-    //let (p1, p2, p3) = retriangle(triangle);
-    let p1 = &triangle.vertexs[0].v;
-    let p2 = &triangle.vertexs[1].v;
-    let p3 = &triangle.vertexs[2].v;
+        let mut res = RenderResult::new();
+        res.z = fragment.z;
+        res.idx = width * fragment.y + fragment.x;
+        res.color = color;
+        res.coverage = coverage;
 
-    let mut result = Vec::new();
+        result.push(res);
+    });
 
+    result
+}
 
-    for i in l.round() as i32..=r.round() as i32 {
-        for j in b.round() as i32..=t.round() as i32 {
-            match triangle.render {
-                RenderType::COLOR => {
-                    //msaa
-                    let pos = vec![(0.25, 0.25), (0.25, 0.75), (0.75, 0.25), (0.75, 0.75)];
 
-                    let mut count = 0.0;
-                    let mut min_depth = 100.0;
-                    for p in pos {
-                        if inside_triangle(i as f32 + p.0, j as f32 + p.1, &p1, &p2, &p3) {
-                            let (alpha, beta, gamma) =
-                                compute_barycentric_2d(i as f32 + p.0, j as f32 + p.1, triangle);
-                            let z = alpha * triangle.vertexs[0].v.z()
-                                + beta * triangle.vertexs[1].v.z()
-                                + gamma * triangle.vertexs[2].v.z();
-                            min_depth = z.min(min_depth);
-                            count += 1.0;
-                        }
-                    }
+pub fn draw_trangle_edge_equation(
+    framebuffer: &mut Framebuffer,
+    rasterizer: &Rasterizer,
+    triangle: &Triangle,
+    textures: &Vec<Texture>
+) {
+    let width = framebuffer.width();
+    let height = framebuffer.height();
+    let uniforms = rasterizer.uniforms(textures, triangle.material);
+    let depth_state = rasterizer.get_depth_state();
+    let stencil_state = rasterizer.get_stencil_state();
+    let blend_state = rasterizer.get_blend_state();
+
+    rasterize_quads(width, height, triangle, |fragment, coverage| {
+        let idx = (width * fragment.y + fragment.x) as usize;
+        if !framebuffer.depth_stencil_test(idx, fragment.z, depth_state, stencil_state) {
+            return;
+        }
 
-                    if count > 0.0 {
-                        let mut res = RenderResult::new();
-                        res.z = min_depth;
-                        res.idx = width * j + i;
-                        let a = &triangle.vertexs[0].color;
-                        let b = &triangle.vertexs[1].color;
-                        let c = &triangle.vertexs[2].color;
-                        let (alpha, beta, gamma) =
-                            compute_barycentric_2d(i as f32 + 0.5, j as f32 + 0.5, triangle);
-
-                        let origin_b = 0.0;
-                        let origin_g = 0.0;
-                        let origin_r = 0.0;
-
-                        res.r = clamp(
-                            (((a.r() * alpha + b.r() * beta + gamma * c.r()) * count
-                                + origin_r * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        ) as u8;
-                        res.g = clamp(
-                            (((a.g() * alpha + b.g() * beta + gamma * c.g()) * count
-                                + origin_g * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        ) as u8;
-                        res.b = clamp(
-                            (((a.b() * alpha + b.b() * beta + gamma * c.b()) * count
-                                + origin_b * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        ) as u8;
-
-                        result.push(res);
-                    }
-                }
+        let mut color = match shade_fragment(rasterizer, &uniforms, &triangle.render, fragment) {
+            Some(c) => c,
+            None => return,
+        };
 
-                RenderType::TEXTURE => {
-                    if inside_triangle(i as f32 + 0.5, j as f32 + 0.5, &p1, &p2, &p3) {
-
-                        let (alpha, beta, gamma) =
-                            compute_barycentric_2d(i as f32 + 0.5, j as f32 + 0.5, triangle);
-        
-                        let z = alpha * triangle.vertexs[0].v.z() + beta * triangle.vertexs[1].v.z() + gamma * triangle.vertexs[2].v.z();
-                        let mut res = RenderResult::new();
-
-                        res.z = z;
-                        res.idx = width * j + i;
-
-                        let uv = interpolate_vector2f(&triangle.vertexs[0].tex_coords,
-                            &triangle.vertexs[1].tex_coords,
-                            &triangle.vertexs[2].tex_coords, alpha, beta, gamma);
-
-                        let mv = interpolate_vector4f(&triangle.vertexs[0].tv,
-                            &triangle.vertexs[1].tv,
-                            &triangle.vertexs[2].tv, alpha, beta, gamma);
-
-                        let n = interpolate_vector3f(&triangle.vertexs[0].normal,
-                            &triangle.vertexs[1].normal,
-                            &triangle.vertexs[2].normal, alpha, beta, gamma);
-
-                        let (mut r, mut g, mut b) = textures[0].get_color(uv.u(), 1.0-uv.v());
-
-                        if rasterizer.get_lights().len() > 0 {
-                            let result = compute_light(
-                                &Vector3f::new_3(mv.x(), mv.y(), mv.z()), 
-                                &n, 
-                                rasterizer.get_lights(),
-                                &Vector3f::new_3(0.005, 0.005, 0.005),
-                                &Vector3f::new_3(r as f32  / 255.0, g as f32 / 255.0, b as f32 / 255.0),
-                                &Vector3f::new_3(0.7937, 0.7937, 0.7937),
-                                rasterizer.get_eye_pos());
-
-                            r = (result.r() * 255.0) as u8;
-                            g = (result.g() * 255.0) as u8;
-                            b = (result.b() * 255.0) as u8;
-                        }
+        let coverage = blend_state.resolve_coverage(&mut color, coverage);
+        if coverage <= 0.0 {
+            return;
+        }
 
-                        res.r = r;
-                        res.g = g;
-                        res.b = b;
+        framebuffer.update_stencil(idx, stencil_state, stencil_state.pass);
+        framebuffer.update_depth(idx, fragment.z, depth_state);
+        framebuffer.blend_color(idx, &color, coverage, blend_state);
+    });
+}
 
-                        result.push(res);
-                    }
-                }
-            }
-        }
+/// 2x2的像素块，和GPU一样用相邻像素varyings的差分作为屏幕空间导数
+/// 下标k对应的像素是(k % 2, k / 2)，没有被覆盖的像素也会插值(helper pixel)
+struct Quad {
+    varyings: [Vec<f32>; 4],
+}
+
+impl Quad {
+    fn new(triangle: &Triangle, x: i32, y: i32) -> Quad {
+        let varyings = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+            let (alpha, beta, gamma) =
+                compute_barycentric_2d((x + dx) as f32 + 0.5, (y + dy) as f32 + 0.5, triangle);
+            interpolate_varyings(triangle, alpha, beta, gamma)
+        });
+        Quad { varyings }
     }
 
-    result
+    /// 第k个像素的(ddx, ddy)
+    fn derivatives(&self, k: usize) -> (Vec<f32>, Vec<f32>) {
+        let (col, row) = (k % 2, k / 2);
+        let ddx = diff(&self.varyings[row * 2 + 1], &self.varyings[row * 2]);
+        let ddy = diff(&self.varyings[col + 2], &self.varyings[col]);
+        (ddx, ddy)
+    }
 }
 
+fn diff(a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter().zip(b.iter()).map(|(a, b)| a - b).collect()
+}
 
-pub fn draw_trangle_edge_equation(
-    image: &mut Vec<u8>,
-    rasterizer: &Rasterizer,
-    zbuf: &mut Vec<f32>,
-    width: i32,
-    height: i32,
-    triangle: &Triangle,
-    textures: &Vec<Texture>
-) {
-    let mut l = std::f32::MAX;
-    let mut r = std::f32::MIN;
-    let mut t = std::f32::MIN;
-    let mut b = std::f32::MAX;
+/// 按2x2像素块遍历包围盒，对覆盖到的像素调用f(fragment, coverage)
+/// COLOR用4倍msaa的覆盖率和最小深度，TEXTURE只采样像素中心
+fn rasterize_quads<F>(width: i32, height: i32, triangle: &Triangle, mut f: F)
+where
+    F: FnMut(&Fragment, f32),
+{
+    let mut l = f32::MAX;
+    let mut r = f32::MIN;
+    let mut t = f32::MIN;
+    let mut b = f32::MAX;
 
 
     for i in 0..triangle.vertexs.len() {
@@ -182,165 +129,102 @@ pub fn draw_trangle_edge_equation(
     let p2 = &triangle.vertexs[1].v;
     let p3 = &triangle.vertexs[2].v;
 
+    let (l, r) = (l.round() as i32, r.round() as i32);
+    let (b, t) = (b.round() as i32, t.round() as i32);
+
+    //块的起点对齐到偶数
+    for qx in (l & !1..=r).step_by(2) {
+        for qy in (b & !1..=t).step_by(2) {
+            let mut covered = [None; 4];
+            for (k, c) in covered.iter_mut().enumerate() {
+                let (i, j) = (qx + (k % 2) as i32, qy + (k / 2) as i32);
+                if i < l || i > r || j < b || j > t {
+                    continue;
+                }
 
-    for i in l.round() as i32..=r.round() as i32 {
-        for j in b.round() as i32..=t.round() as i32 {
-            match triangle.render {
-                RenderType::COLOR => {
-                    //msaa
-                    let pos = vec![(0.25, 0.25), (0.25, 0.75), (0.75, 0.25), (0.75, 0.75)];
-
-                    let mut count = 0.0;
-                    let mut min_depth = 100.0;
-                    for p in pos {
-                        if inside_triangle(i as f32 + p.0, j as f32 + p.1, &p1, &p2, &p3) {
-                            let (alpha, beta, gamma) =
-                                compute_barycentric_2d(i as f32 + p.0, j as f32 + p.1, triangle);
-                            let z = alpha * triangle.vertexs[0].v.z()
-                                + beta * triangle.vertexs[1].v.z()
-                                + gamma * triangle.vertexs[2].v.z();
-                            min_depth = z.min(min_depth);
-                            count += 1.0;
+                match triangle.render {
+                    RenderType::COLOR => {
+                        //msaa
+                        let (count, min_depth) = msaa_coverage(i, j, p1, p2, p3, triangle);
+                        if count > 0.0 {
+                            *c = Some((min_depth, count / 4.0));
                         }
                     }
 
-                    if count > 0.0 && min_depth >= zbuf[(width * j + i) as usize] {
-                        zbuf[(width * j + i) as usize] = min_depth;
-                        let a = &triangle.vertexs[0].color;
-                        let b = &triangle.vertexs[1].color;
-                        let c = &triangle.vertexs[2].color;
-                        let (alpha, beta, gamma) =
-                            compute_barycentric_2d(i as f32 + 0.5, j as f32 + 0.5, triangle);
-
-                        let origin_b = image[((width * j + i) * 4) as usize] as f32 / 255.0;
-                        let origin_g = image[((width * j + i) * 4 + 1) as usize] as f32 / 255.0;
-                        let origin_r = image[((width * j + i) * 4 + 2) as usize] as f32 / 255.0;
-
-                        let cur_r = clamp(
-                            (((a.r() * alpha + b.r() * beta + gamma * c.r()) * count
-                                + origin_r * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        );
-                        let cur_g = clamp(
-                            (((a.g() * alpha + b.g() * beta + gamma * c.g()) * count
-                                + origin_g * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        );
-                        let cur_b = clamp(
-                            (((a.b() * alpha + b.b() * beta + gamma * c.b()) * count
-                                + origin_b * (4.0 - count))
-                                * 255.0
-                                / 4.0) as i32,
-                            0,
-                            255,
-                        );
-
-                        image[((width * j + i) * 4) as usize] = cur_b as u8;
-                        image[((width * j + i) * 4 + 1) as usize] = cur_g as u8;
-                        image[((width * j + i) * 4 + 2) as usize] = cur_r as u8;
-                        image[((width * j + i) * 4 + 3) as usize] = 255;
+                    RenderType::TEXTURE => {
+                        if inside_triangle(i as f32 + 0.5, j as f32 + 0.5, p1, p2, p3) {
+                            let (alpha, beta, gamma) =
+                                compute_barycentric_2d(i as f32 + 0.5, j as f32 + 0.5, triangle);
+                            let z = alpha * triangle.vertexs[0].v.z() + beta * triangle.vertexs[1].v.z() + gamma * triangle.vertexs[2].v.z();
+                            *c = Some((z, 1.0));
+                        }
                     }
                 }
+            }
 
-                RenderType::TEXTURE => {
-                    if inside_triangle(i as f32 + 0.5, j as f32 + 0.5, &p1, &p2, &p3) {
-
-                        let (alpha, beta, gamma) =
-                            compute_barycentric_2d(i as f32 + 0.5, j as f32 + 0.5, triangle);
-        
-                        let z = alpha * triangle.vertexs[0].v.z() + beta * triangle.vertexs[1].v.z() + gamma * triangle.vertexs[2].v.z();
-                        if z < zbuf[(width * j + i) as usize] {
-                            continue;
-                        }
-        
-                        zbuf[(width * j + i) as usize] = z;
-
-                        let uv = interpolate_vector2f(&triangle.vertexs[0].tex_coords,
-                            &triangle.vertexs[1].tex_coords,
-                            &triangle.vertexs[2].tex_coords, alpha, beta, gamma);
-
-                        let mv = interpolate_vector4f(&triangle.vertexs[0].tv,
-                            &triangle.vertexs[1].tv,
-                            &triangle.vertexs[2].tv, alpha, beta, gamma);
-
-                        let n = interpolate_vector3f(&triangle.vertexs[0].normal,
-                            &triangle.vertexs[1].normal,
-                            &triangle.vertexs[2].normal, alpha, beta, gamma);
-
-                        let (mut r, mut g, mut b) = textures[0].get_color(uv.u(), 1.0-uv.v());
-
-                        if rasterizer.get_lights().len() > 0 {
-                            let result = compute_light(
-                                &Vector3f::new_3(mv.x(), mv.y(), mv.z()), 
-                                &n, 
-                                rasterizer.get_lights(),
-                                &Vector3f::new_3(0.005, 0.005, 0.005),
-                                &Vector3f::new_3(r as f32  / 255.0, g as f32 / 255.0, b as f32 / 255.0),
-                                &Vector3f::new_3(0.7937, 0.7937, 0.7937),
-                                rasterizer.get_eye_pos());
-
-                            r = (result.r() * 255.0) as u8;
-                            g = (result.g() * 255.0) as u8;
-                            b = (result.b() * 255.0) as u8;
-                        }
-
-                        image[((width * j + i) * 4) as usize] = b;
-                        image[((width * j + i) * 4 + 1) as usize] = g;
-                        image[((width * j + i) * 4 + 2) as usize] = r;
-                        image[((width * j + i) * 4 + 3) as usize] = 255;
+            if covered.iter().all(|c| c.is_none()) {
+                continue;
+            }
 
-                    }
+            let quad = Quad::new(triangle, qx, qy);
+            for (k, c) in covered.iter().enumerate() {
+                if let Some((z, coverage)) = *c {
+                    let (ddx, ddy) = quad.derivatives(k);
+                    let fragment = Fragment {
+                        x: qx + (k % 2) as i32,
+                        y: qy + (k / 2) as i32,
+                        z,
+                        varyings: &quad.varyings[k],
+                        ddx: &ddx,
+                        ddy: &ddy,
+                    };
+                    f(&fragment, coverage);
                 }
             }
         }
     }
 }
 
-fn retriangle(triangle: &Triangle) -> (Vector4f, Vector4f, Vector4f) {
-    let p0 = triangle.vertexs[0].v.clone();
-    let p1 = triangle.vertexs[1].v.clone();
-    let p2 = triangle.vertexs[2].v.clone();
-    let (min, mid, max) = {
-        if p0.y() > p1.y() {
-            if p0.y() < p2.y() {
-                (p1, p0, p2)
-            } else if p1.y() > p2.y() {
-                (p2, p1, p0)
-            } else {
-                (p1, p2, p0)
-            }
-        } else {
-            if p1.y() < p2.y() {
-                (p0, p1, p2)
-            } else if p0.y() > p2.y() {
-                (p2, p0, p1)
-            } else {
-                (p0, p2, p1)
-            }
+/// 4倍采样的覆盖数和最小深度
+fn msaa_coverage(i: i32, j: i32, p1: &Vector4f, p2: &Vector4f, p3: &Vector4f, triangle: &Triangle) -> (f32, f32) {
+    let pos = [(0.25, 0.25), (0.25, 0.75), (0.75, 0.25), (0.75, 0.75)];
+
+    let mut count = 0.0;
+    let mut min_depth = 100.0;
+    for p in pos {
+        if inside_triangle(i as f32 + p.0, j as f32 + p.1, p1, p2, p3) {
+            let (alpha, beta, gamma) =
+                compute_barycentric_2d(i as f32 + p.0, j as f32 + p.1, triangle);
+            let z = alpha * triangle.vertexs[0].v.z()
+                + beta * triangle.vertexs[1].v.z()
+                + gamma * triangle.vertexs[2].v.z();
+            min_depth = z.min(min_depth);
+            count += 1.0;
         }
-    };
+    }
 
-    if mid.x() > max.x() {
-        (min, mid, max)
-    } else {
-        (min, max, mid)
+    (count, min_depth)
+}
+
+/// 调用片元着色器，alpha测试不通过时返回None
+fn shade_fragment(rasterizer: &Rasterizer, uniforms: &Uniforms, render: &RenderType, fragment: &Fragment) -> Option<Vector4f> {
+    let color = rasterizer.get_fragment_shader(render, uniforms.material).shade(fragment, uniforms)?;
+    if rasterizer.get_blend_state().discard(&color) {
+        return None;
     }
+
+    Some(color)
 }
 
 fn inside_triangle(x: f32, y: f32, a: &Vector4f, b: &Vector4f, c: &Vector4f) -> bool {
     let v1 = (b.x() - a.x()) * (y - a.y()) - (b.y() - a.y()) * (x - a.x());
     let v2 = (c.x() - b.x()) * (y - b.y()) - (c.y() - b.y()) * (x - b.x());
     let v3 = (a.x() - c.x()) * (y - c.y()) - (a.y() - c.y()) * (x - c.x());
-    return v1 > 0.0 && v2 > 0.0 && v3 > 0.0;
+    //两种顶点顺序都接受，背面剔除在之前的阶段完成
+    (v1 > 0.0 && v2 > 0.0 && v3 > 0.0) || (v1 < 0.0 && v2 < 0.0 && v3 < 0.0)
 }
 
-fn compute_barycentric_2d(x: f32, y: f32, triangle: &Triangle) -> (f32, f32, f32) {
+pub fn compute_barycentric_2d(x: f32, y: f32, triangle: &Triangle) -> (f32, f32, f32) {
     let a = &triangle.vertexs[0].v;
     let b = &triangle.vertexs[1].v;
     let c = &triangle.vertexs[2].v;
@@ -1,9 +1,12 @@
-use crate::{math::{utils::clamp, vector::{Vector4f, Color3f, Point2f, Point3f, Vector3f}}, common::{triangle::{RenderType, self}, texture::Texture, light::compute_light}};
+use crate::{math::{utils::clamp, vector::{Vector4f, Color3f, Point2f, Point3f}}, common::{triangle::RenderType, texture::Texture}};
+use crate::shader::program::{Fragment, Uniforms};
 
 use crate::common::triangle::{Vertex, vertex_interp, Triangle};
 
-use super::rasterizer::{self, Rasterizer};
+use super::framebuffer::Framebuffer;
+use super::rasterizer::Rasterizer;
 
+#[derive(Default)]
 struct Trapezoid<'a > {
     t: f32,
     b: f32,
@@ -25,21 +28,6 @@ struct Scanline {
 }
 
 
-impl<'a> Default for Trapezoid<'a> {
-    fn default() -> Self {
-        Self { 
-            t: Default::default(), 
-            b: Default::default(), 
-            l1: Default::default(), 
-            l2: Default::default(), 
-            r1: Default::default(), 
-            r2: Default::default(), 
-            l: Default::default(),
-            r: Default::default()
-         }
-    }
-}
-
 fn trapezoid_init<'a>(p0: &'a Vertex, p1: &'a Vertex, p2: &'a Vertex) -> Vec<Trapezoid<'a>> {
     let mut traps = Vec::new();
     let (min, mid, max) = {
@@ -62,16 +50,13 @@ fn trapezoid_init<'a>(p0: &'a Vertex, p1: &'a Vertex, p2: &'a Vertex) -> Vec<Tra
         }
     };
 
-    if min.v.y() == mid.v.y() && min.v.y() == max.v.y() {
-        return traps;
-    } else if min.v.x() == mid.v.x() && min.v.x() == max.v.x() {
+    //三个点在同一条水平线或竖直线上
+    if (min.v.y() == mid.v.y() && min.v.y() == max.v.y()) || (min.v.x() == mid.v.x() && min.v.x() == max.v.x()) {
         return traps;
     }
 
     if min.v.y() == mid.v.y() {
-        let mut t: Trapezoid = Trapezoid::default();
-        t.t = min.v.y();
-        t.b = max.v.y();
+        let mut t = Trapezoid { t: min.v.y(), b: max.v.y(), ..Default::default() };
         if min.v.x() < mid.v.x() {
             t.l1 = Some(min);
             t.l2 = Some(max);
@@ -93,9 +78,7 @@ fn trapezoid_init<'a>(p0: &'a Vertex, p1: &'a Vertex, p2: &'a Vertex) -> Vec<Tra
     }
 
     if max.v.y() == mid.v.y() {
-        let mut t: Trapezoid = Trapezoid::default();
-        t.t = min.v.y();
-        t.b = max.v.y();
+        let mut t = Trapezoid { t: min.v.y(), b: max.v.y(), ..Default::default() };
         if max.v.x() < mid.v.x() {
             t.l1 = Some(min);
             t.l2 = Some(max);
@@ -153,7 +136,7 @@ fn trapezoid_init<'a>(p0: &'a Vertex, p1: &'a Vertex, p2: &'a Vertex) -> Vec<Tra
     traps.push(t1);
     traps.push(t2);
 
-    return traps;
+    traps
 }
 
 fn trapezoid_interpation(trap: &mut Trapezoid, y: f32) {
@@ -163,8 +146,8 @@ fn trapezoid_interpation(trap: &mut Trapezoid, y: f32) {
     let y1 = clamp((y - trap.l1.unwrap().v.y()) / s1, 0.0, 1.0);
     let y2 = clamp((y - trap.r1.unwrap().v.y()) / s2, 0.0, 1.0);
 
-    trap.l = Some(vertex_interp(&trap.l1.unwrap(), &trap.l2.unwrap(), y1));
-    trap.r = Some(vertex_interp(&trap.r1.unwrap(), &trap.r2.unwrap(), y2));
+    trap.l = Some(vertex_interp(trap.l1.unwrap(), trap.l2.unwrap(), y1));
+    trap.r = Some(vertex_interp(trap.r1.unwrap(), trap.r2.unwrap(), y2));
 }
 
 fn trapezoid_get_step(trap: &Trapezoid) -> Vertex {
@@ -201,7 +184,15 @@ fn trapezoid_get_step(trap: &Trapezoid) -> Vertex {
             (r.normal.y() - l.normal.y()) * w, 
             (r.normal.z() - l.normal.z()) * w, 
         ),
+        tangent: Vector4f::new_4(
+            (r.tangent.x() - l.tangent.x()) * w, 
+            (r.tangent.y() - l.tangent.y()) * w, 
+            (r.tangent.z() - l.tangent.z()) * w, 
+            (r.tangent.w() - l.tangent.w()) * w),
         rhw: (r.rhw - l.rhw) * w, 
+        varyings: r.varyings.iter().zip(l.varyings.iter())
+            .map(|(r, l)| (r - l) * w)
+            .collect(),
     }
 }
 
@@ -211,111 +202,93 @@ fn trapezoid_init_scanline(trap: &Trapezoid, y: i32) -> Scanline {
 
     Scanline { 
         step: trapezoid_get_step(trap),
-        x: x,
-        y: y,
-        w: w
+        x,
+        y,
+        w
     }
 }
 
-fn trapezoid_draw_scanline(image: &mut Vec<u8>, rasterizer: &Rasterizer, render: &RenderType, width: i32, zbuf: &mut Vec<f32>, trap: &Trapezoid, scanline: &Scanline, textures: &Vec<Texture>, triangle: &Triangle) {
-    let start = trap.l.as_ref().unwrap();
-    match render {
-        RenderType::COLOR => {
-            let mut r = start.color.r();
-            let mut g = start.color.g();
-            let mut b = start.color.b();
-            let mut z = start.v.z();
-        
-            
-            for i in 0..scanline.w {
-                if scanline.x + i < 0 || scanline.x + i >= width {continue;}
-                let index = width * scanline.y + scanline.x + i;
-                if z >= zbuf[index as usize] {
-                    zbuf[index as usize] = z;
-                    let cur_r = clamp((255.0 * r) as i32, 0, 255);
-                    let cur_g = clamp((255.0 * g) as i32, 0, 255);
-                    let cur_b = clamp((255.0 * b) as i32, 0, 255);
+/// rhw和varyings * rhw在屏幕上是线性的，求它们沿y方向每个像素的增量
+fn gradient_y(vertexs: &[Vertex]) -> Vertex {
+    let (p0, p1, p2) = (&vertexs[0], &vertexs[1], &vertexs[2]);
+    let (dx1, dy1) = (p1.v.x() - p0.v.x(), p1.v.y() - p0.v.y());
+    let (dx2, dy2) = (p2.v.x() - p0.v.x(), p2.v.y() - p0.v.y());
+    let area = dx1 * dy2 - dx2 * dy1;
+    let grad = |a0: f32, a1: f32, a2: f32| (dx1 * (a2 - a0) - dx2 * (a1 - a0)) / area;
 
-            
-                    image[(index * 4) as usize] = cur_b as u8;
-                    image[(index * 4 + 1) as usize] = cur_g as u8;
-                    image[(index * 4 + 2) as usize] = cur_r as u8;
-                    image[(index * 4 + 3) as usize] = 255;
-                }
-        
-                r += scanline.step.color.r();
-                g += scanline.step.color.g();
-                b += scanline.step.color.b();
-                z += scanline.step.v.z();
-            }
-        }
-        RenderType::TEXTURE => {
-            let mut u = start.tex_coords.u();
-            let mut v = start.tex_coords.v();
-            let mut normal_x = start.normal.x();
-            let mut normal_y = start.normal.y();
-            let mut normal_z = start.normal.z();
-
-            let mut x = start.tv.x();
-            let mut y = start.tv.y();
-            let mut z = start.tv.z();
-            for i in 0..scanline.w {
-                if scanline.x + i < 0 || scanline.x + i >= width {
-                    continue;
+    Vertex {
+        rhw: grad(p0.rhw, p1.rhw, p2.rhw),
+        varyings: p0.varyings.iter().zip(p1.varyings.iter()).zip(p2.varyings.iter())
+            .map(|((a0, a1), a2)| grad(*a0, *a1, *a2))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn trapezoid_draw_scanline(framebuffer: &mut Framebuffer, rasterizer: &Rasterizer, uniforms: &Uniforms, render: &RenderType, trap: &Trapezoid, scanline: &Scanline, step_y: &Vertex) {
+    let width = framebuffer.width();
+    let start = trap.l.as_ref().unwrap();
+    let shader = rasterizer.get_fragment_shader(render, uniforms.material);
+    let depth_state = rasterizer.get_depth_state();
+    let stencil_state = rasterizer.get_stencil_state();
+    let blend_state = rasterizer.get_blend_state();
+
+    //varyings已经预先乘过rhw，逐像素除回去得到透视校正的结果
+    let mut z = start.v.z();
+    let mut rhw = start.rhw;
+    let mut varyings = start.varyings.clone();
+    let mut current = vec![0.0; varyings.len()];
+    let mut ddx = vec![0.0; varyings.len()];
+    let mut ddy = vec![0.0; varyings.len()];
+
+    for i in 0..scanline.w {
+        if scanline.x + i >= 0 && scanline.x + i < width {
+            let index = (width * scanline.y + scanline.x + i) as usize;
+            if framebuffer.depth_stencil_test(index, z, depth_state, stencil_state) {
+                for (c, v) in current.iter_mut().zip(varyings.iter()) {
+                    *c = v / rhw;
                 }
-                let index = width * scanline.y + scanline.x + i;
-                if z >= zbuf[index as usize] {
-                    zbuf[index as usize] = z;
-                    {
-                        let (mut r, mut g, mut b) = textures[0].get_color(u, 1.0-v);
-
-                        if rasterizer.get_lights().len() > 0 {
-                            let result = compute_light(
-                                &Vector3f::new_3(x, y, z), 
-                                &Vector3f::new_3(normal_x, normal_y, normal_z), 
-                                rasterizer.get_lights(),
-                                &Vector3f::new_3(0.005, 0.005, 0.005),
-                                &Vector3f::new_3(r as f32  / 255.0, g as f32 / 255.0, b as f32 / 255.0),
-                                &Vector3f::new_3(0.7937, 0.7937, 0.7937),
-                                rasterizer.get_eye_pos());
-
-                            r = (result.r() * 255.0) as u8;
-                            g = (result.g() * 255.0) as u8;
-                            b = (result.b() * 255.0) as u8;
-                        }
 
+                //右边和下边相邻像素的值减去当前值
+                let (rhw_x, rhw_y) = (rhw + scanline.step.rhw, rhw + step_y.rhw);
+                let steps = scanline.step.varyings.iter().zip(step_y.varyings.iter());
+                for ((((dx, dy), v), c), (sx, sy)) in ddx.iter_mut().zip(ddy.iter_mut()).zip(varyings.iter()).zip(current.iter()).zip(steps) {
+                    *dx = (v + sx) / rhw_x - c;
+                    *dy = (v + sy) / rhw_y - c;
+                }
 
-                        image[(index * 4) as usize] = b;
-                        image[(index * 4 + 1) as usize] = g;
-                        image[(index * 4 + 2) as usize] = r;
-                        image[(index * 4 + 3) as usize] = 255;
+                let fragment = Fragment { x: scanline.x + i, y: scanline.y, z, varyings: &current, ddx: &ddx, ddy: &ddy };
+                match shader.shade(&fragment, uniforms) {
+                    Some(mut color) if !blend_state.discard(&color) => {
+                        let coverage = blend_state.resolve_coverage(&mut color, 1.0);
+                        if coverage > 0.0 {
+                            framebuffer.update_stencil(index, stencil_state, stencil_state.pass);
+                            framebuffer.update_depth(index, z, depth_state);
+                            framebuffer.blend_color(index, &color, coverage, blend_state);
+                        }
                     }
+                    _ => {}
                 }
-        
-                u += scanline.step.tex_coords.u();
-                v += scanline.step.tex_coords.v();
-                x += scanline.step.tv.x();
-                y += scanline.step.tv.y();
-                z += scanline.step.tv.z();
-                normal_x += scanline.step.normal.x();
-                normal_y += scanline.step.normal.y();
-                normal_z += scanline.step.normal.z();
-                z += scanline.step.v.z();
             }
         }
+
+        z += scanline.step.v.z();
+        rhw += scanline.step.rhw;
+        for (v, step) in varyings.iter_mut().zip(scanline.step.varyings.iter()) {
+            *v += step;
+        }
     }
 
 }
 
-fn trapezoid_draw(image: &mut Vec<u8>, 
+fn trapezoid_draw(framebuffer: &mut Framebuffer, 
     rasterizer: &Rasterizer,
+    uniforms: &Uniforms,
     rendertype: &RenderType,
-    width: i32, 
-    height: i32, 
-    zbuf: &mut Vec<f32>, 
-    trap: &mut Trapezoid, 
-    textures: &Vec<Texture>,
-    triangle: &Triangle) {
+    trap: &mut Trapezoid,
+    step_y: &Vertex) {
+    let height = framebuffer.height();
     let t = trap.t.floor() as i32;
     let b = trap.b.floor() as i32;
 
@@ -338,24 +311,32 @@ fn trapezoid_draw(image: &mut Vec<u8>,
                 // println!("{:?}", traps[0].r2.unwrap().v);
 
             }
-            trapezoid_draw_scanline(image, rasterizer, rendertype, width, zbuf, trap, &scanline, textures, triangle);
+            trapezoid_draw_scanline(framebuffer, rasterizer, uniforms, rendertype, trap, &scanline, step_y);
         }
     }
 }
 
-pub fn draw_trangle_edge_walking(image: &mut Vec<u8>, 
+pub fn draw_trangle_edge_walking(framebuffer: &mut Framebuffer, 
     rasterizer: &Rasterizer,
-    zbuf: &mut Vec<f32>, 
-    width: i32, height: i32, 
     triangle: &Triangle, textures: &Vec<Texture>) {
-    let mut traps = trapezoid_init(&triangle.vertexs[0], &triangle.vertexs[1], &triangle.vertexs[2]);
-    if traps.len() >= 1 {
+    let uniforms = rasterizer.uniforms(textures, triangle.material);
+
+    //沿边和扫描线线性步进的是varyings * rhw
+    let mut vertexs = triangle.vertexs.clone();
+    for v in vertexs.iter_mut() {
+        let rhw = v.rhw;
+        v.varyings.iter_mut().for_each(|x| *x *= rhw);
+    }
+
+    let step_y = gradient_y(&vertexs);
+    let mut traps = trapezoid_init(&vertexs[0], &vertexs[1], &vertexs[2]);
+    if !traps.is_empty() {
         let trap = &mut traps[0];
-        trapezoid_draw(image, rasterizer, &triangle.render, width, height, zbuf, trap, textures, triangle);
+        trapezoid_draw(framebuffer, rasterizer, &uniforms, &triangle.render, trap, &step_y);
     }
 
     if traps.len() >= 2 {
         let trap = &mut traps[1];
-        trapezoid_draw(image, rasterizer, &triangle.render, width, height, zbuf, trap, textures, triangle);
+        trapezoid_draw(framebuffer, rasterizer, &uniforms, &triangle.render, trap, &step_y);
     }
 }
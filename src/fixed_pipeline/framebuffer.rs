@@ -0,0 +1,325 @@
+use crate::math::utils::clamp;
+use crate::math::vector::Vector4f;
+
+use super::blend::BlendState;
+use super::depth::DepthState;
+use super::stencil::{StencilOp, StencilState};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+
+impl CompareFunc {
+    /// value和缓冲中已有的stored比较
+    pub fn test<T: PartialOrd>(&self, value: T, stored: T) -> bool {
+        match self {
+            CompareFunc::Never => false,
+            CompareFunc::Less => value < stored,
+            CompareFunc::LessEqual => value <= stored,
+            CompareFunc::Equal => value == stored,
+            CompareFunc::NotEqual => value != stored,
+            CompareFunc::GreaterEqual => value >= stored,
+            CompareFunc::Greater => value > stored,
+            CompareFunc::Always => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorFormat {
+    Rgba8,
+    Rgba32F,
+}
+
+pub enum ColorData {
+    Rgba8(Vec<u8>),
+    Rgba32F(Vec<f32>),
+}
+
+pub struct ColorAttachment {
+    pub data: ColorData,
+    pub clear_value: Vector4f,
+}
+
+impl ColorAttachment {
+    pub fn new(format: ColorFormat, size: usize) -> ColorAttachment {
+        let data = match format {
+            ColorFormat::Rgba8 => ColorData::Rgba8(vec![0; size * 4]),
+            ColorFormat::Rgba32F => ColorData::Rgba32F(vec![0.0; size * 4]),
+        };
+
+        ColorAttachment {
+            data,
+            clear_value: Vector4f::new_4(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn format(&self) -> ColorFormat {
+        match self.data {
+            ColorData::Rgba8(_) => ColorFormat::Rgba8,
+            ColorData::Rgba32F(_) => ColorFormat::Rgba32F,
+        }
+    }
+
+    pub fn read(&self, idx: usize) -> Vector4f {
+        match &self.data {
+            ColorData::Rgba8(d) => Vector4f::new_4(
+                d[idx * 4] as f32 / 255.0,
+                d[idx * 4 + 1] as f32 / 255.0,
+                d[idx * 4 + 2] as f32 / 255.0,
+                d[idx * 4 + 3] as f32 / 255.0,
+            ),
+            ColorData::Rgba32F(d) => Vector4f::new_4(d[idx * 4], d[idx * 4 + 1], d[idx * 4 + 2], d[idx * 4 + 3]),
+        }
+    }
+
+    pub fn write(&mut self, idx: usize, color: &Vector4f) {
+        match &mut self.data {
+            ColorData::Rgba8(d) => {
+                for i in 0..4 {
+                    d[idx * 4 + i] = clamp((color.v[i] * 255.0) as i32, 0, 255) as u8;
+                }
+            }
+            ColorData::Rgba32F(d) => {
+                d[idx * 4..idx * 4 + 4].copy_from_slice(&color.v[0..4]);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let size = match &self.data {
+            ColorData::Rgba8(d) => d.len() / 4,
+            ColorData::Rgba32F(d) => d.len() / 4,
+        };
+        let c = self.clear_value;
+        for idx in 0..size {
+            self.write(idx, &c);
+        }
+    }
+}
+
+pub struct DepthAttachment {
+    pub data: Vec<f32>,
+    pub clear_value: f32,
+}
+
+impl DepthAttachment {
+    pub fn clear(&mut self) {
+        let v = self.clear_value;
+        self.data.iter_mut().for_each(|d| *d = v);
+    }
+}
+
+pub struct StencilAttachment {
+    pub data: Vec<u8>,
+    pub clear_value: u8,
+}
+
+impl StencilAttachment {
+    pub fn clear(&mut self) {
+        let v = self.clear_value;
+        self.data.iter_mut().for_each(|d| *d = v);
+    }
+}
+
+/// 渲染目标：若干颜色附件 + 深度附件 + 可选的8位模板附件
+/// 深度比较方式由Rasterizer的DepthState决定，深度清除值默认是非reversed_z的far_value
+pub struct Framebuffer {
+    width: i32,
+    height: i32,
+    colors: Vec<ColorAttachment>,
+    depth: DepthAttachment,
+    stencil: Option<StencilAttachment>,
+}
+
+impl Framebuffer {
+    /// 一个RGBA8颜色附件和一个深度附件
+    pub fn new(width: i32, height: i32) -> Framebuffer {
+        let mut fb = Framebuffer::new_depth_only(width, height);
+        fb.add_color_attachment(ColorFormat::Rgba8);
+        fb
+    }
+
+    /// 一个RGBA32F颜色附件和一个深度附件，颜色不会被截断，输出前需要色调映射
+    pub fn new_hdr(width: i32, height: i32) -> Framebuffer {
+        let mut fb = Framebuffer::new_depth_only(width, height);
+        fb.add_color_attachment(ColorFormat::Rgba32F);
+        fb
+    }
+
+    /// 深度按默认的DepthState清除，reversed_z时Renderer会在绘制前换算
+    pub fn new_depth_only(width: i32, height: i32) -> Framebuffer {
+        let size = (width * height) as usize;
+        let far = DepthState::new().far_value();
+        Framebuffer {
+            width,
+            height,
+            colors: Vec::new(),
+            depth: DepthAttachment {
+                data: vec![far; size],
+                clear_value: far,
+            },
+            stencil: None,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// 返回附件的下标
+    pub fn add_color_attachment(&mut self, format: ColorFormat) -> usize {
+        let size = (self.width * self.height) as usize;
+        self.colors.push(ColorAttachment::new(format, size));
+        self.colors.len() - 1
+    }
+
+    pub fn enable_stencil(&mut self, clear_value: u8) {
+        let size = (self.width * self.height) as usize;
+        self.stencil = Some(StencilAttachment {
+            data: vec![clear_value; size],
+            clear_value,
+        });
+    }
+
+    pub fn set_color_clear_value(&mut self, attachment: usize, color: Vector4f) {
+        self.colors[attachment].clear_value = color;
+    }
+
+    pub fn set_depth_clear_value(&mut self, v: f32) {
+        self.depth.clear_value = v;
+    }
+
+    /// 深度缓冲填充为v，不改变清除值
+    pub fn fill_depth(&mut self, v: f32) {
+        self.depth.data.iter_mut().for_each(|d| *d = v);
+    }
+
+    pub fn clear(&mut self) {
+        for c in self.colors.iter_mut() {
+            c.clear();
+        }
+        self.depth.clear();
+        if let Some(s) = self.stencil.as_mut() {
+            s.clear();
+        }
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn get_color_attachment(&self, attachment: usize) -> &ColorAttachment {
+        &self.colors[attachment]
+    }
+
+    pub fn get_depth_attachment(&self) -> &DepthAttachment {
+        &self.depth
+    }
+
+    pub fn get_stencil_attachment(&self) -> Option<&StencilAttachment> {
+        self.stencil.as_ref()
+    }
+
+    pub fn get_stencil_attachment_mut(&mut self) -> Option<&mut StencilAttachment> {
+        self.stencil.as_mut()
+    }
+
+    pub fn depth_test(&self, idx: usize, z: f32, state: &DepthState) -> bool {
+        state.test(z, self.depth.data[idx])
+    }
+
+    /// 没有模板附件或者没有启用时总是通过
+    pub fn stencil_test(&self, idx: usize, state: &StencilState) -> bool {
+        match self.stencil.as_ref() {
+            Some(s) if state.enabled => state.test(s.data[idx]),
+            _ => true,
+        }
+    }
+
+    pub fn update_stencil(&mut self, idx: usize, state: &StencilState, op: StencilOp) {
+        if let Some(s) = self.stencil.as_mut().filter(|_| state.enabled) {
+            s.data[idx] = state.update(s.data[idx], op);
+        }
+    }
+
+    /// 先模板测试再深度测试，失败时按fail或depth_fail更新模板值
+    /// 通过后片元还可能被丢弃，写入颜色时再用update_stencil执行pass
+    pub fn depth_stencil_test(&mut self, idx: usize, z: f32, depth: &DepthState, stencil: &StencilState) -> bool {
+        if !self.stencil_test(idx, stencil) {
+            self.update_stencil(idx, stencil, stencil.fail);
+            return false;
+        }
+        if !self.depth_test(idx, z, depth) {
+            self.update_stencil(idx, stencil, stencil.depth_fail);
+            return false;
+        }
+        true
+    }
+
+    pub fn read_stencil(&self, idx: usize) -> Option<u8> {
+        self.stencil.as_ref().map(|s| s.data[idx])
+    }
+
+    pub fn read_depth(&self, idx: usize) -> f32 {
+        self.depth.data[idx]
+    }
+
+    pub fn write_depth(&mut self, idx: usize, z: f32) {
+        self.depth.data[idx] = z;
+    }
+
+    /// 通过深度测试后写入深度(受DepthState.write控制)
+    pub fn update_depth(&mut self, idx: usize, z: f32, state: &DepthState) {
+        if state.write {
+            self.depth.data[idx] = z;
+        }
+    }
+
+    pub fn read_color(&self, attachment: usize, idx: usize) -> Vector4f {
+        self.colors[attachment].read(idx)
+    }
+
+    /// 片元颜色写入所有颜色附件
+    pub fn write_color(&mut self, idx: usize, color: &Vector4f) {
+        for c in self.colors.iter_mut() {
+            c.write(idx, color);
+        }
+    }
+
+    pub fn write_color_attachment(&mut self, attachment: usize, idx: usize, color: &Vector4f) {
+        self.colors[attachment].write(idx, color);
+    }
+
+    /// 混合后写入所有颜色附件，coverage是msaa的覆盖率(0~1)，按覆盖率和原来的颜色插值rgb
+    pub fn blend_color(&mut self, idx: usize, color: &Vector4f, coverage: f32, blend: &BlendState) {
+        for c in self.colors.iter_mut() {
+            let dst = c.read(idx);
+            let mut out = blend.blend(color, &dst);
+            for i in 0..3 {
+                out.v[i] = dst.v[i] + (out.v[i] - dst.v[i]) * coverage;
+            }
+            c.write(idx, &out);
+        }
+    }
+
+    /// 转换成RGBA8字节，浮点附件直接截断到0~1
+    pub fn to_rgba8(&self, attachment: usize) -> Vec<u8> {
+        match &self.colors[attachment].data {
+            ColorData::Rgba8(d) => d.clone(),
+            ColorData::Rgba32F(d) => d.iter().map(|c| clamp((c * 255.0) as i32, 0, 255) as u8).collect(),
+        }
+    }
+}
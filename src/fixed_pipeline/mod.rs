@@ -1,3 +1,14 @@
 pub mod rasterizer;
 pub mod edge_walking;
-pub mod edge_equation;
\ No newline at end of file
+pub mod edge_equation;
+pub mod clipping;
+pub mod culling;
+pub mod renderer;
+pub mod framebuffer;
+pub mod depth;
+pub mod stencil;
+pub mod blend;
+pub mod oit;
+pub mod shadow;
+pub mod skybox;
+pub mod tonemap;
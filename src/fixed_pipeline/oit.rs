@@ -0,0 +1,67 @@
+use crate::math::vector::Vector4f;
+
+use super::framebuffer::Framebuffer;
+
+/// Weighted Blended Order-Independent Transparency (McGuire & Bavoil 2013)
+/// 半透明片元不需要排序，先累加到accum和revealage，最后一次性合成到Framebuffer
+pub struct OitBuffer {
+    accum: Vec<f32>,
+    revealage: Vec<f32>,
+}
+
+impl OitBuffer {
+    pub fn new(width: i32, height: i32) -> OitBuffer {
+        let size = (width * height) as usize;
+        OitBuffer {
+            accum: vec![0.0; size * 4],
+            revealage: vec![1.0; size],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.accum.iter_mut().for_each(|a| *a = 0.0);
+        self.revealage.iter_mut().for_each(|r| *r = 1.0);
+    }
+
+    /// depth是0(near)到1(far)的深度
+    pub fn weight(alpha: f32, depth: f32) -> f32 {
+        let d = 1.0 - depth.clamp(0.0, 1.0);
+        alpha * (3e3 * d * d * d).clamp(1e-2, 3e3)
+    }
+
+    pub fn accumulate(&mut self, idx: usize, color: &Vector4f, coverage: f32, depth: f32) {
+        let a = color.w() * coverage;
+        if a <= 0.0 {
+            return;
+        }
+
+        let w = OitBuffer::weight(a, depth);
+        for i in 0..3 {
+            self.accum[idx * 4 + i] += color.v[i] * a * w;
+        }
+        self.accum[idx * 4 + 3] += a * w;
+        self.revealage[idx] *= 1.0 - a;
+    }
+
+    /// 按 dst * revealage + avg * (1 - revealage) 合成到所有颜色附件
+    pub fn composite(&self, framebuffer: &mut Framebuffer) {
+        for idx in 0..self.revealage.len() {
+            let revealage = self.revealage[idx];
+            if revealage >= 1.0 {
+                continue;
+            }
+
+            let weight = self.accum[idx * 4 + 3].max(1e-5);
+            for a in 0..framebuffer.color_attachment_count() {
+                let dst = framebuffer.read_color(a, idx);
+                let mut out = dst;
+                for i in 0..3 {
+                    let avg = self.accum[idx * 4 + i] / weight;
+                    out.v[i] = dst.v[i] * revealage + avg * (1.0 - revealage);
+                }
+                out.v[3] = 1.0 - (1.0 - dst.w()) * revealage;
+                framebuffer.write_color_attachment(a, idx, &out);
+            }
+        }
+    }
+}
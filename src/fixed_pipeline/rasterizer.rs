@@ -1,21 +1,45 @@
+use std::sync::Arc;
 
-use crate::{math::{matrix::Mat4x4f, vector::{Vector4f, Vector3f}}, common::texture::Texture};
-use crate::common::triangle::Triangle;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+
+use crate::{math::{matrix::{Mat4x4f, SingularMatrixError}, vector::{Vector4f, Vector3f}}, common::texture::Texture};
+use crate::math::transform::get_rotation_y_matrix;
+use crate::common::mesh::Mesh;
+use crate::common::triangle::{RenderType, Triangle, Vertex};
+use crate::shader::builtin::{BlinnPhongShader, DefaultVertexShader, PbrShader, UnlitShader, VertexColorShader};
+use crate::shader::program::{FragmentShader, Uniforms, VertexShader};
+use crate::common::environment::Environment;
 use crate::common::light::Light;
+use crate::common::skybox::Skybox;
+use crate::common::material::{Material, ShadingModel, TextureSlot};
 
-use super::{edge_walking::draw_trangle_edge_walking, edge_equation::{draw_trangle_edge_equation, draw_trangle_edge_equation_result}};
+use super::edge_equation::{draw_trangle_edge_equation, draw_trangle_edge_equation_result};
+use super::clipping::clip_triangle;
+use super::blend::BlendState;
+use super::depth::{get_reversed_z_matrix, DepthState};
+use super::stencil::StencilState;
+use super::framebuffer::Framebuffer;
+use super::culling::{should_cull, CullMode, FrontFace};
+use super::shadow::{render_cube_shadow_map, render_shadow_map};
 
 pub struct RenderResult {
     pub idx: i32,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+    pub color: Vector4f,
+    /// msaa覆盖率，合并时按它和目标颜色混合
+    pub coverage: f32,
     pub z: f32
 }
 
 impl RenderResult {
     pub fn new() -> RenderResult {
-        RenderResult { idx: 0, r: 0, g: 0, b: 0, z: 0.0 }
+        RenderResult { idx: 0, color: Vector4f::new_4(0.0, 0.0, 0.0, 1.0), coverage: 1.0, z: 0.0 }
+    }
+}
+
+impl Default for RenderResult {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -25,8 +49,22 @@ pub struct Rasterizer {
     projection: Mat4x4f,
     mvp: Mat4x4f,
     mv: Mat4x4f,
+    normal_matrix: Mat4x4f,
+    view_inverse: Mat4x4f,
     lights: Vec<Light>,
-    eye_pos: Vector3f
+    ambient_light: Vector3f,
+    environment: Option<Arc<Environment>>,
+    skybox: Option<Arc<Skybox>>,
+    materials: Vec<Material>,
+    default_material: Material,
+    eye_pos: Vector3f,
+    cull_mode: CullMode,
+    front_face: FrontFace,
+    vertex_shader: Box<dyn VertexShader>,
+    fragment_shader: Option<Box<dyn FragmentShader>>,
+    depth_state: DepthState,
+    stencil_state: StencilState,
+    blend_state: BlendState
 }
 
 impl Rasterizer {
@@ -37,8 +75,22 @@ impl Rasterizer {
             mv: Mat4x4f::identity(),
             projection: Mat4x4f::identity(),
             mvp: Mat4x4f::identity(),
+            normal_matrix: Mat4x4f::identity(),
+            view_inverse: Mat4x4f::identity(),
             lights: Vec::new(),
-            eye_pos: Vector3f::new()
+            ambient_light: Vector3f::new_3(10.0, 10.0, 10.0),
+            environment: None,
+            skybox: None,
+            materials: Vec::new(),
+            default_material: Material::new("default"),
+            eye_pos: Vector3f::new(),
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            vertex_shader: Box::new(DefaultVertexShader),
+            fragment_shader: None,
+            depth_state: DepthState::new(),
+            stencil_state: StencilState::new(),
+            blend_state: BlendState::new()
         }
     }
 
@@ -58,22 +110,282 @@ impl Rasterizer {
         self.lights = lights;
     }
 
+    /// 为开启阴影的光源重新渲染阴影贴图，场景或光源变化后需要调用
+    pub fn update_shadow_maps(&mut self, triangles: &mut [Triangle]) {
+        let mv = self.mv;
+        for light in self.lights.iter_mut() {
+            light.shadow_map = render_shadow_map(light, &mv, triangles);
+            light.cube_shadow_map = render_cube_shadow_map(light, &mv, triangles);
+        }
+    }
+
+    pub fn set_ambient_light(&mut self, intensity: Vector3f) {
+        self.ambient_light = intensity;
+    }
+
+    /// 设置后用环境贴图代替ambient_light作为环境光，预计算比较慢，用Arc在多个Rasterizer间共享
+    pub fn set_environment(&mut self, environment: Option<Arc<Environment>>) {
+        self.environment = environment;
+    }
+
+    /// Renderer::draw_skybox使用的背景
+    pub fn set_skybox(&mut self, skybox: Option<Arc<Skybox>>) {
+        self.skybox = skybox;
+    }
+
+    /// Triangle.material是这个列表的下标
+    pub fn set_materials(&mut self, materials: Vec<Material>) {
+        self.materials = materials;
+    }
+
+    /// 没有设置材质的三角形使用的材质
+    pub fn set_default_material(&mut self, material: Material) {
+        self.default_material = material;
+    }
+
     pub fn set_projection(&mut self, m: Mat4x4f) {
         self.projection = m;
     }
 
+    pub fn set_cull_mode(&mut self, mode: CullMode) {
+        self.cull_mode = mode;
+    }
+
+    pub fn set_front_face(&mut self, front_face: FrontFace) {
+        self.front_face = front_face;
+    }
+
+    pub fn set_vertex_shader<S: VertexShader + 'static>(&mut self, shader: S) {
+        self.vertex_shader = Box::new(shader);
+    }
+
+    /// 设置后所有三角形都使用这个片元着色器，否则按RenderType使用内置着色器
+    pub fn set_fragment_shader<S: FragmentShader + 'static>(&mut self, shader: S) {
+        self.fragment_shader = Some(Box::new(shader));
+    }
+
+    pub fn reset_fragment_shader(&mut self) {
+        self.fragment_shader = None;
+    }
+
+    /// 修改reversed_z后需要重新compute_mvp
+    pub fn set_depth_state(&mut self, state: DepthState) {
+        self.depth_state = state;
+    }
+
+    pub fn set_stencil_state(&mut self, state: StencilState) {
+        self.stencil_state = state;
+    }
+
+    pub fn set_blend_state(&mut self, state: BlendState) {
+        self.blend_state = state;
+    }
+
     pub fn compute_mvp(&mut self) {
         self.mvp = self.projection.mul(&self.view).mul(&self.model);
+        if self.depth_state.reversed_z {
+            self.mvp = get_reversed_z_matrix().mul(&self.mvp);
+        }
         self.mv = self.view.mul(&self.model);
+        //法线用model-view的逆转置变换，非均匀缩放后仍然和表面垂直；奇异矩阵时不变换
+        self.normal_matrix = self.mv.inverse().map(|m| m.transpose()).unwrap_or_else(|_| Mat4x4f::identity());
+        self.view_inverse = self.view.inverse().unwrap_or_else(|_| Mat4x4f::identity());
     }
 
     pub fn get_lights(&self) -> &Vec<Light> {
         &self.lights
     }
 
+    pub fn get_ambient_light(&self) -> &Vector3f {
+        &self.ambient_light
+    }
+
+    pub fn get_environment(&self) -> Option<&Environment> {
+        self.environment.as_deref()
+    }
+
+    pub fn get_skybox(&self) -> Option<&Arc<Skybox>> {
+        self.skybox.as_ref()
+    }
+
+    pub fn get_view(&self) -> &Mat4x4f {
+        &self.view
+    }
+
+    pub fn get_projection(&self) -> &Mat4x4f {
+        &self.projection
+    }
+
+    pub fn get_normal_matrix(&self) -> &Mat4x4f {
+        &self.normal_matrix
+    }
+
+    /// 屏幕坐标还原成世界空间的点，z是深度缓冲里的值，用于拾取
+    pub fn unproject(&self, x: f32, y: f32, z: f32, width: i32, height: i32) -> Result<Vector3f, SingularMatrixError> {
+        let mut vp = self.projection.mul(&self.view);
+        if self.depth_state.reversed_z {
+            vp = get_reversed_z_matrix().mul(&vp);
+        }
+        let inv = get_view_port(width as f32, height as f32).mul(&vp).inverse()?;
+        let p = inv.apply(&Vector4f::new_4(x, y, z, 1.0));
+        Ok(Vector3f::new_3(p.x() / p.w(), p.y() / p.w(), p.z() / p.w()))
+    }
+
+    pub fn get_materials(&self) -> &Vec<Material> {
+        &self.materials
+    }
+
+    pub fn get_material(&self, material: Option<usize>) -> &Material {
+        material.and_then(|i| self.materials.get(i)).unwrap_or(&self.default_material)
+    }
+
     pub fn get_eye_pos(&self) -> &Vector3f {
         &self.eye_pos
     }
+
+    /// eye_pos变换到相机空间，view是看向eye_pos的视图矩阵时就是原点
+    pub fn get_view_eye_pos(&self) -> Vector3f {
+        let e = &self.eye_pos;
+        let p = self.view.apply(&Vector4f::new_4(e.x(), e.y(), e.z(), 1.0));
+        Vector3f::new_3(p.x(), p.y(), p.z())
+    }
+
+    pub fn get_depth_state(&self) -> &DepthState {
+        &self.depth_state
+    }
+
+    pub fn get_stencil_state(&self) -> &StencilState {
+        &self.stencil_state
+    }
+
+    pub fn get_blend_state(&self) -> &BlendState {
+        &self.blend_state
+    }
+
+    pub fn get_vertex_shader(&self) -> &dyn VertexShader {
+        self.vertex_shader.as_ref()
+    }
+
+    /// 没有自定义shader时，TEXTURE按材质的shading选择Blinn-Phong、PBR或不计算光照
+    pub fn get_fragment_shader(&self, render: &RenderType, material: &Material) -> &dyn FragmentShader {
+        match &self.fragment_shader {
+            Some(shader) => shader.as_ref(),
+            None => match (render, material.shading) {
+                (RenderType::COLOR, _) => &VertexColorShader,
+                (RenderType::TEXTURE, ShadingModel::BlinnPhong) => &BlinnPhongShader,
+                (RenderType::TEXTURE, ShadingModel::Pbr) => &PbrShader,
+                (RenderType::TEXTURE, ShadingModel::Unlit) => &UnlitShader,
+            },
+        }
+    }
+
+    /// 材质绑定的纹理在这里按id查找一次，采样时直接按槽位取
+    pub fn uniforms<'a>(&'a self, textures: &'a Vec<Texture>, material: Option<usize>) -> Uniforms<'a> {
+        let material = self.get_material(material);
+        let bindings = TextureSlot::ALL.map(|slot| {
+            let binding = material.get_texture(slot)?;
+            let texture = textures.iter().find(|t| t.get_id() == binding.texture)?;
+            Some((texture, &binding.sampler))
+        });
+        Uniforms {
+            model: &self.model,
+            view: &self.view,
+            projection: &self.projection,
+            mv: &self.mv,
+            mvp: &self.mvp,
+            normal_matrix: &self.normal_matrix,
+            view_inverse: &self.view_inverse,
+            lights: &self.lights,
+            ambient_light: &self.ambient_light,
+            environment: self.environment.as_deref(),
+            eye_pos: &self.eye_pos,
+            view_eye_pos: self.get_view_eye_pos(),
+            textures,
+            material,
+            bindings,
+        }
+    }
+}
+
+impl Default for Rasterizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 变换到裁剪空间并做视锥裁剪，返回已经做完视口变换和透视除法、并且通过背面剔除的三角形
+/// 不修改输入的三角形
+pub fn clip_and_project(rasterizer: &Rasterizer,
+    width: i32,
+    height: i32,
+    triangle: &Triangle,
+    textures: &Vec<Texture>
+) -> Vec<Triangle> {
+    let uniforms = rasterizer.uniforms(textures, triangle.material);
+    let mut clip = triangle.clone();
+    for v in clip.vertexs.iter_mut() {
+        shade_vertex(rasterizer, &uniforms, v);
+    }
+
+    project_triangle(rasterizer, width, height, &clip)
+}
+
+/// 运行顶点着色器，tv保存相机空间坐标，v保存裁剪空间坐标
+fn shade_vertex(rasterizer: &Rasterizer, uniforms: &Uniforms, vertex: &mut Vertex) {
+    let out = rasterizer.vertex_shader.shade(vertex, uniforms);
+    vertex.tv = rasterizer.mv.apply(&vertex.origin_v);
+    vertex.v = out.position;
+    vertex.varyings = out.varyings;
+}
+
+/// 顶点已经在裁剪空间中的三角形做裁剪、视口变换和背面剔除
+fn project_triangle(rasterizer: &Rasterizer, width: i32, height: i32, clip: &Triangle) -> Vec<Triangle> {
+    let view_port = get_view_port(width as f32, height as f32);
+    let mut triangles = clip_triangle(clip);
+    for t in triangles.iter_mut() {
+        let p = t.vertexs.iter().map(|v| view_port.apply(&v.v)).collect();
+        t.set_vertexs(p);
+    }
+
+    triangles.retain(|t| !should_cull(t, rasterizer.cull_mode, rasterizer.front_face));
+    triangles
+}
+
+/// 顶点处理：网格的每个顶点只运行一次顶点着色器，结果按下标缓存供图元装配使用
+pub fn process_vertices(rasterizer: &Rasterizer, mesh: &Mesh, material: Option<usize>, textures: &Vec<Texture>) -> Vec<Vertex> {
+    let uniforms = rasterizer.uniforms(textures, material);
+    mesh.vertices
+        .par_iter()
+        .map(|v| {
+            let mut v = v.clone();
+            shade_vertex(rasterizer, &uniforms, &mut v);
+            v
+        })
+        .collect()
+}
+
+/// 用变换后的顶点装配三角形并光栅化，每个三角形的结果单独返回
+/// material覆盖网格自己的材质，场景图里的实例可以各自使用不同的材质
+pub fn draw_mesh_map(rasterizer: &Rasterizer,
+    width: i32,
+    height: i32,
+    mesh: &Mesh,
+    material: Option<usize>,
+    textures: &Vec<Texture>
+) -> Vec<Vec<RenderResult>> {
+    let processed = process_vertices(rasterizer, mesh, material, textures);
+    (0..mesh.triangle_count())
+        .into_par_iter()
+        .map(|i| {
+            let mut clip = mesh.assemble(&processed, i);
+            clip.set_material(material);
+            let mut result = Vec::new();
+            for t in project_triangle(rasterizer, width, height, &clip) {
+                result.extend(draw_trangle_edge_equation_result(rasterizer, width, height, &t, textures));
+            }
+            result
+        })
+        .collect()
 }
 
 pub fn draw_trangle_map(rasterizer: &Rasterizer, 
@@ -82,54 +394,24 @@ pub fn draw_trangle_map(rasterizer: &Rasterizer,
     triangle: &mut Triangle,
     textures: &Vec<Texture>
 ) -> Vec<RenderResult> {
-    let t1 = rasterizer.mvp.apply(&triangle.vertexs[0].origin_v);
-    let t2 = rasterizer.mvp.apply(&triangle.vertexs[1].origin_v);
-    let t3 = rasterizer.mvp.apply(&triangle.vertexs[2].origin_v);
-
-    triangle.set_tvetexs(vec![
-        rasterizer.mv.apply(&triangle.vertexs[0].origin_v),
-        rasterizer.mv.apply(&triangle.vertexs[1].origin_v),
-        rasterizer.mv.apply(&triangle.vertexs[2].origin_v),
-        ]);
-
-    let view_port = get_view_port(width as f32, height as f32);
-    let mut p1 = view_port.apply(&t1);
-    let mut p2 = view_port.apply(&t2);
-    let mut p3 = view_port.apply(&t3);
+    let mut result = Vec::new();
+    for t in clip_and_project(rasterizer, width, height, triangle, textures) {
+        result.extend(draw_trangle_edge_equation_result(rasterizer, width, height, &t, textures));
+    }
 
-    triangle.set_vertexs(vec![p1, p2, p3]);
-    return draw_trangle_edge_equation_result(rasterizer, width, height, triangle, textures);
+    result
 }
 
 pub fn draw_trangle(rasterizer: &Rasterizer, 
-    image: &mut Vec<u8>, 
-    zbuf: &mut Vec<f32>,
-    near: f32,
-    far: f32,
-    width: i32, 
-    height: i32, 
+    framebuffer: &mut Framebuffer,
     triangle: &mut Triangle,
     textures: &Vec<Texture>
 ) {
-    let t1 = rasterizer.mvp.apply(&triangle.vertexs[0].origin_v);
-    let t2 = rasterizer.mvp.apply(&triangle.vertexs[1].origin_v);
-    let t3 = rasterizer.mvp.apply(&triangle.vertexs[2].origin_v);
-
-    triangle.set_tvetexs(vec![
-        rasterizer.mv.apply(&triangle.vertexs[0].origin_v),
-        rasterizer.mv.apply(&triangle.vertexs[1].origin_v),
-        rasterizer.mv.apply(&triangle.vertexs[2].origin_v),
-        ]);
-
-    let view_port = get_view_port(width as f32, height as f32);
-    let mut p1 = view_port.apply(&t1);
-    let mut p2 = view_port.apply(&t2);
-    let mut p3 = view_port.apply(&t3);
-
-    triangle.set_vertexs(vec![p1, p2, p3]);
-
-    //draw_trangle_edge_walking(image, rasterizer, zbuf, width, height, &triangle, textures);
-    draw_trangle_edge_equation(image, rasterizer, zbuf, width, height, triangle, textures);
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    for t in clip_and_project(rasterizer, width, height, triangle, textures) {
+        //super::edge_walking::draw_trangle_edge_walking(framebuffer, rasterizer, &t, textures);
+        draw_trangle_edge_equation(framebuffer, rasterizer, &t, textures);
+    }
 }
 
 pub fn get_view_matrix(eye: Vector4f, at: Vector4f, mut up: Vector4f) -> Mat4x4f {
@@ -138,51 +420,43 @@ pub fn get_view_matrix(eye: Vector4f, at: Vector4f, mut up: Vector4f) -> Mat4x4f
     up.normlize();
     let mut x = g.cross_product(&up);
     x.normlize();
-    let m = vec![
-        vec![x.x(), x.y(), x.z(), -eye.x()],
-        vec![up.x(), up.y(), up.z(), -eye.y()],
-        vec![-g.x(), -g.y(), -g.z(), -eye.z()],
-        vec![0.0, 0.0, 0.0, 1.0]];
-    Mat4x4f::new_val(m)
+    Mat4x4f::from_array([
+        [x.x(), x.y(), x.z(), -eye.x()],
+        [up.x(), up.y(), up.z(), -eye.y()],
+        [-g.x(), -g.y(), -g.z(), -eye.z()],
+        [0.0, 0.0, 0.0, 1.0]])
 }
 
+/// 每个三角形都会调用
 pub fn get_view_port(width: f32, height: f32) -> Mat4x4f {
-    let m = vec![
-            vec![width/2.0, 0.0, 0.0, width/2.0],
-            vec![0.0, -height/2.0, 0.0, height/2.0],
-            vec![0.0, 0.0, 1.0, 0.0],
-            vec![0.0, 0.0, 0.0, 1.0],
-        ];
-    
-    Mat4x4f::new_val(m)
+    Mat4x4f::from_array([
+        [width/2.0, 0.0, 0.0, width/2.0],
+        [0.0, -height/2.0, 0.0, height/2.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
 }
 
+/// 绕y轴旋转，更多的变换见math::transform
 pub fn get_model_matrix(angel: f32) -> Mat4x4f {
-    let r = std::f32::consts::PI * angel / 180.0;
-    let m = vec![
-            vec![r.cos(), 0.0, r.sin(), 0.0],
-            vec![0.0, 1.0, 0.0, 0.0],
-            vec![-r.sin(), 0.0, r.cos(), 0.0],
-            vec![0.0, 0.0, 0.0, 1.0],
-        ];
-    Mat4x4f::new_val(m)
+    get_rotation_y_matrix(angel)
 }
 
 pub fn get_ortho_projection_matrix(l: f32, r: f32, t: f32, b: f32, n: f32, f: f32) -> Mat4x4f {
-    //映射z到(-1,0)
-    let m1 = Mat4x4f::new_val( 
-        vec![
-            vec![2.0/(r - l), 0.0, 0.0, 0.0],
-            vec![0.0, 2.0/(t - b), 0.0, 0.0],
-            vec![0.0, 0.0, 1.0/(n - f), 0.0],
-            vec![0.0, 0.0, 0.0, 1.0],
+    //映射z到(-1,0)，near对应0，far对应-1
+    let m1 = Mat4x4f::from_array( 
+        [
+            [2.0/(r - l), 0.0, 0.0, 0.0],
+            [0.0, 2.0/(t - b), 0.0, 0.0],
+            [0.0, 0.0, 1.0/(n - f), -0.5],
+            [0.0, 0.0, 0.0, 1.0],
         ]);
-    let m2 = Mat4x4f::new_val(
-        vec![
-            vec![1.0, 0.0, 0.0, -(l+r)/2.0],
-            vec![0.0, 1.0, 0.0, -(t+b)/2.0],
-            vec![0.0, 0.0, 1.0, -(n+f+1.0)/2.0],
-            vec![0.0, 0.0, 0.0, 1.0],
+    let m2 = Mat4x4f::from_array(
+        [
+            [1.0, 0.0, 0.0, -(l+r)/2.0],
+            [0.0, 1.0, 0.0, -(t+b)/2.0],
+            [0.0, 0.0, 1.0, -(n+f)/2.0],
+            [0.0, 0.0, 0.0, 1.0],
         ]);
     m1.mul(&m2)
 }
@@ -197,11 +471,54 @@ pub fn get_presp_projection_matrix(eye_fov: f32, aspect_ratio: f32, near: f32, f
     let l = -r;
     let b = -t;
 
-    get_ortho_projection_matrix(l, r, t, b, near, far).mul(&Mat4x4f::new_val(
-        vec![
-            vec![near, 0.0, 0.0, 0.0],
-            vec![0.0, near, 0.0, 0.0],
-            vec![0.0, 0.0, near+far, -near*far],
-            vec![0.0, 0.0, 1.0, 0.0],
+    //整体取反，齐次坐标不变，但可见点的w = -z为正，方便在裁剪空间裁剪
+    get_ortho_projection_matrix(l, r, t, b, near, far).mul(&Mat4x4f::from_array(
+        [
+            [-near, 0.0, 0.0, 0.0],
+            [0.0, -near, 0.0, 0.0],
+            [0.0, 0.0, -(near+far), near*far],
+            [0.0, 0.0, -1.0, 0.0],
         ]))
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::common::sampler::Sampler;
+    use crate::math::vector::Vector2f;
+
+    #[test]
+    fn uniforms_resolve_texture_bindings() {
+        let texture = |id, c| Texture::from_image(id, RgbaImage::from_pixel(1, 1, Rgba([c, c, c, 255])));
+        let textures = vec![texture(3, 0), texture(7, 255)];
+
+        let mut material = Material::new("m");
+        material.bind_texture(TextureSlot::Albedo, 7, Sampler::new());
+        material.bind_texture(TextureSlot::Emissive, 3, Sampler::bilinear());
+        //不存在的纹理id当作没有绑定
+        material.bind_texture(TextureSlot::Normal, 42, Sampler::new());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_materials(vec![material]);
+
+        let uniforms = rasterizer.uniforms(&textures, Some(0));
+        let (t, s) = uniforms.bindings[TextureSlot::Albedo.index()].unwrap();
+        assert_eq!(t.get_id(), 7);
+        assert_eq!(*s, Sampler::new());
+        let (t, s) = uniforms.bindings[TextureSlot::Emissive.index()].unwrap();
+        assert_eq!(t.get_id(), 3);
+        assert_eq!(*s, Sampler::bilinear());
+        assert!(uniforms.bindings[TextureSlot::Normal.index()].is_none());
+        assert!(uniforms.bindings[TextureSlot::Opacity.index()].is_none());
+
+        let uv = Vector2f::new_2(0.5, 0.5);
+        let d = Vector2f::new_2(0.0, 0.0);
+        assert_eq!(uniforms.sample(TextureSlot::Albedo, &uv, &d, &d).unwrap().x(), 1.0);
+        assert_eq!(uniforms.sample(TextureSlot::Emissive, &uv, &d, &d).unwrap().x(), 0.0);
+        assert!(uniforms.sample(TextureSlot::Normal, &uv, &d, &d).is_none());
+
+        //默认材质没有绑定纹理
+        assert!(rasterizer.uniforms(&textures, None).bindings.iter().all(|b| b.is_none()));
+    }
+}
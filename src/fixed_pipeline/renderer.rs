@@ -0,0 +1,501 @@
+use std::path::Path;
+
+use image::{ImageResult, RgbaImage};
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::common::mesh::Mesh;
+use crate::common::texture::Texture;
+use crate::common::triangle::Triangle;
+use crate::scene::graph::Scene;
+
+use super::framebuffer::Framebuffer;
+use super::oit::OitBuffer;
+use super::rasterizer::{draw_mesh_map, draw_trangle_map, Rasterizer, RenderResult};
+use super::skybox::draw_skybox;
+use super::tonemap::ToneMapping;
+
+/// 离屏渲染，持有一个Framebuffer，不依赖窗口
+/// 着色在线性空间进行，输出图片时按tone_mapping转换成sRGB
+pub struct Renderer {
+    framebuffer: Framebuffer,
+    oit: Option<OitBuffer>,
+    tone_mapping: ToneMapping,
+    /// 清除后还没有写过深度，绘制的reversed_z和清除值的约定不同时重新填充深度
+    depth_cleared: bool,
+    /// 深度清除值是按哪种约定设置的
+    clear_reversed_z: bool,
+    /// 深度缓冲里现在的清除值是按哪种约定填充的
+    filled_reversed_z: bool,
+}
+
+impl Renderer {
+    /// 浮点HDR颜色缓冲，默认ACES色调映射
+    pub fn new(width: i32, height: i32) -> Renderer {
+        Renderer::with_framebuffer(Framebuffer::new_hdr(width, height))
+    }
+
+    /// RGBA8的颜色附件输出时同样会做色调映射，需要原样输出时设置ToneMapping::disabled()
+    pub fn with_framebuffer(framebuffer: Framebuffer) -> Renderer {
+        Renderer {
+            framebuffer,
+            oit: None,
+            tone_mapping: ToneMapping::default(),
+            depth_cleared: true,
+            clear_reversed_z: false,
+            filled_reversed_z: false,
+        }
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub fn get_tone_mapping(&self) -> &ToneMapping {
+        &self.tone_mapping
+    }
+
+    pub fn width(&self) -> i32 {
+        self.framebuffer.width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.framebuffer.height()
+    }
+
+    /// 深度清除值v按reversed_z指定的约定解释，绘制时的约定不同就换算成-1-v
+    /// 直接调用Framebuffer::set_depth_clear_value时按非reversed_z处理
+    pub fn set_depth_clear_value(&mut self, v: f32, reversed_z: bool) {
+        self.framebuffer.set_depth_clear_value(v);
+        self.clear_reversed_z = reversed_z;
+    }
+
+    pub fn clear(&mut self) {
+        self.framebuffer.clear();
+        self.depth_cleared = true;
+        self.filled_reversed_z = self.clear_reversed_z;
+        if let Some(oit) = self.oit.as_mut() {
+            oit.clear();
+        }
+    }
+
+    /// 清除后还没写过深度时，这次绘制的reversed_z和缓冲里的约定不同就按这次的约定重新填充，清除值本身不变
+    fn prepare_depth(&mut self, rasterizer: &Rasterizer) {
+        let reversed_z = rasterizer.get_depth_state().reversed_z;
+        if !self.depth_cleared || reversed_z == self.filled_reversed_z {
+            return;
+        }
+
+        let v = self.framebuffer.get_depth_attachment().clear_value;
+        let v = if reversed_z == self.clear_reversed_z { v } else { -1.0 - v };
+        self.framebuffer.fill_depth(v);
+        self.filled_reversed_z = reversed_z;
+    }
+
+    /// 画rasterizer设置的天空盒，没有设置时什么都不做，需要在不透明物体之前调用
+    pub fn draw_skybox(&mut self, rasterizer: &Rasterizer) {
+        self.prepare_depth(rasterizer);
+        draw_skybox(rasterizer, &mut self.framebuffer);
+    }
+
+    /// 并行光栅化所有三角形，再按深度合并到Framebuffer中
+    pub fn draw(&mut self, rasterizer: &Rasterizer, triangles: &mut Vec<Triangle>, textures: &Vec<Texture>) {
+        let (width, height) = (self.width(), self.height());
+        let res: Vec<Vec<RenderResult>> = triangles
+            .par_iter_mut()
+            .map(|t| draw_trangle_map(rasterizer, width, height, t, textures))
+            .collect();
+        self.merge(rasterizer, res);
+    }
+
+    /// 索引网格：每个顶点只变换一次，再按索引装配三角形并行光栅化
+    pub fn draw_mesh(&mut self, rasterizer: &Rasterizer, mesh: &Mesh, textures: &Vec<Texture>) {
+        let (width, height) = (self.width(), self.height());
+        let res = draw_mesh_map(rasterizer, width, height, mesh, mesh.material, textures);
+        self.merge(rasterizer, res);
+    }
+
+    /// 场景图里每个挂了网格的节点画一次，会修改rasterizer的model(以及相机的view和projection)
+    pub fn draw_scene(&mut self, rasterizer: &mut Rasterizer, scene: &Scene, textures: &Vec<Texture>) {
+        if let Some(camera) = scene.camera.as_ref() {
+            camera.apply(rasterizer);
+        }
+
+        let (width, height) = (self.width(), self.height());
+        for inst in scene.instances() {
+            rasterizer.set_model(inst.world);
+            rasterizer.compute_mvp();
+            let res = draw_mesh_map(rasterizer, width, height, inst.mesh, inst.material, textures);
+            self.merge(rasterizer, res);
+        }
+    }
+
+    /// 按三角形的提交顺序做深度测试和混合
+    fn merge(&mut self, rasterizer: &Rasterizer, res: Vec<Vec<RenderResult>>) {
+        self.prepare_depth(rasterizer);
+        let depth_state = rasterizer.get_depth_state();
+        self.depth_cleared &= !depth_state.write;
+        let stencil_state = rasterizer.get_stencil_state();
+        let fb = &mut self.framebuffer;
+        let blend_state = rasterizer.get_blend_state();
+        for tmp in res {
+            for r in tmp {
+                let idx = r.idx as usize;
+                if fb.depth_stencil_test(idx, r.z, depth_state, stencil_state) {
+                    fb.update_stencil(idx, stencil_state, stencil_state.pass);
+                    fb.blend_color(idx, &r.color, r.coverage, blend_state);
+                    fb.update_depth(idx, r.z, depth_state);
+                }
+            }
+        }
+    }
+
+    /// 无序半透明绘制：只做深度测试不写深度，累加到OIT缓冲，需要在所有不透明物体之后调用，最后调用resolve_oit
+    pub fn draw_oit(&mut self, rasterizer: &Rasterizer, triangles: &mut Vec<Triangle>, textures: &Vec<Texture>) {
+        let (width, height) = (self.width(), self.height());
+        let res: Vec<Vec<RenderResult>> = triangles
+            .par_iter_mut()
+            .map(|t| draw_trangle_map(rasterizer, width, height, t, textures))
+            .collect();
+        self.accumulate_oit(rasterizer, res);
+    }
+
+    /// 索引网格的draw_oit
+    pub fn draw_mesh_oit(&mut self, rasterizer: &Rasterizer, mesh: &Mesh, textures: &Vec<Texture>) {
+        let (width, height) = (self.width(), self.height());
+        let res = draw_mesh_map(rasterizer, width, height, mesh, mesh.material, textures);
+        self.accumulate_oit(rasterizer, res);
+    }
+
+    /// 场景图的draw_oit，和draw_scene一样会修改rasterizer的model(以及相机的view和projection)
+    pub fn draw_scene_oit(&mut self, rasterizer: &mut Rasterizer, scene: &Scene, textures: &Vec<Texture>) {
+        if let Some(camera) = scene.camera.as_ref() {
+            camera.apply(rasterizer);
+        }
+
+        let (width, height) = (self.width(), self.height());
+        for inst in scene.instances() {
+            rasterizer.set_model(inst.world);
+            rasterizer.compute_mvp();
+            let res = draw_mesh_map(rasterizer, width, height, inst.mesh, inst.material, textures);
+            self.accumulate_oit(rasterizer, res);
+        }
+    }
+
+    fn accumulate_oit(&mut self, rasterizer: &Rasterizer, res: Vec<Vec<RenderResult>>) {
+        self.prepare_depth(rasterizer);
+        let (width, height) = (self.width(), self.height());
+        let fb = &mut self.framebuffer;
+        let oit = self.oit.get_or_insert_with(|| OitBuffer::new(width, height));
+        let depth_state = rasterizer.get_depth_state();
+        let stencil_state = rasterizer.get_stencil_state();
+        for tmp in res {
+            for r in tmp {
+                let idx = r.idx as usize;
+                if fb.depth_stencil_test(idx, r.z, depth_state, stencil_state) {
+                    fb.update_stencil(idx, stencil_state, stencil_state.pass);
+                    //转换成0(near)到1(far)
+                    let depth = if depth_state.reversed_z { 1.0 + r.z } else { -r.z };
+                    oit.accumulate(idx, &r.color, r.coverage, depth);
+                }
+            }
+        }
+    }
+
+    /// 把累加的半透明结果合成到Framebuffer并清空OIT缓冲
+    pub fn resolve_oit(&mut self) {
+        if let Some(oit) = self.oit.as_mut() {
+            oit.composite(&mut self.framebuffer);
+            oit.clear();
+        }
+    }
+
+    pub fn get_framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn get_framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// 第0个颜色附件做色调映射和sRGB编码后的RGBA8字节
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let size = (self.width() * self.height()) as usize;
+        self.tone_mapping.resolve(self.framebuffer.get_color_attachment(0), size)
+    }
+
+    /// iced的Handle::from_pixels需要BGRA顺序
+    pub fn to_bgra(&self) -> Vec<u8> {
+        let mut r = self.to_rgba8();
+        for p in r.chunks_exact_mut(4) {
+            p.swap(0, 2);
+        }
+        r
+    }
+
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_raw(self.width() as u32, self.height() as u32, self.to_rgba8()).unwrap()
+    }
+    /// 按扩展名选择格式(png, ppm等)
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        let is_pnm = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("ppm") | Some("pgm") | Some("pbm") | Some("pnm")
+        );
+
+        //pnm不支持alpha通道
+        if is_pnm {
+            image::DynamicImage::ImageRgba8(self.to_image()).to_rgb8().save(path)
+        } else {
+            self.to_image().save(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::triangle::RenderType;
+    use crate::fixed_pipeline::depth::DepthState;
+    use crate::fixed_pipeline::framebuffer::CompareFunc;
+    use crate::fixed_pipeline::rasterizer::get_presp_projection_matrix;
+    use crate::fixed_pipeline::stencil::{StencilOp, StencilState};
+    use crate::math::vector::{Vector3f, Vector4f};
+    use crate::scene::graph::Node;
+    use crate::shader::builtin::VARYING_COLOR;
+    use crate::shader::program::{Fragment, FragmentShader, Uniforms};
+
+    const OPS: [CompareFunc; 8] = [
+        CompareFunc::Never,
+        CompareFunc::Less,
+        CompareFunc::LessEqual,
+        CompareFunc::Equal,
+        CompareFunc::NotEqual,
+        CompareFunc::GreaterEqual,
+        CompareFunc::Greater,
+        CompareFunc::Always,
+    ];
+
+    fn center_triangle() -> Triangle {
+        let mut t = Triangle::new();
+        t.set_origin_vertexs(vec![
+            Vector4f::new_4(-1.0, -1.0, -2.0, 1.0),
+            Vector4f::new_4(1.0, -1.0, -2.0, 1.0),
+            Vector4f::new_4(0.0, 1.0, -2.0, 1.0),
+        ]);
+        t.set_colors(vec![Vector3f::new_3(1.0, 1.0, 1.0); 3]);
+        t.set_render_type(RenderType::COLOR);
+        t
+    }
+
+    fn rasterizer(depth: DepthState, stencil: StencilState) -> Rasterizer {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_projection(get_presp_projection_matrix(60.0, 1.0, -0.1, -50.0));
+        rasterizer.set_depth_state(depth);
+        rasterizer.set_stencil_state(stencil);
+        rasterizer.compute_mvp();
+        rasterizer
+    }
+
+    const CENTER: usize = 8 * 16 + 8;
+
+    /// 在z=-2处画一个盖住屏幕中心的三角形，返回中心像素有没有被写
+    fn draw_center(state: DepthState) -> bool {
+        let mut renderer = Renderer::new(16, 16);
+        renderer.draw(&rasterizer(state, StencilState::new()), &mut vec![center_triangle()], &Vec::new());
+        renderer.get_framebuffer().read_color(0, CENTER).w() > 0.0
+    }
+
+    #[test]
+    fn depth_is_cleared_to_far_value_for_every_compare_op() {
+        for reversed_z in [false, true] {
+            for compare in OPS {
+                let state = DepthState { compare, write: true, reversed_z };
+                //三角形的深度在near和far之间，结果和任意(-1, 0)内的值与far比较相同
+                let expected = compare.test(-0.5, state.far_value());
+                assert_eq!(draw_center(state), expected, "{:?} reversed_z={}", compare, reversed_z);
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_z_default_state_draws() {
+        assert!(draw_center(DepthState::new_reversed_z()));
+        assert!(draw_center(DepthState::new()));
+    }
+
+    /// 中心三角形在默认DepthState下写入的深度
+    fn center_depth() -> f32 {
+        let mut renderer = Renderer::new(16, 16);
+        renderer.draw(&rasterizer(DepthState::new(), StencilState::new()), &mut vec![center_triangle()], &Vec::new());
+        renderer.get_framebuffer().get_depth_attachment().data[CENTER]
+    }
+
+    #[test]
+    fn custom_depth_clear_value_is_kept() {
+        let z = center_depth();
+        //默认约定越大越近：清除值比三角形近时挡住三角形，远时不挡
+        for (clear, visible) in [(z + 1e-3, false), (z - 1e-3, true)] {
+            for state in [DepthState::new(), DepthState::new_reversed_z()] {
+                let mut renderer = Renderer::new(16, 16);
+                renderer.get_framebuffer_mut().set_depth_clear_value(clear);
+                renderer.clear();
+                for _ in 0..2 {
+                    renderer.draw(&rasterizer(state, StencilState::new()), &mut vec![center_triangle()], &Vec::new());
+                    let fb = renderer.get_framebuffer();
+                    assert_eq!(fb.read_color(0, CENTER).w() > 0.0, visible, "clear={} {:?}", clear, state);
+                    assert_eq!(fb.get_depth_attachment().clear_value, clear);
+                    renderer.clear();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn depth_clear_value_in_reversed_z_convention() {
+        let z = -1.0 - center_depth();
+        let mut renderer = Renderer::new(16, 16);
+        //reversed_z越小越近
+        renderer.set_depth_clear_value(z - 1e-3, true);
+        renderer.clear();
+        let (normal, reversed) = (DepthState::new(), DepthState::new_reversed_z());
+        renderer.draw(&rasterizer(reversed, StencilState::new()), &mut vec![center_triangle()], &Vec::new());
+        assert_eq!(renderer.get_framebuffer().read_color(0, CENTER).w(), 0.0);
+        assert_eq!(renderer.get_framebuffer().get_depth_attachment().data[0], z - 1e-3);
+
+        renderer.clear();
+        renderer.draw(&rasterizer(normal, StencilState::new()), &mut vec![center_triangle()], &Vec::new());
+        assert_eq!(renderer.get_framebuffer().read_color(0, CENTER).w(), 0.0);
+        assert_eq!(renderer.get_framebuffer().get_depth_attachment().data[0], -1.0 - (z - 1e-3));
+    }
+
+    #[test]
+    fn stencil_masks_second_draw() {
+        let mut renderer = Renderer::new(16, 16);
+        renderer.get_framebuffer_mut().enable_stencil(0);
+
+        //第一次只写模板：深度测试总是失败，depth_fail时写入reference
+        let write = StencilState {
+            enabled: true,
+            reference: 3,
+            depth_fail: StencilOp::Replace,
+            ..StencilState::new()
+        };
+        let never = DepthState { compare: CompareFunc::Never, ..DepthState::new() };
+        renderer.draw(&rasterizer(never, write), &mut vec![center_triangle()], &Vec::new());
+        let fb = renderer.get_framebuffer();
+        assert_eq!(fb.read_stencil(CENTER), Some(3));
+        assert_eq!(fb.read_stencil(0), Some(0));
+        assert_eq!(fb.read_color(0, CENTER).w(), 0.0);
+
+        //第二次只在模板不等于3的地方画，通过的像素加1
+        let test = StencilState {
+            enabled: true,
+            func: CompareFunc::NotEqual,
+            reference: 3,
+            pass: StencilOp::IncrementClamp,
+            ..StencilState::new()
+        };
+        let mut big = center_triangle();
+        for v in big.vertexs.iter_mut() {
+            v.origin_v.v[0] *= 8.0;
+            v.origin_v.v[1] *= 8.0;
+        }
+        renderer.draw(&rasterizer(DepthState::new(), test), &mut vec![big], &Vec::new());
+        let fb = renderer.get_framebuffer();
+        assert_eq!(fb.read_stencil(CENTER), Some(3));
+        assert_eq!(fb.read_color(0, CENTER).w(), 0.0);
+        let drawn = (0..16 * 16).find(|&i| fb.read_stencil(i) == Some(1)).unwrap();
+        assert!(fb.read_color(0, drawn).w() > 0.0);
+    }
+
+    /// 顶点颜色加上固定的alpha
+    struct AlphaShader(f32);
+
+    impl FragmentShader for AlphaShader {
+        fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Option<Vector4f> {
+            let c = &fragment.varyings[VARYING_COLOR..VARYING_COLOR + 3];
+            Some(Vector4f::new_4(c[0], c[1], c[2], self.0))
+        }
+    }
+
+    /// 半透明的中心三角形画两次：一次用三角形列表，一次用其他draw_*_oit，合成结果应该相同
+    fn oit_image(draw: impl FnOnce(&mut Renderer, &mut Rasterizer, Triangle)) -> Vec<u8> {
+        let mut renderer = Renderer::new(16, 16);
+        let mut rasterizer = rasterizer(DepthState::new(), StencilState::new());
+        rasterizer.set_fragment_shader(AlphaShader(0.5));
+        let mut t = center_triangle();
+        t.set_colors(vec![Vector3f::new_3(0.2, 0.6, 1.0); 3]);
+        draw(&mut renderer, &mut rasterizer, t);
+        renderer.resolve_oit();
+        renderer.to_rgba8()
+    }
+
+    #[test]
+    fn oit_mesh_and_scene_match_triangles() {
+        let expected = oit_image(|r, rasterizer, t| r.draw_oit(rasterizer, &mut vec![t], &Vec::new()));
+        assert!(expected.chunks_exact(4).any(|p| p[0] != p[2]), "nothing was drawn");
+
+        let mesh = oit_image(|r, rasterizer, t| r.draw_mesh_oit(rasterizer, &Mesh::from_triangles(&[t]), &Vec::new()));
+        assert_eq!(mesh, expected);
+
+        let scene = oit_image(|r, rasterizer, t| {
+            let mut scene = Scene::new();
+            let mut node = Node::new("t");
+            node.set_mesh(Some(scene.add_mesh(Mesh::from_triangles(&[t]))));
+            scene.add_node(node, None);
+            r.draw_scene_oit(rasterizer, &scene, &Vec::new());
+        });
+        assert_eq!(scene, expected);
+    }
+
+    /// z处的中心三角形，颜色为c
+    fn colored_triangle(z: f32, c: Vector3f) -> Triangle {
+        let mut t = center_triangle();
+        for v in t.vertexs.iter_mut() {
+            v.origin_v.v[2] = z;
+        }
+        t.set_colors(vec![c; 3]);
+        t
+    }
+
+    /// 不透明绘制后中心像素的深度
+    fn depth_at_center(t: &Triangle) -> f32 {
+        let mut renderer = Renderer::new(16, 16);
+        renderer.draw(&rasterizer(DepthState::new(), StencilState::new()), &mut vec![t.clone()], &Vec::new());
+        renderer.get_framebuffer().get_depth_attachment().data[CENTER]
+    }
+
+    #[test]
+    fn oit_partial_alpha_is_order_independent() {
+        let near = (colored_triangle(-2.0, Vector3f::new_3(1.0, 0.0, 0.0)), 0.5);
+        let far = (colored_triangle(-3.0, Vector3f::new_3(0.0, 0.0, 1.0)), 0.4);
+
+        let draw = |first: &(Triangle, f32), second: &(Triangle, f32)| {
+            let mut renderer = Renderer::new(16, 16);
+            renderer.get_framebuffer_mut().set_color_clear_value(0, Vector4f::new_4(0.0, 1.0, 0.0, 1.0));
+            renderer.clear();
+            for (t, alpha) in [first, second] {
+                let mut rasterizer = rasterizer(DepthState::new(), StencilState::new());
+                rasterizer.set_fragment_shader(AlphaShader(*alpha));
+                renderer.draw_oit(&rasterizer, &mut vec![t.clone()], &Vec::new());
+            }
+            renderer.resolve_oit();
+            renderer.get_framebuffer().read_color(0, CENTER)
+        };
+        let a = draw(&near, &far);
+        let b = draw(&far, &near);
+
+        //加权平均：权重随深度减小，近处的红色占比更大；背景按两层的透过率保留
+        let (wn, wf) = (OitBuffer::weight(0.5, -depth_at_center(&near.0)), OitBuffer::weight(0.4, -depth_at_center(&far.0)));
+        assert!(wn > wf);
+        let revealage = (1.0 - 0.5) * (1.0 - 0.4);
+        let red = 0.5 * wn / (0.5 * wn + 0.4 * wf) * (1.0 - revealage);
+        let blue = 0.4 * wf / (0.5 * wn + 0.4 * wf) * (1.0 - revealage);
+        let expected = [red, revealage, blue, 1.0];
+        for i in 0..4 {
+            assert!((a[i] - expected[i]).abs() < 1e-5, "{:?} != {:?}", a, expected);
+            assert!((a[i] - b[i]).abs() < 1e-6, "{:?} != {:?}", a, b);
+        }
+    }
+}
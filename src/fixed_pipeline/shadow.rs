@@ -0,0 +1,306 @@
+use crate::common::light::{Light, LightType};
+use crate::common::shadow::{CubeShadowMap, ShadowMap};
+use crate::common::texture::Texture;
+use crate::common::triangle::Triangle;
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::{Vector3f, Vector4f};
+use crate::shader::program::{Fragment, FragmentShader, Uniforms};
+
+use super::framebuffer::Framebuffer;
+use super::rasterizer::{draw_trangle, get_ortho_projection_matrix, get_presp_projection_matrix, Rasterizer};
+
+/// 只写深度，不采样纹理
+struct DepthOnlyShader;
+
+impl FragmentShader for DepthOnlyShader {
+    fn shade(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> Option<Vector4f> {
+        Some(Vector4f::new_4(0.0, 0.0, 0.0, 1.0))
+    }
+}
+
+/// 从光源的位置渲染深度图，model_view把三角形变换到光照空间
+/// 平行光用包住整个场景的正交投影，聚光灯用外锥角的透视投影，点光源返回None(见render_cube_shadow_map)
+pub fn render_shadow_map(light: &Light, model_view: &Mat4x4f, triangles: &mut [Triangle]) -> Option<ShadowMap> {
+    let settings = light.shadow_settings?;
+    let (min, max) = scene_bounds(model_view, triangles)?;
+    let center = Vector3f::new_3((min.x() + max.x()) / 2.0, (min.y() + max.y()) / 2.0, (min.z() + max.z()) / 2.0);
+    let radius = max.sub(&min).length() / 2.0 + 1e-3;
+
+    let (view, projection) = match &light.light_type {
+        LightType::Directional { direction } => {
+            let eye = Vector3f::new_3(
+                center.x() - direction.x() * radius * 2.0,
+                center.y() - direction.y() * radius * 2.0,
+                center.z() - direction.z() * radius * 2.0,
+            );
+            let view = get_look_at_matrix(&eye, direction);
+            let (lo, hi) = transformed_bounds(&view, &min, &max);
+            //near和far都在-z方向上
+            let projection = get_ortho_projection_matrix(lo.x(), hi.x(), hi.y(), lo.y(), hi.z() + 1e-3, lo.z() - 1e-3);
+            (view, projection)
+        }
+        LightType::Spot { position, direction, range, outer_angle, .. } => {
+            let view = get_look_at_matrix(position, direction);
+            let far = if range.is_finite() { *range } else { center.sub(position).length() + radius };
+            let fov = (outer_angle * 2.0 + 2.0).min(170.0);
+            let projection = get_presp_projection_matrix(fov, 1.0, -(far * 1e-3).max(1e-2), -far);
+            (view, projection)
+        }
+        LightType::Point { .. } => return None,
+    };
+
+    let size = settings.resolution;
+    let depth = render_depth(model_view, &view, &projection, size, triangles);
+    Some(ShadowMap::new(size, size, depth, view, &projection, settings))
+}
+
+/// 点光源向6个方向各渲染一个90度的深度图
+pub fn render_cube_shadow_map(light: &Light, model_view: &Mat4x4f, triangles: &mut [Triangle]) -> Option<CubeShadowMap> {
+    let settings = light.shadow_settings?;
+    let (position, range) = match &light.light_type {
+        LightType::Point { position, range, .. } => (position, *range),
+        _ => return None,
+    };
+
+    let far = if range.is_finite() {
+        range
+    } else {
+        let (min, max) = scene_bounds(model_view, triangles)?;
+        //到包围盒最远顶点的距离
+        let dx = (position.x() - min.x()).abs().max((position.x() - max.x()).abs());
+        let dy = (position.y() - min.y()).abs().max((position.y() - max.y()).abs());
+        let dz = (position.z() - min.z()).abs().max((position.z() - max.z()).abs());
+        Vector3f::new_3(dx, dy, dz).length() + 1e-3
+    };
+    let projection = get_presp_projection_matrix(90.0, 1.0, -(far * 1e-3).max(1e-2), -far);
+
+    let directions = [
+        Vector3f::new_3(1.0, 0.0, 0.0),
+        Vector3f::new_3(-1.0, 0.0, 0.0),
+        Vector3f::new_3(0.0, 1.0, 0.0),
+        Vector3f::new_3(0.0, -1.0, 0.0),
+        Vector3f::new_3(0.0, 0.0, 1.0),
+        Vector3f::new_3(0.0, 0.0, -1.0),
+    ];
+
+    let size = settings.resolution;
+    let mut map = CubeShadowMap::new(size, *position, settings);
+    for (face, direction) in directions.iter().enumerate() {
+        let view = get_look_at_matrix(position, direction);
+        let depth = render_depth(model_view, &view, &projection, size, triangles);
+        map.set_face(face, &depth, &view, &projection);
+    }
+
+    Some(map)
+}
+
+/// 用edge equation光栅化只渲染深度，返回NDC深度
+fn render_depth(model_view: &Mat4x4f, view: &Mat4x4f, projection: &Mat4x4f, size: i32, triangles: &mut [Triangle]) -> Vec<f32> {
+    let mut rasterizer = Rasterizer::new();
+    rasterizer.set_model(*model_view);
+    rasterizer.set_view(*view);
+    rasterizer.set_projection(*projection);
+    rasterizer.compute_mvp();
+    rasterizer.set_fragment_shader(DepthOnlyShader);
+
+    let mut framebuffer = Framebuffer::new_depth_only(size, size);
+    let textures: Vec<Texture> = Vec::new();
+    for t in triangles.iter_mut() {
+        draw_trangle(&rasterizer, &mut framebuffer, t, &textures);
+    }
+
+    framebuffer.get_depth_attachment().data.clone()
+}
+
+/// 光照空间里所有顶点的包围盒
+fn scene_bounds(model_view: &Mat4x4f, triangles: &[Triangle]) -> Option<(Vector3f, Vector3f)> {
+    let mut min = Vector3f::new_3(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3f::new_3(f32::MIN, f32::MIN, f32::MIN);
+    for t in triangles {
+        for v in t.vertexs.iter() {
+            let p = model_view.apply(&v.origin_v);
+            for i in 0..3 {
+                min.v[i] = min.v[i].min(p.v[i] / p.w());
+                max.v[i] = max.v[i].max(p.v[i] / p.w());
+            }
+        }
+    }
+
+    if min.x() > max.x() {
+        return None;
+    }
+    Some((min, max))
+}
+
+/// 包围盒的8个顶点变换后的包围盒
+fn transformed_bounds(m: &Mat4x4f, min: &Vector3f, max: &Vector3f) -> (Vector3f, Vector3f) {
+    let mut lo = Vector3f::new_3(f32::MAX, f32::MAX, f32::MAX);
+    let mut hi = Vector3f::new_3(f32::MIN, f32::MIN, f32::MIN);
+    for i in 0..8 {
+        let corner = Vector4f::new_4(
+            if i & 1 == 0 { min.x() } else { max.x() },
+            if i & 2 == 0 { min.y() } else { max.y() },
+            if i & 4 == 0 { min.z() } else { max.z() },
+            1.0,
+        );
+        let p = m.apply(&corner);
+        for k in 0..3 {
+            lo.v[k] = lo.v[k].min(p.v[k]);
+            hi.v[k] = hi.v[k].max(p.v[k]);
+        }
+    }
+
+    (lo, hi)
+}
+
+/// 从eye沿direction看的视图矩阵，up方向自动选择
+pub fn get_look_at_matrix(eye: &Vector3f, direction: &Vector3f) -> Mat4x4f {
+    let mut f = *direction;
+    f.normlize();
+    let up = if f.y().abs() > 0.99 { Vector3f::new_3(0.0, 0.0, 1.0) } else { Vector3f::new_3(0.0, 1.0, 0.0) };
+
+    let mut s = cross(&f, &up);
+    s.normlize();
+    let u = cross(&s, &f);
+
+    Mat4x4f::from_array([
+        [s.x(), s.y(), s.z(), -s.dot_product(eye)],
+        [u.x(), u.y(), u.z(), -u.dot_product(eye)],
+        [-f.x(), -f.y(), -f.z(), f.dot_product(eye)],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+fn cross(a: &Vector3f, b: &Vector3f) -> Vector3f {
+    Vector3f::new_3(
+        a.y() * b.z() - a.z() * b.y(),
+        a.z() * b.x() - a.x() * b.z(),
+        a.x() * b.y() - a.y() * b.x(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::shadow::ShadowSettings;
+
+    /// y处水平的正方形，中心(cx, cz)，边长2*half
+    fn quad(y: f32, cx: f32, cz: f32, half: f32) -> Vec<Triangle> {
+        let p = |dx: f32, dz: f32| Vector4f::new_4(cx + dx * half, y, cz + dz * half, 1.0);
+        let mut a = Triangle::new();
+        a.set_origin_vertexs(vec![p(-1.0, -1.0), p(1.0, -1.0), p(1.0, 1.0)]);
+        let mut b = Triangle::new();
+        b.set_origin_vertexs(vec![p(-1.0, -1.0), p(1.0, 1.0), p(-1.0, 1.0)]);
+        vec![a, b]
+    }
+
+    fn shadow_settings() -> ShadowSettings {
+        ShadowSettings { resolution: 64, bias: 0.05, slope_bias: 0.0, pcf_radius: 0 }
+    }
+
+    #[test]
+    fn directional_occluder() {
+        //地面y=0，遮挡物在y=1，y=3处的小三角形只用来扩大包围盒
+        let mut triangles = quad(0.0, 0.0, 0.0, 4.0);
+        triangles.extend(quad(1.0, 0.0, 0.0, 1.0));
+        triangles.extend(quad(3.0, 3.5, 3.5, 0.25));
+        let mut light = Light::directional(Vector3f::new_3(0.0, -1.0, 0.0), Vector3f::new_3(1.0, 1.0, 1.0));
+        light.enable_shadows(shadow_settings());
+        let map = render_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).unwrap();
+        assert_eq!((map.width(), map.height()), (64, 64));
+
+        let v = |x, y, z| map.visibility(&Vector3f::new_3(x, y, z), 1.0);
+        //遮挡物后面
+        assert_eq!(v(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(v(0.5, 0.5, -0.5), 0.0);
+        //遮挡物表面和前面
+        assert_eq!(v(0.0, 1.0, 0.0), 1.0);
+        assert_eq!(v(0.0, 2.0, 0.0), 1.0);
+        //遮挡物旁边的地面
+        assert_eq!(v(2.5, 0.0, 0.0), 1.0);
+        assert_eq!(v(-2.0, 0.0, -3.0), 1.0);
+    }
+
+    #[test]
+    fn spot_occluder() {
+        let mut triangles = quad(0.0, 0.0, 0.0, 4.0);
+        triangles.extend(quad(1.0, 0.0, 0.0, 0.5));
+        let mut light = Light::spot(Vector3f::new_3(0.0, 4.0, 0.0), Vector3f::new_3(0.0, -1.0, 0.0), Vector3f::new_3(1.0, 1.0, 1.0), 40.0, 50.0);
+        light.enable_shadows(shadow_settings());
+        let map = render_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).unwrap();
+
+        let v = |x, y, z| map.visibility(&Vector3f::new_3(x, y, z), 1.0);
+        //透视投影：遮挡物在地面上的影子被放大4/3倍
+        assert_eq!(v(0.6, 0.0, 0.0), 0.0);
+        assert_eq!(v(0.0, 0.0, -0.6), 0.0);
+        assert_eq!(v(0.0, 1.0, 0.0), 1.0);
+        assert_eq!(v(0.0, 2.0, 0.0), 1.0);
+        assert_eq!(v(0.8, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn point_light_has_no_shadow_map() {
+        let mut triangles = quad(0.0, 0.0, 0.0, 1.0);
+        let mut light = Light::new(Vector3f::new_3(0.0, 1.0, 0.0), Vector3f::new_3(1.0, 1.0, 1.0));
+        light.enable_shadows(shadow_settings());
+        assert!(render_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).is_none());
+        //没有开启阴影
+        light.disable_shadows();
+        assert!(render_cube_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).is_none());
+    }
+
+    /// 垂直于axis轴、中心在axis方向offset处的正方形
+    fn axis_quad(axis: usize, offset: f32, half: f32) -> Vec<Triangle> {
+        let p = |a: f32, b: f32| {
+            let mut v = Vector4f::new_4(0.0, 0.0, 0.0, 1.0);
+            v.v[axis] = offset;
+            v.v[(axis + 1) % 3] = a * half;
+            v.v[(axis + 2) % 3] = b * half;
+            v
+        };
+        let mut a = Triangle::new();
+        a.set_origin_vertexs(vec![p(-1.0, -1.0), p(1.0, -1.0), p(1.0, 1.0)]);
+        let mut b = Triangle::new();
+        b.set_origin_vertexs(vec![p(-1.0, -1.0), p(1.0, 1.0), p(-1.0, 1.0)]);
+        vec![a, b]
+    }
+
+    #[test]
+    fn cube_shadow_map_every_face() {
+        //光源在原点，外面是边长8的盒子，每个方向距离1处有一块小的遮挡物
+        let mut triangles = Vec::new();
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                triangles.extend(axis_quad(axis, sign * 4.0, 4.0));
+                triangles.extend(axis_quad(axis, sign, 0.3));
+            }
+        }
+        let mut light = Light::new(Vector3f::new(), Vector3f::new_3(1.0, 1.0, 1.0));
+        light.enable_shadows(shadow_settings());
+        let map = render_cube_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).unwrap();
+        assert_eq!(map.size(), 64);
+        assert!(render_shadow_map(&light, &Mat4x4f::identity(), &mut triangles).is_none());
+
+        for axis in 0..3 {
+            for (i, sign) in [1.0, -1.0].iter().enumerate() {
+                let point = |along: f32, side: f32| {
+                    let mut p = Vector3f::new();
+                    p.v[axis] = sign * along;
+                    p.v[(axis + 1) % 3] = side;
+                    p
+                };
+                let face = axis * 2 + i;
+                assert_eq!(CubeShadowMap::face_index(&point(2.0, 0.0)), face);
+
+                let v = |along, side| map.visibility(&point(along, side), 1.0);
+                //遮挡物前面、后面、墙上
+                assert_eq!(v(0.5, 0.0), 1.0, "face {}", face);
+                assert_eq!(v(2.0, 0.0), 0.0, "face {}", face);
+                assert_eq!(v(3.9, 0.2), 0.0, "face {}", face);
+                //影子以外
+                assert_eq!(v(2.0, 1.0), 1.0, "face {}", face);
+                assert_eq!(v(3.9, 2.0), 1.0, "face {}", face);
+            }
+        }
+    }
+}
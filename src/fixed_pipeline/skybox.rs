@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::common::skybox::Skybox;
+use crate::common::texture::Texture;
+use crate::common::triangle::{RenderType, Triangle, Vertex};
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::{Vector3f, Vector4f};
+use crate::shader::program::{Fragment, FragmentShader, Uniforms, VertexOutput, VertexShader};
+
+use super::culling::CullMode;
+use super::depth::DepthState;
+use super::framebuffer::Framebuffer;
+use super::rasterizer::{draw_trangle, Rasterizer};
+
+/// 输出方向作为varyings，深度固定在far平面上
+struct SkyboxVertexShader {
+    far_depth: f32,
+}
+
+impl VertexShader for SkyboxVertexShader {
+    fn shade(&self, vertex: &Vertex, uniforms: &Uniforms) -> VertexOutput {
+        let mut position = uniforms.mvp.apply(&vertex.origin_v);
+        position.v[2] = self.far_depth * position.w();
+        VertexOutput {
+            position,
+            varyings: vertex.origin_v.v[0..3].to_vec(),
+        }
+    }
+}
+
+struct SkyboxShader {
+    skybox: Arc<Skybox>,
+}
+
+impl FragmentShader for SkyboxShader {
+    fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Option<Vector4f> {
+        let v = fragment.varyings;
+        let c = self.skybox.sample(&Vector3f::new_3(v[0], v[1], v[2]));
+        Some(Vector4f::new_4(c.r(), c.g(), c.b(), 1.0))
+    }
+}
+
+/// 去掉平移，只保留旋转，相机怎么移动天空盒都在无穷远处
+pub fn get_skybox_view_matrix(view: &Mat4x4f) -> Mat4x4f {
+    let mut m = *view;
+    for i in 0..3 {
+        m.m[i][3] = 0.0;
+    }
+    m
+}
+
+/// 用rasterizer的视图和投影画天空盒，只在深度缓冲里还是far的地方可见，不写深度
+/// 在不透明物体之前画，msaa边缘才能和天空混合
+pub fn draw_skybox(rasterizer: &Rasterizer, framebuffer: &mut Framebuffer) {
+    let skybox = match rasterizer.get_skybox() {
+        Some(s) => s.clone(),
+        None => return,
+    };
+
+    let depth_state = *rasterizer.get_depth_state();
+    let mut sky = Rasterizer::new();
+    sky.set_view(get_skybox_view_matrix(rasterizer.get_view()));
+    sky.set_projection(*rasterizer.get_projection());
+    sky.set_depth_state(DepthState { write: false, ..depth_state });
+    sky.set_stencil_state(*rasterizer.get_stencil_state());
+    sky.compute_mvp();
+    sky.set_cull_mode(CullMode::None);
+    sky.set_vertex_shader(SkyboxVertexShader { far_depth: depth_state.far_value() });
+    sky.set_fragment_shader(SkyboxShader { skybox });
+
+    let textures: Vec<Texture> = Vec::new();
+    for mut t in cube_triangles() {
+        draw_trangle(&sky, framebuffer, &mut t, &textures);
+    }
+}
+
+/// 包住原点的单位立方体，12个三角形
+fn cube_triangles() -> Vec<Triangle> {
+    let p = |x: f32, y: f32, z: f32| Vector4f::new_4(x, y, z, 1.0);
+    let corners = [
+        p(-1.0, -1.0, -1.0), p(1.0, -1.0, -1.0), p(1.0, 1.0, -1.0), p(-1.0, 1.0, -1.0),
+        p(-1.0, -1.0, 1.0), p(1.0, -1.0, 1.0), p(1.0, 1.0, 1.0), p(-1.0, 1.0, 1.0),
+    ];
+    let faces = [
+        [0, 1, 2, 3], [5, 4, 7, 6], [4, 0, 3, 7],
+        [1, 5, 6, 2], [3, 2, 6, 7], [4, 5, 1, 0],
+    ];
+
+    let mut triangles = Vec::with_capacity(12);
+    for f in faces {
+        for idx in [[f[0], f[1], f[2]], [f[0], f[2], f[3]]] {
+            let mut t = Triangle::new();
+            t.set_origin_vertexs(idx.iter().map(|&i| corners[i]).collect());
+            t.set_render_type(RenderType::TEXTURE);
+            triangles.push(t);
+        }
+    }
+    triangles
+}
@@ -0,0 +1,77 @@
+use super::framebuffer::CompareFunc;
+
+/// 模板测试后对缓冲中模板值的操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl StencilOp {
+    pub fn apply(&self, stored: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::IncrementClamp => stored.saturating_add(1),
+            StencilOp::DecrementClamp => stored.saturating_sub(1),
+            StencilOp::Invert => !stored,
+            StencilOp::IncrementWrap => stored.wrapping_add(1),
+            StencilOp::DecrementWrap => stored.wrapping_sub(1),
+        }
+    }
+}
+
+/// 模板测试状态，每次绘制可以不同；Framebuffer没有模板附件时不起作用
+/// 比较的是 (reference & read_mask) 和 (stored & read_mask)，写入时只改write_mask里的位
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StencilState {
+    pub enabled: bool,
+    pub func: CompareFunc,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    /// 模板测试失败
+    pub fail: StencilOp,
+    /// 模板测试通过，深度测试失败
+    pub depth_fail: StencilOp,
+    /// 都通过
+    pub pass: StencilOp,
+}
+
+impl StencilState {
+    pub fn new() -> StencilState {
+        StencilState {
+            enabled: false,
+            func: CompareFunc::Always,
+            reference: 0,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+
+    pub fn test(&self, stored: u8) -> bool {
+        self.func.test(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    /// op作用后的模板值，write_mask以外的位保持不变
+    pub fn update(&self, stored: u8, op: StencilOp) -> u8 {
+        let v = op.apply(stored, self.reference);
+        (stored & !self.write_mask) | (v & self.write_mask)
+    }
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
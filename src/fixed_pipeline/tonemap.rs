@@ -0,0 +1,90 @@
+use crate::common::color::linear_to_srgb;
+use crate::math::vector::Vector4f;
+
+use super::framebuffer::ColorAttachment;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// 直接截断到0~1
+    None,
+    /// c / (1 + c)
+    Reinhard,
+    /// ACES filmic曲线(Narkowicz的拟合)
+    Aces,
+}
+
+/// 把线性HDR颜色转换成可以显示的8位颜色：先乘曝光，再做色调映射，最后sRGB编码
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f32,
+    /// 输出时做sRGB编码，颜色附件里已经是显示值时关掉
+    pub encode_srgb: bool,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure: 1.0,
+            encode_srgb: true,
+        }
+    }
+
+    /// 不做任何处理，只截断
+    pub fn disabled() -> ToneMapping {
+        ToneMapping {
+            operator: ToneMapOperator::None,
+            exposure: 1.0,
+            encode_srgb: false,
+        }
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    /// 曝光的单位是EV，每加1亮度翻倍
+    pub fn set_exposure_ev(&mut self, ev: f32) {
+        self.exposure = ev.exp2();
+    }
+
+    /// 对单个通道做曝光和色调映射，结果在0~1
+    pub fn map(&self, c: f32) -> f32 {
+        let c = (c * self.exposure).max(0.0);
+        let c = match self.operator {
+            ToneMapOperator::None => c,
+            ToneMapOperator::Reinhard => c / (1.0 + c),
+            ToneMapOperator::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        };
+        c.clamp(0.0, 1.0)
+    }
+
+    /// alpha不参与映射
+    pub fn apply(&self, color: &Vector4f) -> Vector4f {
+        let mut r = *color;
+        for i in 0..3 {
+            r.v[i] = self.map(color.v[i]);
+            if self.encode_srgb {
+                r.v[i] = linear_to_srgb(r.v[i]);
+            }
+        }
+        r
+    }
+
+    /// 整个颜色附件转换成RGBA8字节
+    pub fn resolve(&self, attachment: &ColorAttachment, size: usize) -> Vec<u8> {
+        let mut r = Vec::with_capacity(size * 4);
+        for idx in 0..size {
+            let c = self.apply(&attachment.read(idx));
+            r.extend(c.v.iter().map(|v| (v * 255.0 + 0.5) as u8));
+        }
+        r
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self::new(ToneMapOperator::Aces)
+    }
+}
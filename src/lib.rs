@@ -0,0 +1,19 @@
+pub mod math;
+pub mod common;
+pub mod fixed_pipeline;
+pub mod loader;
+pub mod shader;
+pub mod scene;
+
+pub use math::vector::{Vector, Vector2f, Vector3f, Vector4f, Color3f, Point2f, Point3f};
+pub use math::matrix::{Matrix, Mat4x4f};
+pub use common::triangle::{Triangle, Vertex, RenderType};
+pub use common::texture::Texture;
+pub use common::mesh::Mesh;
+pub use common::sampler::Sampler;
+pub use common::material::Material;
+pub use common::light::Light;
+pub use fixed_pipeline::rasterizer::Rasterizer;
+pub use fixed_pipeline::renderer::Renderer;
+pub use scene::graph::{Node, Scene};
+pub use scene::camera::Camera;
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use image::ColorType;
+
+use crate::common::color::srgb_to_linear;
+use crate::common::environment::{Environment, EnvironmentMap};
+use crate::common::skybox::CubeMap;
+
+use super::error::LoadError;
+
+/// 读取equirectangular全景图，.hdr等浮点图片按线性空间处理，8/16位图片按sRGB解码
+pub fn load_environment_map(path: &Path) -> Result<EnvironmentMap, LoadError> {
+    let (width, height, data) = load_linear_rgb(path)?;
+    Ok(EnvironmentMap::new(width, height, data))
+}
+
+fn load_linear_rgb(path: &Path) -> Result<(u32, u32, Vec<[f32; 3]>), LoadError> {
+    let image = image::open(path).map_err(|e| LoadError::image(path, e))?;
+    let srgb = !matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    let image = image.to_rgb32f();
+    let data = image.pixels()
+        .map(|p| if srgb { p.0.map(srgb_to_linear) } else { p.0 })
+        .collect();
+    Ok((image.width(), image.height(), data))
+}
+
+/// 读取全景图并预计算IBL
+pub fn load_environment(path: &Path) -> Result<Environment, LoadError> {
+    Ok(Environment::new(load_environment_map(path)?))
+}
+
+/// 读取立方体贴图的6个面，顺序是+X,-X,+Y,-Y,+Z,-Z，每个面必须是大小相同的正方形
+pub fn load_cube_map(paths: &[&Path; 6]) -> Result<CubeMap, LoadError> {
+    let mut size = 0;
+    let mut faces = Vec::with_capacity(6);
+    for (i, path) in paths.iter().enumerate() {
+        let (width, height, data) = load_linear_rgb(path)?;
+        if i == 0 {
+            size = width;
+        }
+        if width != size || height != size {
+            return Err(LoadError::InvalidCubeMap {
+                path: path.to_path_buf(),
+                expected: (size, size),
+                got: (width, height),
+            });
+        }
+        faces.push(data);
+    }
+    Ok(CubeMap::new(size, faces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_map_face_size_mismatch() {
+        let dir = std::env::temp_dir().join(format!("mini3d-cube-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = (0..6)
+            .map(|i| {
+                let p = dir.join(format!("{}.png", i));
+                let (w, h) = if i == 3 { (4, 2) } else { (4, 4) };
+                image::RgbImage::new(w, h).save(&p).unwrap();
+                p
+            })
+            .collect();
+        let refs: [&Path; 6] = std::array::from_fn(|i| paths[i].as_path());
+
+        let err = load_cube_map(&refs).err().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        match err {
+            LoadError::InvalidCubeMap { path, expected, got } => {
+                assert_eq!(path, paths[3]);
+                assert_eq!(expected, (4, 4));
+                assert_eq!(got, (4, 2));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// 立方体贴图的面不是正方形，或者和第一个面大小不同，expected和got都是(宽, 高)
+    InvalidCubeMap {
+        path: PathBuf,
+        expected: (u32, u32),
+        got: (u32, u32),
+    },
+}
+
+impl LoadError {
+    pub fn io(path: &Path, source: io::Error) -> LoadError {
+        LoadError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn image(path: &Path, source: image::ImageError) -> LoadError {
+        LoadError::Image {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn parse(path: &Path, line: usize, message: impl Into<String>) -> LoadError {
+        LoadError::Parse {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            LoadError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            LoadError::Image { path, source } => write!(f, "{}: {}", path.display(), source),
+            LoadError::InvalidCubeMap { path, expected, got } => write!(
+                f,
+                "{}: cube map face must be {}x{}, got {}x{}",
+                path.display(),
+                expected.0,
+                expected.1,
+                got.0,
+                got.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Parse { .. } => None,
+            LoadError::Image { source, .. } => Some(source),
+            LoadError::InvalidCubeMap { .. } => None,
+        }
+    }
+}
+
+/// 按空白切分后的一行，附带文件和行号用于报错
+pub struct LineParser<'a> {
+    pub path: &'a Path,
+    pub line: usize,
+}
+
+impl<'a> LineParser<'a> {
+    pub fn error(&self, message: impl Into<String>) -> LoadError {
+        LoadError::parse(self.path, self.line, message)
+    }
+
+    pub fn f32(&self, s: Option<&str>, what: &str) -> Result<f32, LoadError> {
+        let s = s.ok_or_else(|| self.error(format!("missing {}", what)))?;
+        s.parse::<f32>()
+            .map_err(|_| self.error(format!("invalid {} `{}`", what, s)))
+    }
+
+    pub fn f32_or(&self, s: Option<&str>, what: &str, default: f32) -> Result<f32, LoadError> {
+        match s {
+            Some(_) => self.f32(s, what),
+            None => Ok(default),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::common::material::{Material, ShadingModel, TextureSlot};
+use crate::common::sampler::{Sampler, WrapMode};
+use crate::common::texture::{ColorSpace, Texture};
+use crate::math::vector::Color3f;
+
+use super::error::LoadError;
+use super::mtl::ObjMaterial;
+
+/// 颜色贴图用ColorSpace::Srgb，数据贴图用ColorSpace::Linear
+pub fn load_texture(id: i32, path: &Path, color_space: ColorSpace) -> Result<Texture, LoadError> {
+    let image = image::open(path).map_err(|e| LoadError::image(path, e))?;
+    Ok(Texture::with_color_space(id, image.to_rgba8(), color_space))
+}
+
+/// mtl材质转换成Material，贴图加载成纹理(相同路径只加载一次，id按加载顺序递增)并绑定到材质的槽位上
+/// 颜色贴图(albedo/emissive/ambient/specular)按sRGB解码，其余按线性数据处理
+/// illum 0不计算光照，illum 1没有高光，其余按illum 2处理
+pub fn load_materials(materials: &[ObjMaterial], textures: &mut Vec<Texture>) -> Result<Vec<Material>, LoadError> {
+    let mut loaded: HashMap<(PathBuf, ColorSpace), i32> = HashMap::new();
+    let mut sampler = Sampler::trilinear();
+    sampler.set_wrap(WrapMode::Repeat, WrapMode::Repeat);
+
+    let mut result = Vec::with_capacity(materials.len());
+    for m in materials {
+        let mut material = Material::new(&m.name);
+        material.ambient = m.ambient;
+        material.diffuse = m.diffuse;
+        material.specular = m.specular;
+        material.emissive = m.emissive;
+        material.shininess = m.shininess;
+        material.opacity = m.opacity;
+        if m.roughness.is_some() || m.metallic.is_some() || m.map_roughness.is_some() || m.map_metallic.is_some() {
+            material.shading = ShadingModel::Pbr;
+            material.roughness = m.roughness.unwrap_or(1.0);
+            material.metallic = m.metallic.unwrap_or(0.0);
+        }
+        match m.illum {
+            0 => material.shading = ShadingModel::Unlit,
+            1 => material.specular = Color3f::new_3(0.0, 0.0, 0.0),
+            _ => {}
+        }
+        let maps = [
+            (TextureSlot::Albedo, &m.map_diffuse),
+            (TextureSlot::Normal, &m.map_bump),
+            (TextureSlot::Roughness, &m.map_roughness),
+            (TextureSlot::Emissive, &m.map_emissive),
+            (TextureSlot::Metallic, &m.map_metallic),
+            (TextureSlot::Ambient, &m.map_ambient),
+            (TextureSlot::Specular, &m.map_specular),
+            (TextureSlot::Shininess, &m.map_shininess),
+            (TextureSlot::Opacity, &m.map_opacity),
+        ];
+
+        for (slot, path) in maps {
+            let path = match path {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let color_space = match slot {
+                TextureSlot::Albedo | TextureSlot::Emissive | TextureSlot::Ambient | TextureSlot::Specular => ColorSpace::Srgb,
+                _ => ColorSpace::Linear,
+            };
+            let key = (path.clone(), color_space);
+            let id = match loaded.get(&key) {
+                Some(id) => *id,
+                None => {
+                    let id = textures.len() as i32;
+                    textures.push(load_texture(id, path, color_space)?);
+                    loaded.insert(key, id);
+                    id
+                }
+            };
+            material.bind_texture(slot, id, sampler);
+        }
+
+        result.push(material);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_every_map_and_applies_illum() {
+        let dir = std::env::temp_dir().join(format!("mini3d-material-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = dir.join("a.png");
+        image::RgbImage::new(2, 2).save(&png).unwrap();
+
+        let mut m = ObjMaterial::new("all");
+        m.map_ambient = Some(png.clone());
+        m.map_specular = Some(png.clone());
+        m.map_shininess = Some(png.clone());
+        m.map_opacity = Some(png.clone());
+        m.specular = Color3f::new_3(1.0, 1.0, 1.0);
+        let mut unlit = ObjMaterial::new("unlit");
+        unlit.illum = 0;
+        let mut matte = ObjMaterial::new("matte");
+        matte.illum = 1;
+        matte.specular = Color3f::new_3(1.0, 1.0, 1.0);
+
+        let mut textures = Vec::new();
+        let r = load_materials(&[m, unlit, matte], &mut textures);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let r = r.unwrap();
+
+        //同一张图按sRGB和线性各加载一次
+        assert_eq!(textures.len(), 2);
+        assert_eq!((textures[0].color_space(), textures[1].color_space()), (ColorSpace::Srgb, ColorSpace::Linear));
+        let id = |slot| r[0].get_texture(slot).map(|b| b.texture);
+        assert_eq!(id(TextureSlot::Ambient), Some(0));
+        assert_eq!(id(TextureSlot::Specular), Some(0));
+        assert_eq!(id(TextureSlot::Shininess), Some(1));
+        assert_eq!(id(TextureSlot::Opacity), Some(1));
+        assert_eq!(r[0].shading, ShadingModel::BlinnPhong);
+        assert_eq!(r[0].specular, Color3f::new_3(1.0, 1.0, 1.0));
+
+        assert_eq!(r[1].shading, ShadingModel::Unlit);
+        assert_eq!(r[2].shading, ShadingModel::BlinnPhong);
+        assert_eq!(r[2].specular, Color3f::new_3(0.0, 0.0, 0.0));
+    }
+}
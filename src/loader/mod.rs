@@ -0,0 +1,5 @@
+pub mod error;
+pub mod mtl;
+pub mod material;
+pub mod environment;
+pub mod obj;
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::math::vector::Color3f;
+
+use super::error::{LineParser, LoadError};
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: Color3f,
+    pub diffuse: Color3f,
+    pub specular: Color3f,
+    pub emissive: Color3f,
+    pub shininess: f32,
+    pub opacity: f32,
+    pub illum: i32,
+    pub map_ambient: Option<PathBuf>,
+    pub map_diffuse: Option<PathBuf>,
+    pub map_specular: Option<PathBuf>,
+    pub map_shininess: Option<PathBuf>,
+    pub map_emissive: Option<PathBuf>,
+    pub map_opacity: Option<PathBuf>,
+    pub map_bump: Option<PathBuf>,
+    /// PBR扩展 Pr/Pm/map_Pr/map_Pm，出现任意一个时使用PBR光照
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub map_roughness: Option<PathBuf>,
+    pub map_metallic: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: name.to_string(),
+            ambient: Color3f::new_3(0.0, 0.0, 0.0),
+            diffuse: Color3f::new_3(1.0, 1.0, 1.0),
+            specular: Color3f::new_3(0.0, 0.0, 0.0),
+            emissive: Color3f::new_3(0.0, 0.0, 0.0),
+            shininess: 1.0,
+            opacity: 1.0,
+            illum: 2,
+            map_ambient: None,
+            map_diffuse: None,
+            map_specular: None,
+            map_shininess: None,
+            map_emissive: None,
+            map_opacity: None,
+            map_bump: None,
+            roughness: None,
+            metallic: None,
+            map_roughness: None,
+            map_metallic: None,
+        }
+    }
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<Vec<ObjMaterial>, LoadError> {
+    let path = path.as_ref();
+    let f = File::open(path).map_err(|e| LoadError::io(path, e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for (idx, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| LoadError::io(path, e))?;
+        let p = LineParser { path, line: idx + 1 };
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let key = tokens.next().unwrap();
+        if key == "newmtl" {
+            let name = tokens.collect::<Vec<&str>>().join(" ");
+            if name.is_empty() {
+                return Err(p.error("newmtl without a name"));
+            }
+            materials.push(ObjMaterial::new(&name));
+            continue;
+        }
+
+        let m = match materials.last_mut() {
+            Some(m) => m,
+            None => return Err(p.error(format!("`{}` before any newmtl", key))),
+        };
+
+        match key {
+            "Ka" => m.ambient = parse_color(&p, &mut tokens)?,
+            "Kd" => m.diffuse = parse_color(&p, &mut tokens)?,
+            "Ks" => m.specular = parse_color(&p, &mut tokens)?,
+            "Ke" => m.emissive = parse_color(&p, &mut tokens)?,
+            "Ns" => m.shininess = p.f32(tokens.next(), "shininess")?,
+            "d" => m.opacity = p.f32(tokens.next(), "opacity")?,
+            "Tr" => m.opacity = 1.0 - p.f32(tokens.next(), "transparency")?,
+            "illum" => {
+                m.illum = p.f32(tokens.next(), "illumination model")? as i32;
+            }
+            "map_Ka" => m.map_ambient = Some(parse_map(&p, dir, tokens)?),
+            "map_Kd" => m.map_diffuse = Some(parse_map(&p, dir, tokens)?),
+            "map_Ks" => m.map_specular = Some(parse_map(&p, dir, tokens)?),
+            "map_Ns" => m.map_shininess = Some(parse_map(&p, dir, tokens)?),
+            "map_Ke" => m.map_emissive = Some(parse_map(&p, dir, tokens)?),
+            "map_d" => m.map_opacity = Some(parse_map(&p, dir, tokens)?),
+            "Pr" => m.roughness = Some(p.f32(tokens.next(), "roughness")?),
+            "Pm" => m.metallic = Some(p.f32(tokens.next(), "metallic")?),
+            "map_Pr" => m.map_roughness = Some(parse_map(&p, dir, tokens)?),
+            "map_Pm" => m.map_metallic = Some(parse_map(&p, dir, tokens)?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                m.map_bump = Some(parse_map(&p, dir, tokens)?)
+            }
+            // Ni, Tf, refl 等暂不支持
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_color<'a, I>(p: &LineParser, tokens: &mut I) -> Result<Color3f, LoadError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let r = p.f32(tokens.next(), "red component")?;
+    let g = p.f32_or(tokens.next(), "green component", r)?;
+    let b = p.f32_or(tokens.next(), "blue component", r)?;
+    Ok(Color3f::new_3(r, g, b))
+}
+
+/// 贴图前面可能带有 -bm 0.5 之类的选项，选项之后剩下的部分都是路径，路径可以包含空格
+/// 选项的值只做格式检查，不影响加载
+fn parse_map<'a, I>(p: &LineParser, dir: &Path, tokens: I) -> Result<PathBuf, LoadError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut tokens = tokens.peekable();
+    while let Some(option) = tokens.next_if(|t| t.starts_with('-')) {
+        match option {
+            "-bm" | "-boost" => {
+                p.f32(tokens.next(), option)?;
+            }
+            "-mm" => {
+                p.f32(tokens.next(), "-mm base")?;
+                p.f32(tokens.next(), "-mm gain")?;
+            }
+            //u必须有，v和w可以省略
+            "-s" | "-o" | "-t" => {
+                p.f32(tokens.next(), option)?;
+                for _ in 0..2 {
+                    if tokens.next_if(|t| t.parse::<f32>().is_ok()).is_none() {
+                        break;
+                    }
+                }
+            }
+            "-blendu" | "-blendv" | "-clamp" | "-cc" => match tokens.next() {
+                Some("on") | Some("off") => {}
+                Some(v) => return Err(p.error(format!("invalid {} `{}`, expected on or off", option, v))),
+                None => return Err(p.error(format!("missing {}", option))),
+            },
+            "-texres" => {
+                p.f32(tokens.next(), option)?;
+            }
+            "-imfchan" => match tokens.next() {
+                Some("r") | Some("g") | Some("b") | Some("m") | Some("l") | Some("z") => {}
+                Some(v) => return Err(p.error(format!("invalid -imfchan `{}`", v))),
+                None => return Err(p.error("missing -imfchan")),
+            },
+            _ => return Err(p.error(format!("unknown texture option `{}`", option))),
+        }
+    }
+
+    let file = tokens.collect::<Vec<&str>>().join(" ");
+    if file.is_empty() {
+        return Err(p.error("texture map without a file name"));
+    }
+    Ok(dir.join(file.replace('\\', "/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(tag: &str, content: &str) -> Result<Vec<ObjMaterial>, LoadError> {
+        let path = std::env::temp_dir().join(format!("mini3d-mtl-{}-{}.mtl", tag, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let r = load_mtl(&path);
+        std::fs::remove_file(&path).unwrap();
+        r
+    }
+
+    #[test]
+    fn parses_all_keys() {
+        let mtl = "newmtl metal plate\n\
+                   Ka 0.1\nKd 0.5 0.25 1\nKs 1 1 1\nKe 0 0.5 0\n\
+                   Ns 32 # 注释\nTr 0.25\nillum 1\n\
+                   map_Kd -bm 0.5 diffuse.png\nbump normal.png\n\
+                   Pr 0.3\nPm 1\nmap_Pr rough.png\n";
+        let m = load("all", mtl).unwrap();
+        assert_eq!(m.len(), 1);
+        let m = &m[0];
+        assert_eq!(m.name, "metal plate");
+        assert_eq!(m.ambient, Color3f::new_3(0.1, 0.1, 0.1));
+        assert_eq!(m.diffuse, Color3f::new_3(0.5, 0.25, 1.0));
+        assert_eq!(m.emissive, Color3f::new_3(0.0, 0.5, 0.0));
+        assert_eq!(m.shininess, 32.0);
+        assert_eq!(m.opacity, 0.75);
+        assert_eq!(m.illum, 1);
+        assert!(m.map_diffuse.as_ref().unwrap().ends_with("diffuse.png"));
+        assert!(m.map_bump.as_ref().unwrap().ends_with("normal.png"));
+        assert_eq!((m.roughness, m.metallic), (Some(0.3), Some(1.0)));
+        assert!(m.map_roughness.is_some() && m.map_metallic.is_none());
+    }
+
+    #[test]
+    fn map_options_and_paths_with_spaces() {
+        let mtl = "newmtl a\n\
+                   map_Kd -s 2 2 -o 0.5 -blendu off -clamp on my texture.png\n\
+                   map_Ka -mm 0 1 -t 0.1 -texres 512 ambient.png\n\
+                   map_Ks -cc on -boost 2 -s 2 2 1 textures\\spec map.png\n\
+                   map_d -imfchan m -blendv on alpha.png\n\
+                   bump -bm 0.5 -o 1 2 3 normal.png\n\
+                   map_Pr -s 2 3.png\n";
+        let m = &load("options", mtl).unwrap()[0];
+        let name = |p: &Option<PathBuf>| p.as_ref().unwrap().file_name().unwrap().to_str().unwrap().to_string();
+        assert_eq!(name(&m.map_diffuse), "my texture.png");
+        assert_eq!(name(&m.map_ambient), "ambient.png");
+        assert!(m.map_specular.as_ref().unwrap().ends_with("textures/spec map.png"));
+        assert_eq!(name(&m.map_opacity), "alpha.png");
+        assert_eq!(name(&m.map_bump), "normal.png");
+        //-s的v、w可以省略，遇到不是数字的token就是路径
+        assert_eq!(name(&m.map_roughness), "3.png");
+    }
+
+    #[test]
+    fn error_lines() {
+        let cases = [
+            ("Kd 1 1 1\n", 1, "`Kd` before any newmtl"),
+            ("\nnewmtl\n", 2, "newmtl without a name"),
+            ("newmtl a\nKd 1 red 1\n", 2, "invalid green component `red`"),
+            ("newmtl a\n# 注释\nmap_Kd\n", 3, "texture map without a file name"),
+            ("newmtl a\nmap_Kd -bm 0.5\n", 2, "texture map without a file name"),
+            ("newmtl a\nmap_Kd -bm\n", 2, "missing -bm"),
+            ("newmtl a\nmap_Kd -mm 0 a.png\n", 2, "invalid -mm gain `a.png`"),
+            ("newmtl a\nmap_Kd -clamp yes a.png\n", 2, "invalid -clamp `yes`, expected on or off"),
+            ("newmtl a\nmap_Kd -imfchan x a.png\n", 2, "invalid -imfchan `x`"),
+            ("newmtl a\nmap_Kd -foo 1 a.png\n", 2, "unknown texture option `-foo`"),
+        ];
+        for (i, (mtl, line, message)) in cases.iter().enumerate() {
+            match load(&format!("err{}", i), mtl) {
+                Err(LoadError::Parse { line: l, message: m, .. }) => {
+                    assert_eq!((l, m.as_str()), (*line, *message), "case {}", i)
+                }
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("case {}: expected a parse error", i),
+            }
+        }
+    }
+}
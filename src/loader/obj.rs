@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::common::mesh::Mesh;
+use crate::common::tangent::generate_tangents;
+use crate::common::triangle::{RenderType, Triangle};
+use crate::math::vector::{Color3f, Point2f, Point3f, Vector4f};
+
+use super::error::{LineParser, LoadError};
+use super::mtl::{load_mtl, ObjMaterial};
+
+pub struct ObjMesh {
+    pub name: String,
+    pub material: Option<usize>,
+    pub triangles: Vec<Triangle>,
+}
+
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+impl ObjModel {
+    pub fn triangles(&self) -> Vec<Triangle> {
+        self.meshes
+            .iter()
+            .flat_map(|m| m.triangles.iter().cloned())
+            .collect()
+    }
+
+    /// 每个ObjMesh转换成索引网格，有的面没有纹理坐标时按render拆成多个
+    pub fn meshes(&self) -> Vec<Mesh> {
+        self.meshes.iter().flat_map(|m| Mesh::group_triangles(&m.triangles)).collect()
+    }
+
+    pub fn material(&self, mesh: &ObjMesh) -> Option<&ObjMaterial> {
+        mesh.material.map(|i| &self.materials[i])
+    }
+}
+
+struct FaceVertex {
+    v: usize,
+    vt: Option<usize>,
+    vn: Option<usize>,
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, LoadError> {
+    let path = path.as_ref();
+    let f = File::open(path).map_err(|e| LoadError::io(path, e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vector4f> = Vec::new();
+    let mut texcoords: Vec<Point2f> = Vec::new();
+    let mut normals: Vec<Point3f> = Vec::new();
+
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    let mut material_index: HashMap<String, usize> = HashMap::new();
+    let mut meshes: Vec<ObjMesh> = Vec::new();
+
+    let mut name = String::new();
+    let mut material: Option<usize> = None;
+
+    for (idx, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| LoadError::io(path, e))?;
+        let p = LineParser { path, line: idx + 1 };
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next().unwrap() {
+            "v" => {
+                let x = p.f32(tokens.next(), "x coordinate")?;
+                let y = p.f32(tokens.next(), "y coordinate")?;
+                let z = p.f32(tokens.next(), "z coordinate")?;
+                let w = p.f32_or(tokens.next(), "w coordinate", 1.0)?;
+                positions.push(Vector4f::new_4(x, y, z, w));
+            }
+            "vt" => {
+                let u = p.f32(tokens.next(), "u coordinate")?;
+                let v = p.f32_or(tokens.next(), "v coordinate", 0.0)?;
+                texcoords.push(Point2f::new_2(u, v));
+            }
+            "vn" => {
+                let x = p.f32(tokens.next(), "normal x")?;
+                let y = p.f32(tokens.next(), "normal y")?;
+                let z = p.f32(tokens.next(), "normal z")?;
+                normals.push(Point3f::new_3(x, y, z));
+            }
+            "f" => {
+                let mut face = Vec::new();
+                for t in tokens {
+                    face.push(parse_face_vertex(
+                        &p,
+                        t,
+                        positions.len(),
+                        texcoords.len(),
+                        normals.len(),
+                    )?);
+                }
+                if face.len() < 3 {
+                    return Err(p.error(format!("face has {} vertices, need at least 3", face.len())));
+                }
+
+                let color = match material {
+                    Some(i) => materials[i].diffuse,
+                    None => Color3f::new_3(1.0, 1.0, 1.0),
+                };
+                let mesh = current_mesh(&mut meshes, &name, material);
+                //多边形按扇形三角化
+                for i in 1..face.len() - 1 {
+                    let mut t = build_triangle(
+                        [&face[0], &face[i], &face[i + 1]],
+                        &positions,
+                        &texcoords,
+                        &normals,
+                        &color,
+                    );
+                    t.set_material(material);
+                    mesh.triangles.push(t);
+                }
+            }
+            "o" | "g" => {
+                name = tokens.collect::<Vec<&str>>().join(" ");
+            }
+            "usemtl" => {
+                let mtl = tokens.collect::<Vec<&str>>().join(" ");
+                match material_index.get(&mtl) {
+                    Some(i) => material = Some(*i),
+                    None => return Err(p.error(format!("unknown material `{}`", mtl))),
+                }
+            }
+            "mtllib" => {
+                for file in tokens {
+                    for m in load_mtl(dir.join(file))? {
+                        material_index.insert(m.name.clone(), materials.len());
+                        materials.push(m);
+                    }
+                }
+            }
+            // s, l, p 以及其他不认识的指令直接忽略
+            _ => {}
+        }
+    }
+
+    meshes.retain(|m| !m.triangles.is_empty());
+    for m in meshes.iter_mut() {
+        generate_tangents(&mut m.triangles);
+    }
+    Ok(ObjModel { meshes, materials })
+}
+
+fn current_mesh<'a>(meshes: &'a mut Vec<ObjMesh>, name: &str, material: Option<usize>) -> &'a mut ObjMesh {
+    let reuse = match meshes.last() {
+        Some(m) => m.name == name && m.material == material,
+        None => false,
+    };
+
+    if !reuse {
+        meshes.push(ObjMesh {
+            name: name.to_string(),
+            material,
+            triangles: Vec::new(),
+        });
+    }
+
+    meshes.last_mut().unwrap()
+}
+
+fn parse_face_vertex(
+    p: &LineParser,
+    s: &str,
+    v_count: usize,
+    vt_count: usize,
+    vn_count: usize,
+) -> Result<FaceVertex, LoadError> {
+    let mut parts = s.split('/');
+    let v = resolve_index(p, parts.next(), v_count, "vertex")?;
+    let vt = match parts.next() {
+        Some("") | None => None,
+        Some(t) => Some(resolve_index(p, Some(t), vt_count, "texcoord")?),
+    };
+    let vn = match parts.next() {
+        Some("") | None => None,
+        Some(t) => Some(resolve_index(p, Some(t), vn_count, "normal")?),
+    };
+
+    Ok(FaceVertex { v, vt, vn })
+}
+
+/// obj 的索引从1开始，负数表示相对于当前已读入元素的末尾
+fn resolve_index(p: &LineParser, s: Option<&str>, count: usize, what: &str) -> Result<usize, LoadError> {
+    let s = s.ok_or_else(|| p.error(format!("missing {} index", what)))?;
+    let i = s
+        .parse::<i64>()
+        .map_err(|_| p.error(format!("invalid {} index `{}`", what, s)))?;
+
+    let r = if i > 0 { i - 1 } else { count as i64 + i };
+    if i == 0 || r < 0 || r >= count as i64 {
+        return Err(p.error(format!("{} index {} out of range ({} defined)", what, i, count)));
+    }
+
+    Ok(r as usize)
+}
+
+fn build_triangle(
+    face: [&FaceVertex; 3],
+    positions: &[Vector4f],
+    texcoords: &[Point2f],
+    normals: &[Point3f],
+    color: &Color3f,
+) -> Triangle {
+    let mut t = Triangle::new();
+    let v: Vec<Vector4f> = face.iter().map(|f| positions[f.v]).collect();
+
+    //没有法线时使用面法线
+    let n: Vec<Point3f> = if face.iter().all(|f| f.vn.is_some()) {
+        face.iter().map(|f| normals[f.vn.unwrap()]).collect()
+    } else {
+        let mut n = v[1].sub(&v[0]).cross_product(&v[2].sub(&v[0]));
+        n.v[3] = 0.0;
+        n.normlize();
+        vec![Point3f::new_3(n.x(), n.y(), n.z()); 3]
+    };
+
+    if face.iter().all(|f| f.vt.is_some()) {
+        t.set_tex_coords(face.iter().map(|f| texcoords[f.vt.unwrap()]).collect());
+        t.set_render_type(RenderType::TEXTURE);
+    }
+
+    t.set_origin_vertexs(v);
+    t.set_normal(n);
+    t.set_colors(vec![*color; 3]);
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 把文件写进单独的临时目录，返回目录
+    fn write_files(tag: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mini3d-obj-{}-{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+        dir
+    }
+
+    fn load(tag: &str, files: &[(&str, &str)]) -> Result<ObjModel, LoadError> {
+        let dir = write_files(tag, files);
+        let r = load_obj(dir.join(files[0].0));
+        std::fs::remove_dir_all(&dir).unwrap();
+        r
+    }
+
+    fn parse_error_line(r: Result<ObjModel, LoadError>) -> (usize, String) {
+        match r {
+            Err(LoadError::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn quad_with_texcoords_and_normals() {
+        let obj = "# 注释\n\
+                   v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   vn 0 0 1\n\
+                   o quad\n\
+                   f 1/1/1 2/2/1 3/3/1 -1/-1/-1\n";
+        let model = load("quad", &[("a.obj", obj)]).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].name, "quad");
+
+        let t = &model.meshes[0].triangles;
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[1].vertexs[2].origin_v, Vector4f::new_4(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(t[1].vertexs[2].tex_coords, Point2f::new_2(0.0, 1.0));
+        assert_eq!(t[0].vertexs[0].normal, Point3f::new_3(0.0, 0.0, 1.0));
+        assert_eq!(t[0].render, RenderType::TEXTURE);
+    }
+
+    #[test]
+    fn face_normal_when_missing() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        let model = load("facenormal", &[("a.obj", obj)]).unwrap();
+        let t = &model.meshes[0].triangles[0];
+        for v in t.vertexs.iter() {
+            assert_eq!(v.normal, Point3f::new_3(0.0, 0.0, 1.0));
+        }
+        assert_eq!(t.render, RenderType::COLOR);
+    }
+
+    #[test]
+    fn materials_from_mtllib() {
+        let obj = "mtllib a.mtl\n\
+                   v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   usemtl red\nf 1 2 3\n\
+                   usemtl blue\nf 3 2 1\n";
+        let mtl = "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\nmap_Kd tex\\blue.png\n";
+        let model = load("mtllib", &[("a.obj", obj), ("a.mtl", mtl)]).unwrap();
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.meshes.len(), 2);
+
+        let blue = model.material(&model.meshes[1]).unwrap();
+        assert_eq!(blue.name, "blue");
+        assert!(blue.map_diffuse.as_ref().unwrap().ends_with("tex/blue.png"));
+        assert_eq!(model.meshes[0].triangles[0].vertexs[0].color, Color3f::new_3(1.0, 0.0, 0.0));
+        assert_eq!(model.meshes[1].triangles[0].material, Some(1));
+    }
+
+    #[test]
+    fn error_lines() {
+        let cases = [
+            ("v 0 0\n", 1, "missing z coordinate"),
+            ("v 0 0 0\nv 0 x 0\n", 2, "invalid y coordinate `x`"),
+            ("v 0 0 0\nv 1 0 0\n\n# 空行和注释也计入行号\nf 1 2\n", 5, "face has 2 vertices, need at least 3"),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", 4, "vertex index 4 out of range (3 defined)"),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n", 4, "vertex index 0 out of range (3 defined)"),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n", 4, "texcoord index 1 out of range (0 defined)"),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//a 2 3\n", 4, "invalid normal index `a`"),
+            ("v 0 0 0\nusemtl nothing\n", 2, "unknown material `nothing`"),
+        ];
+        for (i, (obj, line, message)) in cases.iter().enumerate() {
+            let (l, m) = parse_error_line(load(&format!("err{}", i), &[("a.obj", obj)]));
+            assert_eq!((l, m.as_str()), (*line, *message), "case {}", i);
+        }
+    }
+
+    #[test]
+    fn mtl_error_reports_mtl_path() {
+        let obj = "v 0 0 0\nmtllib bad.mtl\n";
+        let mtl = "newmtl a\nKd 1 1 1\nNs\n";
+        let dir = write_files("mtlerr", &[("a.obj", obj), ("bad.mtl", mtl)]);
+        let r = load_obj(dir.join("a.obj"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        match r {
+            Err(LoadError::Parse { path, line, message }) => {
+                assert_eq!(path, dir.join("bad.mtl"));
+                assert_eq!(line, 3);
+                assert_eq!(message, "missing shininess");
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn missing_file_is_io_error() {
+        let r = load_obj(std::env::temp_dir().join("mini3d-obj-does-not-exist.obj"));
+        assert!(matches!(r, Err(LoadError::Io { .. })));
+    }
+}
@@ -1,250 +1,126 @@
-mod math;
-mod common;
-mod fixed_pipeline;
-
-use std::fs::{File, self};
-use std::io::{BufReader, BufRead};
 use std::path::Path;
-use rayon::current_num_threads;
-use rayon::iter::ParallelIterator;
-use rayon::iter::IntoParallelRefMutIterator;
-
-use iced::futures::executor::{ThreadPool, ThreadPoolBuilder};
-use iced::{
-    slider, Alignment, Column, Container, Element, Length, Sandbox, Settings,
-    Slider, Text, Image, image::Handle,
-};
+use std::sync::Arc;
 
-use math::vector::{Vector4f, Vector2f, Vector3f};
-use fixed_pipeline::rasterizer::{Rasterizer, get_model_matrix, get_presp_projection_matrix, get_view_matrix, draw_trangle, get_ortho_projection_matrix, draw_trangle_map, RenderResult};
-use common::triangle::Triangle;
-use common::texture::Texture;
-use common::light::Light;
+use mini3d_rust::common::skybox::Skybox;
+use mini3d_rust::fixed_pipeline::tonemap::{ToneMapOperator, ToneMapping};
+use mini3d_rust::loader::environment::{load_environment, load_environment_map};
+use mini3d_rust::math::quaternion::Quaternion;
+use mini3d_rust::{Mat4x4f, Mesh, Node, Renderer, Scene, Vector3f};
 
-pub fn main() -> iced::Result {
-    SoftRender::run(Settings::default())
-}
+mod demo;
 
-struct SoftRender {
-    radius: f32,
-    slider: slider::State,
-    texture: Vec<Texture>,
-    t: Vec<Triangle>
-}
+use demo::{build_rasterizer, load_scene};
 
-#[derive(Debug, Clone, Copy)]
-enum Message {
-    RadiusChanged(f32),
+/// 无窗口渲染，窗口程序见 src/bin/viewer.rs
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run_headless(&args) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
 }
 
-impl Sandbox for SoftRender {
-    type Message = Message;
-
-    fn new() -> SoftRender {
-        let f = File::open(Path::new("./objdata")).unwrap();
-        let reader = BufReader::new(f);
-        let lines = reader.lines();
-        let mut idx = 0;
-        let texture = Texture::new(0, "./spot_texture.png");
-
-        let mut e = SoftRender {
-            radius: 50.0,
-            slider: slider::State::new(),
-            texture: Vec::new(),
-            t: Vec::new()
-        };
-
-        e.texture.push(texture);
-                
-        let mut vetexs = Vec::new();
-        let mut texcoords = Vec::new();
-        let mut normals = Vec::new();
-        lines.for_each(|line| {
-            if let Ok(line) = line {
-                if idx % 3 == 0 {
-                    let all: Vec<&str> = line.split(",").collect();
-                    //翻转模型z值
-                    vetexs.push(Vector4f::new_4(all[0].parse::<f32>().unwrap(), all[1].parse::<f32>().unwrap(), -1.0 * all[2].parse::<f32>().unwrap(), 1.0));
-                } else if idx % 3 == 1 {
-                    let all: Vec<&str> = line.split(",").collect();
-                    texcoords.push(Vector2f::new_2(all[0].parse::<f32>().unwrap(), all[1].parse::<f32>().unwrap()));
-                } else {
-                    let all: Vec<&str> = line.split(",").collect();
-                    normals.push(Vector3f::new_3(all[0].parse::<f32>().unwrap(), all[1].parse::<f32>().unwrap(),all[2].parse::<f32>().unwrap()));
+/// --headless --out frame.png --width 512 --height 512 --angle 0 [--env sky.hdr] [--skybox sky.hdr]
+/// [--tonemap none|reinhard|aces] [--exposure 0](EV) [--instances 1]
+fn run_headless(args: &[String]) -> Result<(), String> {
+    let mut out = String::from("frame.png");
+    let mut width = 512;
+    let mut height = 512;
+    let mut angle = 0.0;
+    let mut env: Option<String> = None;
+    let mut skybox: Option<String> = None;
+    let mut tone_mapping = ToneMapping::default();
+    let mut instances = 1;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            //这个程序总是离屏渲染，保留参数兼容原来的用法
+            "--headless" => {}
+            "--out" => out = next_arg(&mut iter, arg)?,
+            "--width" => width = parse_arg(&mut iter, arg)?,
+            "--height" => height = parse_arg(&mut iter, arg)?,
+            "--angle" => angle = parse_arg(&mut iter, arg)?,
+            "--env" => env = Some(next_arg(&mut iter, arg)?),
+            "--skybox" => skybox = Some(next_arg(&mut iter, arg)?),
+            "--tonemap" => {
+                tone_mapping.operator = match next_arg(&mut iter, arg)?.as_str() {
+                    "none" => ToneMapOperator::None,
+                    "reinhard" => ToneMapOperator::Reinhard,
+                    "aces" => ToneMapOperator::Aces,
+                    v => return Err(format!("invalid value `{}` for `{}`", v, arg)),
                 }
-
-                idx += 1;
             }
-        });
-
-        for _ in 0..vetexs.len() / 3 {
-            let mut t = Triangle::new();
-            let mut v = Vec::new();
-            let mut p = Vec::new();
-            let mut n = Vec::new();
-            v.push(vetexs.remove(0));
-            v.push(vetexs.remove(0));
-            v.push(vetexs.remove(0));
-
-            p.push(texcoords.remove(0));
-            p.push(texcoords.remove(0));
-            p.push(texcoords.remove(0));
-
-            n.push(normals.remove(0));
-            n.push(normals.remove(0));
-            n.push(normals.remove(0));
-
-
-
-            t.set_origin_vertexs(v);
-            t.set_tex_coords(p);
-            t.set_normal(n);
-            t.set_render_type(common::triangle::RenderType::TEXTURE);
-            e.t.push(t);
+            "--exposure" => tone_mapping.set_exposure_ev(parse_arg(&mut iter, arg)?),
+            "--instances" => instances = parse_arg(&mut iter, arg)?,
+            _ => return Err(format!("unknown argument `{}`", arg)),
         }
-
-        e
     }
 
-    fn title(&self) -> String {
-        String::from("mini3d-rs")
+    if width <= 0 || height <= 0 {
+        return Err(format!("invalid size {}x{}", width, height));
     }
 
-    fn update(&mut self, message: Message) {
-        match message {
-            Message::RadiusChanged(radius) => {
-                self.radius = radius;
-            }
-        }
+    let scene = load_scene().map_err(|e| e.to_string())?;
+    let graph = build_scene_graph(scene.meshes, angle, instances);
+    let mut rasterizer = build_rasterizer(angle, width as f32 / height as f32);
+    rasterizer.set_materials(scene.materials);
+    if let Some(path) = env {
+        let environment = load_environment(Path::new(&path)).map_err(|e| e.to_string())?;
+        rasterizer.set_environment(Some(Arc::new(environment)));
     }
+    if let Some(path) = skybox {
+        let map = load_environment_map(Path::new(&path)).map_err(|e| e.to_string())?;
+        rasterizer.set_skybox(Some(Arc::new(Skybox::Equirect(map))));
+    }
+    //阴影贴图用世界空间的三角形生成
+    rasterizer.set_model(Mat4x4f::identity());
+    rasterizer.compute_mvp();
+    rasterizer.update_shadow_maps(&mut graph.world_triangles());
+    let mut renderer = Renderer::new(width, height);
+    renderer.set_tone_mapping(tone_mapping);
+    renderer.draw_skybox(&rasterizer);
+    renderer.draw_scene(&mut rasterizer, &graph, &scene.textures);
+    renderer.save(&out).map_err(|e| format!("{}: {}", out, e))
+}
 
-    fn view(&mut self) -> Element<Message> {
-		let mut image = vec![0 as u8; 512*512*4];
-        // let mut triangle1 = Triangle::new();
-        // triangle1.set_colors(vec![
-        //     Color3f::new_3(1.0, 0.0, 0.0), 
-        //     Color3f::new_3(1.0, 0.0, 0.0),
-        //     Color3f::new_3(1.0, 0.0, 0.0),
-        // ]);
-
-        // triangle1.set_vertexs(vec![
-        //     Vector4f::new_4(2.0, -2.0, -0.1, 1.0),
-        //     Vector4f::new_4(0.0, 2.0, -20.0, 1.0),
-        //     Vector4f::new_4(-3.0, -2.0, -5.0, 1.0)
-        // ]);
-
-        // let mut triangle2 = Triangle::new();
-        // triangle2.set_colors(vec![
-        //     Color3f::new_3(0.0, 1.0, 0.0), 
-        //     Color3f::new_3(0.0, 1.0, 0.0),
-        //     Color3f::new_3(0.0, 1.0, 0.0),
-        // ]);
-
-        // triangle2.set_vertexs(vec![
-        //     Vector4f::new_4(1.0, -2.0, -5.0, 1.0),
-        //     Vector4f::new_4(0.0, 4.0, -10.0, 1.0),
-        //     Vector4f::new_4(-3.0, -2.0, -1.0, 1.0)
-        // ]);
-
-        let mut rasterizer = Rasterizer::new();
-        rasterizer.set_model(get_model_matrix((self.radius as f32 - 50.0) * 180.0 / 50.0));
-        //rasterizer.set_model(get_model_matrix(0.0));
-
-        rasterizer.set_view(get_view_matrix(
-            Vector4f::new_4(0.0, 0.0, 2.0, 1.0),
-            Vector4f::new_4(0.0, 0.0, 0.0, 1.0),
-            Vector4f::new_4(0.0, 1.0, 0.0, 1.0)
-        ));
-
-        rasterizer.set_projection(get_presp_projection_matrix(60.0, 1.0, -0.1, -50.0));
-        rasterizer.compute_mvp();
-        rasterizer.set_eye_pos(Vector3f::new_3(0.0, 0.0, 2.0));
-        rasterizer.set_lights(vec![
-            Light::new(            
-                Vector3f::new_3(20.0, 20.0, -20.0),
-                Vector3f::new_3(500.0, 500.0, 500.0)
-            ),
-            Light::new(            
-                Vector3f::new_3(20.0, -20.0, 0.0),
-                Vector3f::new_3(500.0, 500.0, 500.0)
-            ),
-            ]
-        );
-
-        let mut zbuf: Vec<f32> = vec![-51.0; 512*512];
-        let res: Vec<Vec<RenderResult>> = self.t.par_iter_mut()
-        .map(|x| {
-            draw_trangle_map(&rasterizer, 512, 512, x, &self.texture)
-        })
-        .collect();
-
-        for tmp in res {
-            for r in tmp {
-                if r.z > zbuf[r.idx as usize] {
-                    image[r.idx as usize * 4] = r.b;
-                    image[r.idx as usize * 4 + 1] = r.g;
-                    image[r.idx as usize * 4 + 2] = r.r;
-                    image[r.idx as usize * 4 + 3] = 255;
-                    zbuf[r.idx as usize] = r.z;
-                }
-            }
+/// 把示例网格放进场景图：根节点绕y轴旋转angle度，count个实例排成一排，共用同一组网格
+fn build_scene_graph(meshes: Vec<Mesh>, angle: f32, count: usize) -> Scene {
+    let mut scene = Scene::new();
+    let ids: Vec<usize> = meshes.into_iter().map(|m| scene.add_mesh(m)).collect();
+
+    let mut root = Node::new("root");
+    root.set_rotation(Quaternion::from_axis_angle(&Vector3f::new_3(0.0, 1.0, 0.0), angle));
+    let root = scene.add_node(root, None);
+
+    let count = count.max(1);
+    let spacing = 1.2;
+    let scale = (2.0 / (spacing * count as f32)).min(1.0);
+    for i in 0..count {
+        let mut node = Node::new(&format!("spot{}", i));
+        if count > 1 {
+            let x = (i as f32 - (count - 1) as f32 / 2.0) * spacing * scale;
+            node.set_translation(Vector3f::new_3(x, 0.0, 0.0));
+            node.set_rotation(Quaternion::from_euler(&Vector3f::new_3(0.0, 30.0 * i as f32, 0.0)));
+            node.set_scale(Vector3f::new_3(scale, scale, scale));
         }
-        // for t in self.t.iter_mut() {
-        //     draw_trangle(&rasterizer, &mut image, &mut zbuf,-0.1, -50.0, 512, 512, t, &self.texture);
-        // }
-        //draw_trangle(&rasterizer, &mut image, &mut zbuf,-0.1, -50.0, 256, 256, triangle1);
-        //draw_trangle(&rasterizer, &mut image, &mut zbuf , -0.1, -50.0, 256, 256, triangle2);
-
-        // let mut imgbuf = image::ImageBuffer::new(512, 512);
+        let instance = scene.add_node(node, Some(root));
 
-        // // Iterate over the coordinates and pixels of the image
-        // for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        //     *pixel = image::Rgb([image[((512*y+x)*4+2) as usize], image[((512*y+x)*4+1) as usize], image[((512*y+x)*4) as usize]]);
-        // }
-        // imgbuf.save("b.png").unwrap();
-
-        let handle = Handle::from_pixels(512, 512, image);
-        let content = Column::new()
-            .padding(20)
-            .spacing(20)
-            .max_width(500)
-            .align_items(Alignment::Center)
-            .push(Text::new(format!("Radius: {:.2}", self.radius)))
-			.push(Image::new(handle).width(Length::Fill).height(Length::Fill))
-            .push(
-                Slider::new(
-                    &mut self.slider,
-                    1.0..=100.0,
-                    self.radius,
-                    Message::RadiusChanged,
-                )
-                .step(0.01),
-            );
-
-        Container::new(content)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .center_x()
-            .center_y()
-            .into()
-    }
-
-    fn background_color(&self) -> iced::Color {
-        iced::Color::WHITE
+        for &m in ids.iter() {
+            let mut part = Node::new("mesh");
+            part.set_mesh(Some(m));
+            scene.add_node(part, Some(instance));
+        }
     }
 
-    fn scale_factor(&self) -> f64 {
-        1.0
-    }
+    scene
+}
 
-    fn should_exit(&self) -> bool {
-        false
-    }
+fn next_arg<'a, I: Iterator<Item = &'a String>>(iter: &mut I, name: &str) -> Result<String, String> {
+    iter.next().cloned().ok_or_else(|| format!("missing value for `{}`", name))
+}
 
-    fn run(settings: Settings<()>) -> Result<(), iced::Error>
-    where
-        Self: 'static + Sized,
-    {
-        <Self as iced::Application>::run(settings)
-    }
+fn parse_arg<'a, T: std::str::FromStr, I: Iterator<Item = &'a String>>(iter: &mut I, name: &str) -> Result<T, String> {
+    let v = next_arg(iter, name)?;
+    v.parse::<T>().map_err(|_| format!("invalid value `{}` for `{}`", v, name))
 }
@@ -1,113 +1,432 @@
-use std::ops::Add;
-use std::ops::Sub;
-use std::ops::Mul;
+use std::fmt;
+use std::ops::{Add, Div, Index, IndexMut, Mul, Neg, Sub};
+
 use crate::math::vector::Vector;
 
+use super::simd::SimdScalar;
 
 pub type Mat4x4f = Matrix<4, 4, f32>;
 
-#[derive(Clone)]
+/// 按行存储，m[i][j]是第i行第j列，16字节对齐
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, align(16))]
 pub struct Matrix<const M: usize, const N: usize, T> where T: Clone{
-    pub m: Vec<Vec<T>>
+    pub m: [[T; N]; M]
 }
 
 impl<const M: usize, const N: usize, T> Matrix<M, N, T> where T: Default + Clone {
     pub fn new() -> Matrix<M, N, T> {
         Matrix {
-            m: vec![vec![T::default(); N]; M]
+            m: std::array::from_fn(|_| std::array::from_fn(|_| T::default()))
         }
     }
 
+    /// 行数和列数必须是M和N
     pub fn new_val(v: Vec<Vec<T>>) -> Matrix<M, N, T> {
+        assert!(v.len() == M && v.iter().all(|r| r.len() == N), "expected a {}x{} matrix", M, N);
         Matrix {
-            m: v
+            m: std::array::from_fn(|i| std::array::from_fn(|j| v[i][j].clone()))
         }
     }
 }
 
-impl<const M: usize, const N: usize, T> Matrix<M, N, T> where T: Default + Clone + Mul<Output = T> + Add<Output = T>{
-    pub fn apply(&self, v: &Vector<N, T>) -> Vector<M, T> {
-        let mut r = Vec::new();
-        for i in 0..M {
-            r.push({
-                let mut cur = T::default();
-                for j in 0..N {
-                    cur = cur + self.m[i][j].clone() * v.v[j].clone();
-                }
+/// 全0矩阵，单位矩阵用identity
+impl<const M: usize, const N: usize, T> Default for Matrix<M, N, T> where T: Default + Clone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-                cur
-            });
-        }
+impl<const M: usize, const N: usize, T> Matrix<M, N, T> where T: Clone {
+    pub fn from_array(m: [[T; N]; M]) -> Matrix<M, N, T> {
+        Matrix { m }
+    }
+}
+
+impl<const M: usize, const N: usize, T> From<[[T; N]; M]> for Matrix<M, N, T> where T: Clone {
+    fn from(m: [[T; N]; M]) -> Self {
+        Matrix { m }
+    }
+}
 
-        Vector { v: r }
+impl<const M: usize, const N: usize, T> Matrix<M, N, T> where T: SimdScalar {
+    /// 4x4的f32矩阵走SIMD
+    pub fn apply(&self, v: &Vector<N, T>) -> Vector<M, T> {
+        Vector { v: T::mat_vec(&self.m, &v.v) }
     }
 }
 
 impl<const M: usize, const N: usize> Matrix<M, N, f32> {
     pub fn identity() -> Matrix<M, N, f32> {
-        let mut index = 0;
-        let mut m = vec![vec![f32::default(); N]; M];
-        for i in 0..M {
-            m[i][index] += 1.0;
-            index += 1;
-        }
         Matrix {
-            m: m
+            m: std::array::from_fn(|i| std::array::from_fn(|j| if i == j { 1.0 } else { 0.0 }))
         }
     }
 }
 
-impl<const M: usize, const N: usize, T> Add for Matrix<M, N, T> where T: Default + Add<Output = T> + Clone {
+impl<const N: usize, T> Matrix<N, N, T> where T: SimdScalar {
+    pub fn mul(&self, rhs: &Matrix<N, N, T>) -> Matrix<N, N, T> {
+        product(self, rhs)
+    }
+}
+
+/// M x K 乘 K x N
+fn product<const M: usize, const K: usize, const N: usize, T>(a: &Matrix<M, K, T>, b: &Matrix<K, N, T>) -> Matrix<M, N, T>
+    where T: SimdScalar {
+    Matrix { m: T::mat_mat(&a.m, &b.m) }
+}
+
+impl<const M: usize, const N: usize, T> Index<usize> for Matrix<M, N, T> where T: Clone {
+    type Output = [T; N];
+
+    fn index(&self, i: usize) -> &[T; N] {
+        &self.m[i]
+    }
+}
+
+impl<const M: usize, const N: usize, T> IndexMut<usize> for Matrix<M, N, T> where T: Clone {
+    fn index_mut(&mut self, i: usize) -> &mut [T; N] {
+        &mut self.m[i]
+    }
+}
+
+impl<const M: usize, const N: usize, T> Add for Matrix<M, N, T> where T: Add<Output = T> + Clone {
     type Output = Matrix<M, N, T>;
+
     fn add(self, rhs: Self) -> Self::Output {
-        let mut m = vec![vec![T::default(); N]; M];
-        for i in 0..M {
-            for j in 0..N {
-                m[i][j] = self.m[i][j].clone() + rhs.m[i][j].clone();
-            }
-            
-        }
-        Matrix {
-            m: m
-        }
+        &self + &rhs
     }
 }
 
-impl<const M: usize, const N: usize, T> Sub for Matrix<M, N, T> where T: Default + Sub<Output = T> + Clone {
+impl<const M: usize, const N: usize, T> Add for &Matrix<M, N, T> where T: Add<Output = T> + Clone {
+    type Output = Matrix<M, N, T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Matrix { m: std::array::from_fn(|i| std::array::from_fn(|j| self.m[i][j].clone() + rhs.m[i][j].clone())) }
+    }
+}
+
+impl<const M: usize, const N: usize, T> Sub for Matrix<M, N, T> where T: Sub<Output = T> + Clone {
     type Output = Matrix<M, N, T>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        let mut m = vec![vec![T::default(); N]; M];
-        for i in 0..M {
-            for j in 0..N {
-                m[i][j] = self.m[i][j].clone() - rhs.m[i][j].clone();
+        &self - &rhs
+    }
+}
+
+impl<const M: usize, const N: usize, T> Sub for &Matrix<M, N, T> where T: Sub<Output = T> + Clone {
+    type Output = Matrix<M, N, T>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Matrix { m: std::array::from_fn(|i| std::array::from_fn(|j| self.m[i][j].clone() - rhs.m[i][j].clone())) }
+    }
+}
+
+impl<const M: usize, const N: usize, T> Neg for Matrix<M, N, T> where T: Neg<Output = T> + Clone {
+    type Output = Matrix<M, N, T>;
+
+    fn neg(self) -> Self::Output {
+        -&self
+    }
+}
+
+impl<const M: usize, const N: usize, T> Neg for &Matrix<M, N, T> where T: Neg<Output = T> + Clone {
+    type Output = Matrix<M, N, T>;
+
+    fn neg(self) -> Self::Output {
+        Matrix { m: std::array::from_fn(|i| std::array::from_fn(|j| -self.m[i][j].clone())) }
+    }
+}
+
+/// 矩阵乘法 (M x K) * (K x N)
+impl<const M: usize, const K: usize, const N: usize, T> Mul<&Matrix<K, N, T>> for &Matrix<M, K, T>
+    where T: SimdScalar {
+    type Output = Matrix<M, N, T>;
+
+    fn mul(self, rhs: &Matrix<K, N, T>) -> Self::Output {
+        product(self, rhs)
+    }
+}
+
+impl<const M: usize, const K: usize, const N: usize, T> Mul<Matrix<K, N, T>> for Matrix<M, K, T>
+    where T: SimdScalar {
+    type Output = Matrix<M, N, T>;
+
+    fn mul(self, rhs: Matrix<K, N, T>) -> Self::Output {
+        product(&self, &rhs)
+    }
+}
+
+/// 变换向量，等于apply
+impl<const M: usize, const N: usize, T> Mul<&Vector<N, T>> for &Matrix<M, N, T>
+    where T: SimdScalar {
+    type Output = Vector<M, T>;
+
+    fn mul(self, rhs: &Vector<N, T>) -> Self::Output {
+        self.apply(rhs)
+    }
+}
+
+impl<const M: usize, const N: usize, T> Mul<Vector<N, T>> for Matrix<M, N, T>
+    where T: SimdScalar {
+    type Output = Vector<M, T>;
+
+    fn mul(self, rhs: Vector<N, T>) -> Self::Output {
+        self.apply(&rhs)
+    }
+}
+
+/// 行列式为0(或者接近0)的矩阵没有逆矩阵
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SingularMatrixError;
+
+impl fmt::Display for SingularMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "matrix is singular")
+    }
+}
+
+impl std::error::Error for SingularMatrixError {}
+
+impl<const M: usize, const N: usize, T> Matrix<M, N, T> where T: Clone {
+    pub fn transpose(&self) -> Matrix<N, M, T> {
+        Matrix { m: std::array::from_fn(|j| std::array::from_fn(|i| self.m[i][j].clone())) }
+    }
+}
+
+impl<const N: usize, T> Matrix<N, N, T> where T: Default + Clone + PartialOrd + From<f32>
+    + Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Div<Output = T> + Neg<Output = T> {
+    /// 部分主元的高斯消元
+    pub fn determinant(&self) -> T {
+        let mut a = self.m.clone();
+        let mut det = T::from(1.0);
+        for col in 0..N {
+            let pivot = match pivot_row(&a, col) {
+                Some(p) if a[p][col] != T::default() => p,
+                _ => return T::default(),
+            };
+            if pivot != col {
+                a.swap(pivot, col);
+                det = -det;
+            }
+
+            det = det * a[col][col].clone();
+            let (top, bottom) = a.split_at_mut(col + 1);
+            let p = &top[col];
+            for row in bottom.iter_mut() {
+                let f = row[col].clone() / p[col].clone();
+                for (x, y) in row.iter_mut().zip(p.iter()).skip(col) {
+                    *x = x.clone() - f.clone() * y.clone();
+                }
             }
-            
-        }
-        Matrix {
-            m: m
         }
+        det
     }
-}
 
-impl<const N: usize, T> Matrix<N, N, T> where T:Default + Clone + Mul<Output = T> + Add<Output = T> {
-    pub fn mul(&self, rhs: &Matrix<N, N, T>) -> Matrix<N, N, T> {
-        let mut m = vec![vec![T::default(); N]; N];
-        for i in 0..N {
-            for j in 0..N {
-                m[i][j] = {
-                    let mut r = T::default();
-                    for k in 0..N {
-                        r = r + self.m[i][k].clone() * rhs.m[k][j].clone();
-                    }
-                    r
+    /// 高斯-约当消元，主元相对矩阵里最大的元素太小时认为是奇异矩阵
+    pub fn inverse(&self) -> Result<Matrix<N, N, T>, SingularMatrixError> {
+        let mut scale = T::default();
+        for row in self.m.iter() {
+            for v in row.iter() {
+                let v = abs(v.clone());
+                if v > scale {
+                    scale = v;
                 }
             }
-            
         }
-        Matrix {
-            m: m
+        let epsilon = scale * T::from(N as f32 * f32::EPSILON);
+
+        let mut a = self.m.clone();
+        let mut inv = Matrix::<N, N, T>::new().m;
+        for (i, row) in inv.iter_mut().enumerate() {
+            row[i] = T::from(1.0);
+        }
+
+        for col in 0..N {
+            let pivot = match pivot_row(&a, col) {
+                Some(p) if abs(a[p][col].clone()) > epsilon => p,
+                _ => return Err(SingularMatrixError),
+            };
+            a.swap(pivot, col);
+            inv.swap(pivot, col);
+
+            let p = a[col][col].clone();
+            for k in 0..N {
+                a[col][k] = a[col][k].clone() / p.clone();
+                inv[col][k] = inv[col][k].clone() / p.clone();
+            }
+
+            for row in 0..N {
+                if row == col {
+                    continue;
+                }
+                let f = a[row][col].clone();
+                for k in 0..N {
+                    a[row][k] = a[row][k].clone() - f.clone() * a[col][k].clone();
+                    inv[row][k] = inv[row][k].clone() - f.clone() * inv[col][k].clone();
+                }
+            }
+        }
+
+        Ok(Matrix { m: inv })
+    }
+}
+
+fn abs<T: Default + PartialOrd + Neg<Output = T>>(v: T) -> T {
+    if v < T::default() { -v } else { v }
+}
+
+/// col列中从col行往下绝对值最大的行
+fn pivot_row<const N: usize, T: Default + Clone + PartialOrd + Neg<Output = T>>(a: &[[T; N]], col: usize) -> Option<usize> {
+    let mut best: Option<(usize, T)> = None;
+    for (row, r) in a.iter().enumerate().skip(col) {
+        let v = abs(r[col].clone());
+        if best.as_ref().is_none_or(|(_, b)| v > *b) {
+            best = Some((row, v));
         }
     }
+    best.map(|(row, _)| row)
 }
 
+#[cfg(test)]
+//引用和值的运算符impl都要测
+#[allow(clippy::op_ref)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    fn sample() -> Mat4x4f {
+        Mat4x4f::from_array([
+            [0.5, -1.0, 2.0, 3.0],
+            [1.5, 0.25, -0.75, -2.0],
+            [-3.0, 4.0, 0.125, 1.0],
+            [0.0, 0.5, -0.5, 1.0],
+        ])
+    }
+
+    #[test]
+    fn ops_match_methods() {
+        let a = sample();
+        let b = a.transpose();
+        let v = Vector4f::new_4(1.0, -2.0, 0.5, 1.0);
+
+        assert_eq!(&a * &v, a.apply(&v));
+        assert_eq!(a * v, a.apply(&v));
+        assert_eq!(&a * &b, Mat4x4f::mul(&a, &b));
+        assert_eq!(a * b, Mat4x4f::mul(&a, &b));
+
+        let sum = &a + &b;
+        let diff = &a - &b;
+        let neg = -a;
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(sum[i][j], a.m[i][j] + b.m[i][j]);
+                assert_eq!(diff[i][j], a.m[i][j] - b.m[i][j]);
+                assert_eq!(neg[i][j], -a.m[i][j]);
+            }
+        }
+        assert_eq!(a + b, sum);
+        assert_eq!(a - b, diff);
+        assert_eq!(Mat4x4f::new_val(a.m.iter().map(|r| r.to_vec()).collect()), a);
+    }
+
+    #[test]
+    fn vector_ops_match_methods() {
+        let a = Vector4f::new_4(1.0, -2.0, 0.5, 1.0);
+        let b = Vector4f::new_4(0.25, 3.0, -1.5, 2.0);
+
+        assert_eq!(&a + &b, Vector4f::add(&a, &b));
+        assert_eq!(a + b, Vector4f::add(&a, &b));
+        assert_eq!(&a - &b, Vector4f::sub(&a, &b));
+        assert_eq!(a - b, Vector4f::sub(&a, &b));
+        assert_eq!(-a, Vector4f::sub(&Vector4f::new(), &a));
+        assert_eq!(a * 2.0, Vector4f::add(&a, &a));
+        assert_eq!(a[1], a.y());
+    }
+
+    #[test]
+    fn generic_element_types() {
+        let m = Matrix::<2, 3, i32>::from_array([[1, 2, 3], [4, 5, 6]]);
+        let n = Matrix::<3, 2, i32>::from_array([[1, 0], [0, 1], [1, 1]]);
+        assert_eq!(m.apply(&Vector::from_array([1, 1, 1])).v, [6, 15]);
+        assert_eq!((&m * &n).m, [[4, 5], [10, 11]]);
+        assert_eq!(m.transpose().m, [[1, 4], [2, 5], [3, 6]]);
+    }
+
+    fn assert_near(a: &Mat4x4f, b: &Mat4x4f, eps: f32) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a[i][j] - b[i][j]).abs() < eps, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn determinant() {
+        //按第一行展开算出来的精确值
+        assert!((sample().determinant() - 24.171875).abs() < 1e-4);
+        assert_eq!(Mat4x4f::identity().determinant(), 1.0);
+        assert_eq!(sample().transpose().determinant(), sample().determinant());
+
+        //交换两行变号，上三角矩阵是对角线的乘积
+        let mut m = sample();
+        m.m.swap(0, 2);
+        assert!((m.determinant() + 24.171875).abs() < 1e-4);
+        let t = Matrix::<3, 3, f32>::from_array([[2.0, 5.0, -1.0], [0.0, -3.0, 7.0], [0.0, 0.0, 0.5]]);
+        assert_eq!(t.determinant(), -3.0);
+
+        //第一列全是0时不能除以主元
+        let z = Matrix::<2, 2, f64>::from_array([[0.0, 1.0], [0.0, 2.0]]);
+        assert_eq!(z.determinant(), 0.0);
+        let mut dup = sample();
+        dup.m[3] = dup.m[1];
+        assert!(dup.determinant().abs() < 1e-5);
+    }
+
+    #[test]
+    fn inverse_of_regular_matrix() {
+        let a = sample();
+        let inv = a.inverse().unwrap();
+        assert_near(&(a * inv), &Mat4x4f::identity(), 1e-5);
+        assert_near(&(inv * a), &Mat4x4f::identity(), 1e-5);
+        assert!((inv.determinant() * a.determinant() - 1.0).abs() < 1e-5);
+
+        //平移的逆是反方向平移，需要选主元才能消元
+        let t = Mat4x4f::from_array([
+            [0.0, 1.0, 0.0, 2.0],
+            [1.0, 0.0, 0.0, -3.0],
+            [0.0, 0.0, 1.0, 4.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let expected = Mat4x4f::from_array([
+            [0.0, 1.0, 0.0, 3.0],
+            [1.0, 0.0, 0.0, -2.0],
+            [0.0, 0.0, 1.0, -4.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(t.inverse().unwrap(), expected);
+
+        //判断奇异用的是相对误差，整体很小的矩阵也能求逆
+        let small = Matrix::<2, 2, f64>::from_array([[1e-9, 0.0], [0.0, 2e-9]]);
+        let inv = small.inverse().unwrap();
+        assert!((inv[0][0] - 1e9).abs() < 1e-3 && (inv[1][1] - 5e8).abs() < 1e-3);
+    }
+
+    #[test]
+    fn inverse_of_singular_matrix() {
+        assert_eq!(Mat4x4f::new().inverse(), Err(SingularMatrixError));
+
+        let mut dup = sample();
+        dup.m[3] = dup.m[1];
+        assert_eq!(dup.inverse(), Err(SingularMatrixError));
+
+        //第三行是前两行的和，消元后只剩舍入误差
+        let mut sum = sample();
+        sum.m[2] = std::array::from_fn(|j| sum.m[0][j] + sum.m[1][j]);
+        assert_eq!(sum.inverse(), Err(SingularMatrixError));
+
+        let scaled = Matrix::<2, 2, f32>::from_array([[1e6, 2e6], [3e6, 6e6]]);
+        assert_eq!(scaled.inverse(), Err(SingularMatrixError));
+        assert_eq!(SingularMatrixError.to_string(), "matrix is singular");
+    }
+}
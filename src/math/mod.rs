@@ -1,3 +1,6 @@
 pub mod utils;
 pub mod matrix;
-pub mod vector;
\ No newline at end of file
+pub mod vector;
+pub mod quaternion;
+pub mod transform;
+pub mod simd;
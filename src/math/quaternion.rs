@@ -0,0 +1,248 @@
+use super::matrix::Mat4x4f;
+use super::vector::Vector3f;
+
+/// 单位四元数表示旋转，q = w + xi + yj + zk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// 绕axis旋转angle度，axis不需要是单位向量
+    pub fn from_axis_angle(axis: &Vector3f, angle: f32) -> Quaternion {
+        let l = axis.length();
+        if l <= 0.0 {
+            return Quaternion::identity();
+        }
+
+        let half = std::f32::consts::PI * angle / 180.0 / 2.0;
+        let s = half.sin() / l;
+        Quaternion::new(half.cos(), axis.x() * s, axis.y() * s, axis.z() * s)
+    }
+
+    /// 欧拉角(度)，先绕x轴，再绕y轴，最后绕z轴，即 Rz * Ry * Rx
+    pub fn from_euler(degrees: &Vector3f) -> Quaternion {
+        let qx = Quaternion::from_axis_angle(&Vector3f::new_3(1.0, 0.0, 0.0), degrees.x());
+        let qy = Quaternion::from_axis_angle(&Vector3f::new_3(0.0, 1.0, 0.0), degrees.y());
+        let qz = Quaternion::from_axis_angle(&Vector3f::new_3(0.0, 0.0, 1.0), degrees.z());
+        qz.mul(&qy).mul(&qx)
+    }
+
+    /// from_euler的逆变换，绕y轴的角度在(-90, 90)之间，等于±90时绕x轴的角度取0
+    pub fn to_euler(&self) -> Vector3f {
+        let q = self.normalized();
+        let sy = (2.0 * (q.w * q.y - q.z * q.x)).clamp(-1.0, 1.0);
+        let (x, y, z) = if sy.abs() > 0.99999 {
+            //万向节锁，x和z的旋转合并到z上
+            let z = -2.0 * q.x.atan2(q.w) * sy.signum();
+            (0.0, sy.signum() * std::f32::consts::FRAC_PI_2, z)
+        } else {
+            let x = (2.0 * (q.w * q.x + q.y * q.z)).atan2(1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+            let z = (2.0 * (q.w * q.z + q.x * q.y)).atan2(1.0 - 2.0 * (q.y * q.y + q.z * q.z));
+            (x, sy.asin(), z)
+        };
+        Vector3f::new_3(x.to_degrees(), y.to_degrees(), z.to_degrees())
+    }
+
+    /// 先做rhs的旋转，再做self的旋转
+    pub fn mul(&self, rhs: &Quaternion) -> Quaternion {
+        let (a, b) = (self, rhs);
+        Quaternion::new(
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        )
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn dot(&self, rhs: &Quaternion) -> f32 {
+        self.w * rhs.w + self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Quaternion {
+        let l = self.length();
+        if l <= 0.0 {
+            return Quaternion::identity();
+        }
+        Quaternion::new(self.w / l, self.x / l, self.y / l, self.z / l)
+    }
+
+    /// 球面线性插值，走最短的路径，夹角很小时退化成线性插值
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f32) -> Quaternion {
+        let mut b = *b;
+        let mut d = a.dot(&b);
+        if d < 0.0 {
+            b = Quaternion::new(-b.w, -b.x, -b.y, -b.z);
+            d = -d;
+        }
+
+        let (s0, s1) = if d > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = d.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quaternion::new(
+            a.w * s0 + b.w * s1,
+            a.x * s0 + b.x * s1,
+            a.y * s0 + b.y * s1,
+            a.z * s0 + b.z * s1,
+        ).normalized()
+    }
+
+    /// 旋转一个向量，和to_matrix一样先归一化，不是单位四元数时也不会缩放向量
+    pub fn rotate(&self, v: &Vector3f) -> Vector3f {
+        let q = self.normalized();
+        let p = Quaternion::new(0.0, v.x(), v.y(), v.z());
+        let r = q.mul(&p).mul(&q.conjugate());
+        Vector3f::new_3(r.x, r.y, r.z)
+    }
+
+    pub fn to_matrix(&self) -> Mat4x4f {
+        let Quaternion { w, x, y, z } = self.normalized();
+        Mat4x4f::from_array([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    /// q和-q表示同一个旋转
+    fn same_rotation(a: &Quaternion, b: &Quaternion) -> bool {
+        a.normalized().dot(&b.normalized()).abs() > 1.0 - 1e-5
+    }
+
+    fn assert_degrees(a: &Vector3f, b: &Vector3f) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-2, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        for x in [-170.0, -45.0, 0.0, 30.0, 120.0] {
+            for y in [-89.0, -60.0, 0.0, 15.0, 89.0] {
+                for z in [-135.0, 0.0, 10.0, 179.0] {
+                    let e = Vector3f::new_3(x, y, z);
+                    let q = Quaternion::from_euler(&e);
+                    assert_degrees(&q.to_euler(), &e);
+                    assert!(same_rotation(&Quaternion::from_euler(&q.to_euler()), &q));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn euler_round_trip_at_gimbal_lock() {
+        for y in [90.0, -90.0] {
+            for (x, z) in [(0.0, 0.0), (30.0, 20.0), (-75.0, 110.0), (45.0, -45.0)] {
+                let q = Quaternion::from_euler(&Vector3f::new_3(x, y, z));
+                let e = q.to_euler();
+                //x并到z里，但表示的旋转不变
+                assert_eq!(e.x(), 0.0);
+                assert!((e.y() - y).abs() < 1e-2, "{:?}", e);
+                assert!(same_rotation(&Quaternion::from_euler(&e), &q), "{} {} {} -> {:?}", x, y, z, e);
+            }
+        }
+    }
+
+    #[test]
+    fn euler_order_is_x_then_y_then_z() {
+        let q = Quaternion::from_euler(&Vector3f::new_3(90.0, 90.0, 0.0));
+        //x轴转90度把y转到z，再绕y转90度把z转到x
+        let v = q.rotate(&Vector3f::new_3(0.0, 1.0, 0.0));
+        assert!((v.x() - 1.0).abs() < 1e-6 && v.y().abs() < 1e-6 && v.z().abs() < 1e-6, "{:?}", v);
+    }
+
+    #[test]
+    fn rotate_matches_matrix() {
+        let q = Quaternion::from_euler(&Vector3f::new_3(25.0, -40.0, 70.0));
+        let v = Vector3f::new_3(1.0, -2.0, 0.5);
+        let r = q.rotate(&v);
+        let m = q.to_matrix().apply(&Vector4f::new_4(v.x(), v.y(), v.z(), 1.0));
+        for i in 0..3 {
+            assert!((r[i] - m[i]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rotate_normalizes() {
+        let q = Quaternion::from_euler(&Vector3f::new_3(25.0, -40.0, 70.0));
+        let scaled = Quaternion::new(q.w * 3.0, q.x * 3.0, q.y * 3.0, q.z * 3.0);
+        let v = Vector3f::new_3(1.0, -2.0, 0.5);
+        let (a, b) = (q.rotate(&v), scaled.rotate(&v));
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+        assert!((b.length() - v.length()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn slerp_endpoints_and_constant_speed() {
+        let z = Vector3f::new_3(0.0, 0.0, 1.0);
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(&z, 90.0);
+
+        assert!(same_rotation(&Quaternion::slerp(&a, &b, 0.0), &a));
+        assert!(same_rotation(&Quaternion::slerp(&a, &b, 1.0), &b));
+        for t in [0.25, 0.5, 0.75] {
+            let q = Quaternion::slerp(&a, &b, t);
+            assert!(same_rotation(&q, &Quaternion::from_axis_angle(&z, 90.0 * t)), "t = {}", t);
+            assert!((q.length() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn slerp_takes_shortest_path() {
+        let z = Vector3f::new_3(0.0, 0.0, 1.0);
+        let a = Quaternion::from_axis_angle(&z, 10.0);
+        //-b和b是同一个旋转，插值不能绕远路
+        let b = Quaternion::from_axis_angle(&z, 50.0);
+        let neg_b = Quaternion::new(-b.w, -b.x, -b.y, -b.z);
+        let q = Quaternion::slerp(&a, &neg_b, 0.5);
+        assert!(same_rotation(&q, &Quaternion::from_axis_angle(&z, 30.0)));
+    }
+
+    #[test]
+    fn slerp_nearly_equal_falls_back_to_lerp() {
+        let z = Vector3f::new_3(0.0, 0.0, 1.0);
+        let a = Quaternion::from_axis_angle(&z, 20.0);
+        let b = Quaternion::from_axis_angle(&z, 20.5);
+        let q = Quaternion::slerp(&a, &b, 0.5);
+        assert!((q.length() - 1.0).abs() < 1e-6);
+        assert!(same_rotation(&q, &Quaternion::from_axis_angle(&z, 20.25)));
+    }
+}
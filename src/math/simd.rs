@@ -0,0 +1,230 @@
+// Vector4f和Mat4x4f的SSE实现，矩阵按行存储
+// 累加顺序和标量版本一致，结果逐位相同；非x86_64平台使用标量版本
+
+use std::ops::{Add, Mul};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Matrix和Vector的元素类型，决定矩阵乘法和点积怎么计算
+/// 默认是逐元素累加的标量实现，f32在4x4时改用SSE
+pub trait SimdScalar: Default + Copy + Add<Output = Self> + Mul<Output = Self> {
+    fn mat_vec<const M: usize, const N: usize>(m: &[[Self; N]; M], v: &[Self; N]) -> [Self; M] {
+        scalar_mat_vec(m, v)
+    }
+
+    fn mat_mat<const M: usize, const K: usize, const N: usize>(a: &[[Self; K]; M], b: &[[Self; N]; K]) -> [[Self; N]; M] {
+        scalar_mat_mat(a, b)
+    }
+
+    fn dot<const N: usize>(a: &[Self; N], b: &[Self; N]) -> Self {
+        scalar_dot(a, b)
+    }
+}
+
+impl SimdScalar for f64 {}
+
+impl SimdScalar for i32 {}
+
+/// M和N是常量，分支在编译时就确定了
+impl SimdScalar for f32 {
+    fn mat_vec<const M: usize, const N: usize>(m: &[[f32; N]; M], v: &[f32; N]) -> [f32; M] {
+        if M == 4 && N == 4 {
+            if let (Ok(a), Ok(x)) = (<&[f32; 16]>::try_from(m.as_flattened()), <&[f32; 4]>::try_from(&v[..])) {
+                let mut r = [0.0; M];
+                r.copy_from_slice(&mat4_mul_vec4(a, x));
+                return r;
+            }
+        }
+        scalar_mat_vec(m, v)
+    }
+
+    fn mat_mat<const M: usize, const K: usize, const N: usize>(a: &[[f32; K]; M], b: &[[f32; N]; K]) -> [[f32; N]; M] {
+        if M == 4 && K == 4 && N == 4 {
+            if let (Ok(x), Ok(y)) = (<&[f32; 16]>::try_from(a.as_flattened()), <&[f32; 16]>::try_from(b.as_flattened())) {
+                let mut r = [[0.0; N]; M];
+                r.as_flattened_mut().copy_from_slice(&mat4_mul_mat4(x, y));
+                return r;
+            }
+        }
+        scalar_mat_mat(a, b)
+    }
+
+    fn dot<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+        if let (Ok(x), Ok(y)) = (<&[f32; 4]>::try_from(&a[..]), <&[f32; 4]>::try_from(&b[..])) {
+            return dot4(x, y);
+        }
+        scalar_dot(a, b)
+    }
+}
+
+fn scalar_mat_vec<const M: usize, const N: usize, T: SimdScalar>(m: &[[T; N]; M], v: &[T; N]) -> [T; M] {
+    std::array::from_fn(|i| scalar_dot(&m[i], v))
+}
+
+fn scalar_mat_mat<const M: usize, const K: usize, const N: usize, T: SimdScalar>(a: &[[T; K]; M], b: &[[T; N]; K]) -> [[T; N]; M] {
+    std::array::from_fn(|i| {
+        std::array::from_fn(|j| {
+            let mut cur = T::default();
+            for k in 0..K {
+                cur = cur + a[i][k] * b[k][j];
+            }
+            cur
+        })
+    })
+}
+
+fn scalar_dot<const N: usize, T: SimdScalar>(a: &[T; N], b: &[T; N]) -> T {
+    let mut cur = T::default();
+    for (x, y) in a.iter().zip(b.iter()) {
+        cur = cur + *x * *y;
+    }
+    cur
+}
+
+/// m * v，m按行展开成16个数
+/// 每行乘v后转置，再按列累加: (((0 + c0) + c1) + c2) + c3，和标量版本逐行累加的顺序相同
+#[cfg(target_arch = "x86_64")]
+pub fn mat4_mul_vec4(m: &[f32; 16], v: &[f32; 4]) -> [f32; 4] {
+    let mut r = [0.0; 4];
+    // SAFETY: x86_64上SSE总是可用，loadu和storeu不要求对齐
+    unsafe {
+        let x = _mm_loadu_ps(v.as_ptr());
+        let p0 = _mm_mul_ps(_mm_loadu_ps(m[0..4].as_ptr()), x);
+        let p1 = _mm_mul_ps(_mm_loadu_ps(m[4..8].as_ptr()), x);
+        let p2 = _mm_mul_ps(_mm_loadu_ps(m[8..12].as_ptr()), x);
+        let p3 = _mm_mul_ps(_mm_loadu_ps(m[12..16].as_ptr()), x);
+
+        //4x4转置，ck的第i个分量是pi的第k个分量
+        let t0 = _mm_unpacklo_ps(p0, p1);
+        let t1 = _mm_unpacklo_ps(p2, p3);
+        let t2 = _mm_unpackhi_ps(p0, p1);
+        let t3 = _mm_unpackhi_ps(p2, p3);
+        let c0 = _mm_movelh_ps(t0, t1);
+        let c1 = _mm_movehl_ps(t1, t0);
+        let c2 = _mm_movelh_ps(t2, t3);
+        let c3 = _mm_movehl_ps(t3, t2);
+
+        let acc = _mm_add_ps(_mm_add_ps(_mm_add_ps(_mm_add_ps(_mm_setzero_ps(), c0), c1), c2), c3);
+        _mm_storeu_ps(r.as_mut_ptr(), acc);
+    }
+    r
+}
+
+/// a * b，结果的第i行 = sum(a[i][k] * b的第k行)
+#[cfg(target_arch = "x86_64")]
+pub fn mat4_mul_mat4(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let mut r = [0.0; 16];
+    // SAFETY: 同上，loadu和storeu不要求对齐
+    unsafe {
+        let rows = [
+            _mm_loadu_ps(b[0..4].as_ptr()),
+            _mm_loadu_ps(b[4..8].as_ptr()),
+            _mm_loadu_ps(b[8..12].as_ptr()),
+            _mm_loadu_ps(b[12..16].as_ptr()),
+        ];
+        for (out, row) in r.chunks_exact_mut(4).zip(a.chunks_exact(4)) {
+            let mut acc = _mm_setzero_ps();
+            for (k, &x) in row.iter().enumerate() {
+                acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(x), rows[k]));
+            }
+            _mm_storeu_ps(out.as_mut_ptr(), acc);
+        }
+    }
+    r
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn dot4(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let mut p = [0.0; 4];
+    // SAFETY: 同上
+    unsafe {
+        _mm_storeu_ps(p.as_mut_ptr(), _mm_mul_ps(_mm_loadu_ps(a.as_ptr()), _mm_loadu_ps(b.as_ptr())));
+    }
+    0.0 + p[0] + p[1] + p[2] + p[3]
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn mat4_mul_vec4(m: &[f32; 16], v: &[f32; 4]) -> [f32; 4] {
+    std::array::from_fn(|i| 0.0 + m[i * 4] * v[0] + m[i * 4 + 1] * v[1] + m[i * 4 + 2] * v[2] + m[i * 4 + 3] * v[3])
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn mat4_mul_mat4(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    std::array::from_fn(|n| {
+        let (i, j) = (n / 4, n % 4);
+        0.0 + a[i * 4] * b[j] + a[i * 4 + 1] * b[4 + j] + a[i * 4 + 2] * b[8 + j] + a[i * 4 + 3] * b[12 + j]
+    })
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn dot4(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    0.0 + a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 固定种子的伪随机数，覆盖不同的数量级和符号
+    fn values(seed: u32, n: usize) -> Vec<f32> {
+        let mut s = seed;
+        (0..n)
+            .map(|_| {
+                s = s.wrapping_mul(1664525).wrapping_add(1013904223);
+                let x = (s >> 8) as f32 / (1u32 << 24) as f32 - 0.5;
+                x * [1e-3, 1.0, 7.0, 1e3][(s & 3) as usize]
+            })
+            .collect()
+    }
+
+    fn mat4(seed: u32) -> [[f32; 4]; 4] {
+        let v = values(seed, 16);
+        std::array::from_fn(|i| std::array::from_fn(|j| v[i * 4 + j]))
+    }
+
+    fn vec4(seed: u32) -> [f32; 4] {
+        let v = values(seed, 4);
+        [v[0], v[1], v[2], v[3]]
+    }
+
+    fn bits<const N: usize>(v: &[f32; N]) -> [u32; N] {
+        v.map(f32::to_bits)
+    }
+
+    #[test]
+    fn mat4_mul_vec4_matches_scalar_bitwise() {
+        for seed in 0..200 {
+            let (m, v) = (mat4(seed), vec4(seed + 1000));
+            assert_eq!(bits(&mat4_mul_vec4(m.as_flattened().try_into().unwrap(), &v)), bits(&scalar_mat_vec(&m, &v)));
+            assert_eq!(bits(&f32::mat_vec(&m, &v)), bits(&scalar_mat_vec(&m, &v)));
+        }
+    }
+
+    #[test]
+    fn mat4_mul_mat4_matches_scalar_bitwise() {
+        for seed in 0..200 {
+            let (a, b) = (mat4(seed), mat4(seed + 1000));
+            let expected = scalar_mat_mat(&a, &b);
+            let r = mat4_mul_mat4(a.as_flattened().try_into().unwrap(), b.as_flattened().try_into().unwrap());
+            assert_eq!(bits(&r), bits(expected.as_flattened().try_into().unwrap()));
+            assert_eq!(f32::mat_mat(&a, &b), expected);
+        }
+    }
+
+    #[test]
+    fn dot4_matches_scalar_bitwise() {
+        for seed in 0..200 {
+            let (a, b) = (vec4(seed), vec4(seed + 1000));
+            assert_eq!(dot4(&a, &b).to_bits(), scalar_dot(&a, &b).to_bits());
+        }
+    }
+
+    #[test]
+    fn other_sizes_use_scalar() {
+        let m = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        assert_eq!(f32::mat_vec(&m, &[1.0, 0.5, -1.0]), [-1.0, 0.5]);
+        assert_eq!(i32::mat_vec(&[[1, 2], [3, 4]], &[5, 6]), [17, 39]);
+        assert_eq!(f64::dot(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]), 32.0);
+    }
+}
@@ -0,0 +1,259 @@
+use super::matrix::Mat4x4f;
+use super::quaternion::Quaternion;
+use super::utils::interpolation;
+use super::vector::Vector3f;
+
+pub fn get_translation_matrix(v: &Vector3f) -> Mat4x4f {
+    Mat4x4f::from_array([
+        [1.0, 0.0, 0.0, v.x()],
+        [0.0, 1.0, 0.0, v.y()],
+        [0.0, 0.0, 1.0, v.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+pub fn get_scale_matrix(v: &Vector3f) -> Mat4x4f {
+    Mat4x4f::from_array([
+        [v.x(), 0.0, 0.0, 0.0],
+        [0.0, v.y(), 0.0, 0.0],
+        [0.0, 0.0, v.z(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// 绕x轴旋转angle度
+pub fn get_rotation_x_matrix(angle: f32) -> Mat4x4f {
+    let r = std::f32::consts::PI * angle / 180.0;
+    Mat4x4f::from_array([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, r.cos(), -r.sin(), 0.0],
+        [0.0, r.sin(), r.cos(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// 绕y轴旋转angle度
+pub fn get_rotation_y_matrix(angle: f32) -> Mat4x4f {
+    let r = std::f32::consts::PI * angle / 180.0;
+    Mat4x4f::from_array([
+        [r.cos(), 0.0, r.sin(), 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [-r.sin(), 0.0, r.cos(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// 绕z轴旋转angle度
+pub fn get_rotation_z_matrix(angle: f32) -> Mat4x4f {
+    let r = std::f32::consts::PI * angle / 180.0;
+    Mat4x4f::from_array([
+        [r.cos(), -r.sin(), 0.0, 0.0],
+        [r.sin(), r.cos(), 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// 罗德里格斯公式，绕过原点的axis轴旋转angle度，axis不需要是单位向量
+pub fn get_axis_angle_matrix(axis: &Vector3f, angle: f32) -> Mat4x4f {
+    let l = axis.length();
+    if l <= 0.0 {
+        return Mat4x4f::identity();
+    }
+
+    let (x, y, z) = (axis.x() / l, axis.y() / l, axis.z() / l);
+    let r = std::f32::consts::PI * angle / 180.0;
+    let (c, s) = (r.cos(), r.sin());
+    let t = 1.0 - c;
+    Mat4x4f::from_array([
+        [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+        [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+        [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// 平移、旋转、缩放，对应的矩阵是 T * R * S
+#[derive(Clone, Debug)]
+pub struct Transform {
+    pub translation: Vector3f,
+    pub rotation: Quaternion,
+    pub scale: Vector3f,
+}
+
+impl Transform {
+    pub fn new(translation: Vector3f, rotation: Quaternion, scale: Vector3f) -> Transform {
+        Transform { translation, rotation, scale }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(Vector3f::new(), Quaternion::identity(), Vector3f::new_3(1.0, 1.0, 1.0))
+    }
+
+    pub fn set_translation(&mut self, v: Vector3f) {
+        self.translation = v;
+    }
+
+    pub fn set_rotation(&mut self, q: Quaternion) {
+        self.rotation = q;
+    }
+
+    /// 欧拉角(度)，见Quaternion::from_euler
+    pub fn set_euler(&mut self, degrees: &Vector3f) {
+        self.rotation = Quaternion::from_euler(degrees);
+    }
+
+    pub fn set_scale(&mut self, v: Vector3f) {
+        self.scale = v;
+    }
+
+    pub fn get_matrix(&self) -> Mat4x4f {
+        let (t, s) = (&self.translation, &self.scale);
+        let mut m = self.rotation.to_matrix();
+        for i in 0..3 {
+            for j in 0..3 {
+                m.m[i][j] *= s.v[j];
+            }
+            m.m[i][3] = t.v[i];
+        }
+        m
+    }
+
+    /// 变换一个点
+    pub fn apply_point(&self, p: &Vector3f) -> Vector3f {
+        let s = Vector3f::new_3(p.x() * self.scale.x(), p.y() * self.scale.y(), p.z() * self.scale.z());
+        self.rotation.rotate(&s).add(&self.translation)
+    }
+
+    /// 平移和缩放线性插值，旋转球面插值，用于关键帧动画
+    pub fn interpolate(a: &Transform, b: &Transform, t: f32) -> Transform {
+        let lerp = |x: &Vector3f, y: &Vector3f| {
+            Vector3f::new_3(interpolation(x.x(), y.x(), t), interpolation(x.y(), y.y(), t), interpolation(x.z(), y.z(), t))
+        };
+        Transform::new(
+            lerp(&a.translation, &b.translation),
+            Quaternion::slerp(&a.rotation, &b.rotation, t),
+            lerp(&a.scale, &b.scale),
+        )
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    fn assert_matrix(a: &Mat4x4f, b: &Mat4x4f) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-5, "{:?} != {:?}", a.m, b.m);
+            }
+        }
+    }
+
+    fn assert_point(a: &Vector3f, b: &Vector3f) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn apply(m: &Mat4x4f, p: &Vector3f) -> Vector3f {
+        let r = m.apply(&Vector4f::new_4(p.x(), p.y(), p.z(), 1.0));
+        Vector3f::new_3(r.x(), r.y(), r.z())
+    }
+
+    fn sample() -> Transform {
+        Transform::new(
+            Vector3f::new_3(1.0, -2.0, 3.0),
+            Quaternion::from_euler(&Vector3f::new_3(30.0, -45.0, 60.0)),
+            Vector3f::new_3(2.0, 0.5, -1.5),
+        )
+    }
+
+    #[test]
+    fn builders() {
+        let p = Vector3f::new_3(1.0, 2.0, 3.0);
+        assert_point(&apply(&get_translation_matrix(&Vector3f::new_3(-1.0, 0.5, 2.0)), &p), &Vector3f::new_3(0.0, 2.5, 5.0));
+        assert_point(&apply(&get_scale_matrix(&Vector3f::new_3(2.0, -1.0, 0.5)), &p), &Vector3f::new_3(2.0, -2.0, 1.5));
+
+        //右手坐标系，逆时针旋转：x转到y，y转到z，z转到x
+        let (x, y, z) = (Vector3f::new_3(1.0, 0.0, 0.0), Vector3f::new_3(0.0, 1.0, 0.0), Vector3f::new_3(0.0, 0.0, 1.0));
+        assert_point(&apply(&get_rotation_x_matrix(90.0), &y), &z);
+        assert_point(&apply(&get_rotation_y_matrix(90.0), &z), &x);
+        assert_point(&apply(&get_rotation_z_matrix(90.0), &x), &y);
+        assert_point(&apply(&get_rotation_z_matrix(180.0), &p), &Vector3f::new_3(-1.0, -2.0, 3.0));
+
+        //绕(1,1,1)转120度轮换三个坐标轴
+        let m = get_axis_angle_matrix(&Vector3f::new_3(1.0, 1.0, 1.0), 120.0);
+        assert_point(&apply(&m, &x), &y);
+        assert_point(&apply(&m, &y), &z);
+        assert_point(&apply(&m, &p), &Vector3f::new_3(3.0, 1.0, 2.0));
+        //零向量当作不旋转
+        assert_matrix(&get_axis_angle_matrix(&Vector3f::new(), 45.0), &Mat4x4f::identity());
+    }
+
+    #[test]
+    fn axis_angle_matches_axis_rotations_and_quaternion() {
+        for angle in [-150.0, -30.0, 0.0, 45.0, 90.0, 200.0] {
+            let axes = [
+                (Vector3f::new_3(1.0, 0.0, 0.0), get_rotation_x_matrix(angle)),
+                (Vector3f::new_3(0.0, 2.0, 0.0), get_rotation_y_matrix(angle)),
+                (Vector3f::new_3(0.0, 0.0, 0.5), get_rotation_z_matrix(angle)),
+            ];
+            for (axis, m) in axes.iter() {
+                assert_matrix(&get_axis_angle_matrix(axis, angle), m);
+                assert_matrix(&Quaternion::from_axis_angle(axis, angle).to_matrix(), m);
+            }
+
+            let axis = Vector3f::new_3(-1.0, 2.0, 0.5);
+            assert_matrix(&get_axis_angle_matrix(&axis, angle), &Quaternion::from_axis_angle(&axis, angle).to_matrix());
+        }
+    }
+
+    #[test]
+    fn matrix_is_t_r_s() {
+        let t = sample();
+        let expected = get_translation_matrix(&t.translation)
+            .mul(&t.rotation.to_matrix())
+            .mul(&get_scale_matrix(&t.scale));
+        assert_matrix(&t.get_matrix(), &expected);
+        assert_matrix(&Transform::identity().get_matrix(), &Mat4x4f::identity());
+    }
+
+    #[test]
+    fn apply_point_matches_matrix() {
+        let t = sample();
+        let m = t.get_matrix();
+        for p in [Vector3f::new(), Vector3f::new_3(1.0, 2.0, 3.0), Vector3f::new_3(-0.5, 4.0, -2.0)] {
+            assert_point(&t.apply_point(&p), &apply(&m, &p));
+        }
+
+        //四元数不是单位长度时也一样
+        let mut t = sample();
+        let q = t.rotation;
+        t.set_rotation(Quaternion::new(q.w * 2.0, q.x * 2.0, q.y * 2.0, q.z * 2.0));
+        let p = Vector3f::new_3(1.0, 2.0, 3.0);
+        assert_point(&t.apply_point(&p), &apply(&t.get_matrix(), &p));
+    }
+
+    #[test]
+    fn interpolate() {
+        let z = Vector3f::new_3(0.0, 0.0, 1.0);
+        let a = Transform::new(Vector3f::new_3(0.0, 0.0, 0.0), Quaternion::identity(), Vector3f::new_3(1.0, 1.0, 1.0));
+        let b = Transform::new(Vector3f::new_3(4.0, -2.0, 8.0), Quaternion::from_axis_angle(&z, 90.0), Vector3f::new_3(3.0, 1.0, 0.0));
+
+        assert_matrix(&Transform::interpolate(&a, &b, 0.0).get_matrix(), &a.get_matrix());
+        assert_matrix(&Transform::interpolate(&a, &b, 1.0).get_matrix(), &b.get_matrix());
+
+        let m = Transform::interpolate(&a, &b, 0.5);
+        assert_point(&m.translation, &Vector3f::new_3(2.0, -1.0, 4.0));
+        assert_point(&m.scale, &Vector3f::new_3(2.0, 1.0, 0.5));
+        assert_matrix(&m.rotation.to_matrix(), &get_rotation_z_matrix(45.0));
+    }
+}
@@ -1,5 +1,6 @@
-use std::ops::{Add, Sub};
+use std::ops::{Add, Index, IndexMut, Mul, Neg, Sub};
 
+use super::simd::SimdScalar;
 use super::utils::interpolation;
 
 pub type Vector2f = Vector<2, f32>;
@@ -10,126 +11,122 @@ pub type Color3f = Vector<3, f32>;
 pub type Point3f = Vector<3, f32>;
 pub type Point2f = Vector<2, f32>;
 
-#[derive(Clone, Debug)]
+/// 定长数组存储，复制不需要分配堆内存；16字节对齐，Vector4f可以直接对齐加载到SSE寄存器
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, align(16))]
 pub struct Vector<const N: usize, T> {
-    pub v: Vec<T>
+    pub v: [T; N]
 }
 
 impl<const N: usize, T> Vector<N, T> where T: Default + Clone {
     pub fn new() -> Vector<N, T> {
-        Vector { v: vec![T::default(); N] }
+        Vector { v: std::array::from_fn(|_| T::default()) }
+    }
+}
+
+impl<const N: usize, T> Default for Vector<N, T> where T: Default + Clone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, T> Vector<N, T> {
+    pub fn from_array(v: [T; N]) -> Vector<N, T> {
+        Vector { v }
+    }
+}
+
+impl<const N: usize, T> From<[T; N]> for Vector<N, T> {
+    fn from(v: [T; N]) -> Self {
+        Vector { v }
     }
 }
 
 impl<T> Vector<2, T> where T: Default + Clone {
     pub fn new_2(x: T, y: T) -> Vector<2, T> {
-        Vector { v: vec![x, y] }
+        Vector { v: [x, y] }
     }
 }
 
 impl<T> Vector<3, T> where T: Default + Clone {
     pub fn new_3(x: T, y: T, z: T) -> Vector<3, T> {
-        Vector { v: vec![x, y, z] }
+        Vector { v: [x, y, z] }
     }
 }
 
 impl<T> Vector<4, T> where T: Default + Clone {
     pub fn new_4(x: T, y: T, z: T, w: T) -> Vector<4, T> {
-        Vector { v: vec![x, y, z, w] }
+        Vector { v: [x, y, z, w] }
     }
 }
 
 impl<const N: usize> Vector<N, f32> {
     pub fn length(&self) -> f32 {
-        let mut r = 0.0;
-        for v in &self.v {
-            r += v*v;
-        }
-
-        r.sqrt()
+        self.dot_product(self).sqrt()
     }
 
     pub fn normlize(&mut self) {
         let l = self.length();
-        for i in 0..self.v.len() {
-            self.v[i] = self.v[i] / l;
+        for x in self.v.iter_mut() {
+            *x /= l;
         }
     }
 
     pub fn dot_product(&self, v2: &Vector<N, f32>) -> f32 {
-        let mut r = 0.0;
-        for i in 0..self.v.len() {
-            r += self.v[i] * v2.v[i];
-        }
-
-        r
+        f32::dot(&self.v, &v2.v)
     }
 
     pub fn sub(&self, other: &Vector<N, f32>) -> Vector<N, f32> {
-        let mut r = Vec::new();
-        for i in 0..N {
-            r.push(self.v[i].clone() - other.v[i].clone());
-        }
-
-        Vector {
-            v: r
-        }
+        Vector { v: std::array::from_fn(|i| self.v[i] - other.v[i]) }
     }
 
     pub fn add(&self, rhs: &Vector<N, f32>) -> Vector<N, f32> {
-        let mut r = Vec::new();
-        for i in 0..N {
-            r.push(self.v[i].clone() - rhs.v[i].clone());
-        }
-
-        Vector {
-            v: r
-        }
+        Vector { v: std::array::from_fn(|i| self.v[i] + rhs.v[i]) }
     }
 }
 
 impl<const N: usize, T> Vector<N, T> where T: Clone {
     pub fn x(&self) -> T {
-        return self.v[0].clone();
+        self.v[0].clone()
     }
 
     pub fn u(&self) -> T {
-        return self.v[0].clone();
+        self.v[0].clone()
     }
 
     pub fn r(&self) -> T {
-        return self.v[0].clone();
+        self.v[0].clone()
     }
 
     pub fn y(&self) -> T {
-        return self.v[1].clone();
+        self.v[1].clone()
     }
 
     pub fn v(&self) -> T {
-        return self.v[1].clone();
+        self.v[1].clone()
     }
 
     pub fn g(&self) -> T {
-        return self.v[1].clone();
+        self.v[1].clone()
     }
 
     pub fn z(&self) -> T {
-        return self.v[2].clone();
+        self.v[2].clone()
     }
 
     pub fn b(&self) -> T {
-        return self.v[2].clone();
+        self.v[2].clone()
     }
 
     pub fn w(&self) -> T {
-        return self.v[3].clone();
+        self.v[3].clone()
     }
 }
 
 impl Vector<4, f32> {
     pub fn cross_product(&self, v1: &Vector<4, f32>) -> Vector<4, f32> {
         Vector {
-            v: vec![
+            v: [
                 self.v[1] * v1.v[2] - self.v[2] * v1.v[1],
                 self.v[2] * v1.v[0] - self.v[0] * v1.v[2],
                 self.v[0] * v1.v[1] - self.v[1] * v1.v[0],
@@ -153,6 +150,85 @@ impl Vector<4, f32> {
     }
 }
 
+impl<const N: usize, T> Index<usize> for Vector<N, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        &self.v[i]
+    }
+}
+
+impl<const N: usize, T> IndexMut<usize> for Vector<N, T> {
+    fn index_mut(&mut self, i: usize) -> &mut T {
+        &mut self.v[i]
+    }
+}
+
+impl<const N: usize, T> Add for Vector<N, T> where T: Clone + Add<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        &self + &rhs
+    }
+}
+
+impl<const N: usize, T> Add for &Vector<N, T> where T: Clone + Add<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vector { v: std::array::from_fn(|i| self.v[i].clone() + rhs.v[i].clone()) }
+    }
+}
+
+impl<const N: usize, T> Sub for Vector<N, T> where T: Clone + Sub<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        &self - &rhs
+    }
+}
+
+impl<const N: usize, T> Sub for &Vector<N, T> where T: Clone + Sub<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vector { v: std::array::from_fn(|i| self.v[i].clone() - rhs.v[i].clone()) }
+    }
+}
+
+/// 数乘
+impl<const N: usize, T> Mul<T> for Vector<N, T> where T: Clone + Mul<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        &self * rhs
+    }
+}
+
+impl<const N: usize, T> Mul<T> for &Vector<N, T> where T: Clone + Mul<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        Vector { v: std::array::from_fn(|i| self.v[i].clone() * rhs.clone()) }
+    }
+}
+
+impl<const N: usize, T> Neg for Vector<N, T> where T: Clone + Neg<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn neg(self) -> Self::Output {
+        -&self
+    }
+}
+
+impl<const N: usize, T> Neg for &Vector<N, T> where T: Clone + Neg<Output = T> {
+    type Output = Vector<N, T>;
+
+    fn neg(self) -> Self::Output {
+        Vector { v: std::array::from_fn(|i| -self.v[i].clone()) }
+    }
+}
+
 pub fn vector4f_interpolation(v1: &Vector4f, v2: &Vector4f, t: f32) -> Vector4f {
     Vector4f::new_4(
         interpolation(v1.x(), v2.x(), t),
@@ -160,4 +236,4 @@ pub fn vector4f_interpolation(v1: &Vector4f, v2: &Vector4f, t: f32) -> Vector4f
         interpolation(v1.z(), v2.z(), t),
         interpolation(v1.w(), v2.w(), t)
     )
-}
\ No newline at end of file
+}
@@ -0,0 +1,54 @@
+use crate::fixed_pipeline::rasterizer::{get_presp_projection_matrix, Rasterizer};
+use crate::fixed_pipeline::shadow::get_look_at_matrix;
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::Vector3f;
+
+/// 透视相机，位置和目标点是世界空间的，near和far按投影矩阵的约定取负值
+#[derive(Clone)]
+pub struct Camera {
+    pub position: Vector3f,
+    pub target: Vector3f,
+    /// 垂直视角(度)
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vector3f, target: Vector3f, aspect_ratio: f32) -> Camera {
+        Camera {
+            position,
+            target,
+            fov: 60.0,
+            aspect_ratio,
+            near: -0.1,
+            far: -50.0,
+        }
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near = near;
+        self.far = far;
+    }
+
+    pub fn get_view_matrix(&self) -> Mat4x4f {
+        get_look_at_matrix(&self.position, &self.target.sub(&self.position))
+    }
+
+    pub fn get_projection_matrix(&self) -> Mat4x4f {
+        get_presp_projection_matrix(self.fov, self.aspect_ratio, self.near, self.far)
+    }
+
+    /// 设置rasterizer的view、projection和eye_pos，model由场景里的节点决定
+    pub fn apply(&self, rasterizer: &mut Rasterizer) {
+        rasterizer.set_view(self.get_view_matrix());
+        rasterizer.set_projection(self.get_projection_matrix());
+        rasterizer.set_eye_pos(self.position);
+        rasterizer.compute_mvp();
+    }
+}
@@ -0,0 +1,327 @@
+use crate::common::mesh::Mesh;
+use crate::common::triangle::Triangle;
+use crate::math::matrix::Mat4x4f;
+use crate::math::quaternion::Quaternion;
+use crate::math::transform::Transform;
+use crate::math::vector::Vector3f;
+
+use super::camera::Camera;
+
+/// 节点在Scene::nodes里的下标
+pub type NodeId = usize;
+
+/// 场景图的节点，局部变换是相对父节点的平移、旋转、缩放
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    /// Scene::meshes里的下标，多个节点可以引用同一个网格(实例化)
+    pub mesh: Option<usize>,
+    /// 覆盖网格自己的材质
+    pub material: Option<usize>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            transform: Transform::identity(),
+            mesh: None,
+            material: None,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    pub fn set_translation(&mut self, v: Vector3f) {
+        self.transform.set_translation(v);
+    }
+
+    pub fn set_rotation(&mut self, q: Quaternion) {
+        self.transform.set_rotation(q);
+    }
+
+    pub fn set_scale(&mut self, v: Vector3f) {
+        self.transform.set_scale(v);
+    }
+
+    pub fn set_mesh(&mut self, mesh: Option<usize>) {
+        self.mesh = mesh;
+    }
+
+    pub fn set_material(&mut self, material: Option<usize>) {
+        self.material = material;
+    }
+
+    pub fn get_parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &Vec<NodeId> {
+        &self.children
+    }
+
+    pub fn get_local_matrix(&self) -> Mat4x4f {
+        self.transform.get_matrix()
+    }
+}
+
+/// 网格的一次绘制：引用的网格、世界矩阵和生效的材质
+pub struct Instance<'a> {
+    pub mesh: &'a Mesh,
+    pub world: Mat4x4f,
+    pub material: Option<usize>,
+}
+
+/// 场景图：节点按下标存储，父节点的世界矩阵乘以子节点的局部矩阵得到子节点的世界矩阵
+/// 世界矩阵不缓存，每次按当前的节点变换计算，修改变换或父节点后不需要额外的更新
+pub struct Scene {
+    nodes: Vec<Node>,
+    pub meshes: Vec<Mesh>,
+    /// None时使用rasterizer当前的view和projection
+    pub camera: Option<Camera>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            nodes: Vec::new(),
+            meshes: Vec::new(),
+            camera: None,
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: Option<Camera>) {
+        self.camera = camera;
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    /// 添加节点，parent为None时是根节点
+    pub fn add_node(&mut self, mut node: Node, parent: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        node.parent = None;
+        node.children.clear();
+        self.nodes.push(node);
+        self.set_parent(id, parent);
+        id
+    }
+
+    /// 把节点挂到新的父节点下，不能挂到自己或自己的子孙节点下
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        if let Some(p) = parent {
+            let mut cur = Some(p);
+            while let Some(c) = cur {
+                assert!(c != id, "node {} can not be a descendant of itself", id);
+                cur = self.nodes[c].parent;
+            }
+        }
+
+        if let Some(old) = self.nodes[id].parent {
+            self.nodes[old].children.retain(|&c| c != id);
+        }
+        self.nodes[id].parent = parent;
+        if let Some(p) = parent {
+            self.nodes[p].children.push(id);
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn get_node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn get_node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn roots(&self) -> Vec<NodeId> {
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].parent.is_none()).collect()
+    }
+
+    /// 沿父节点往上依次左乘局部矩阵
+    pub fn get_world_matrix(&self, id: NodeId) -> Mat4x4f {
+        let mut world = self.nodes[id].get_local_matrix();
+        let mut cur = self.nodes[id].parent;
+        while let Some(p) = cur {
+            world = self.nodes[p].get_local_matrix().mul(&world);
+            cur = self.nodes[p].parent;
+        }
+        world
+    }
+
+    /// 从根节点往下传播，一次算出所有节点的世界矩阵
+    fn compute_world_matrices(&self) -> Vec<Mat4x4f> {
+        let mut world = vec![Mat4x4f::identity(); self.nodes.len()];
+        let mut stack: Vec<(NodeId, Mat4x4f)> = self.roots().into_iter().map(|r| (r, Mat4x4f::identity())).collect();
+        while let Some((id, parent)) = stack.pop() {
+            let node = &self.nodes[id];
+            world[id] = parent.mul(&node.get_local_matrix());
+            for &c in node.children.iter() {
+                stack.push((c, world[id]));
+            }
+        }
+        world
+    }
+
+    /// 所有挂了网格的节点，世界矩阵按当前的节点变换计算
+    pub fn instances(&self) -> Vec<Instance<'_>> {
+        let world = self.compute_world_matrices();
+        self.nodes
+            .iter()
+            .zip(world)
+            .filter_map(|(node, world)| {
+                let mesh = &self.meshes[node.mesh?];
+                Some(Instance { mesh, world, material: node.material.or(mesh.material) })
+            })
+            .collect()
+    }
+
+    /// 变换到世界空间的三角形，生成阴影贴图时model需要设为单位矩阵
+    pub fn world_triangles(&self) -> Vec<Triangle> {
+        let mut result = Vec::new();
+        for inst in self.instances() {
+            for mut t in inst.mesh.triangles() {
+                for v in t.vertexs.iter_mut() {
+                    v.origin_v = inst.world.apply(&v.origin_v);
+                }
+                t.set_material(inst.material);
+                result.push(t);
+            }
+        }
+        result
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector4f;
+
+    fn translated(name: &str, x: f32, y: f32, z: f32) -> Node {
+        let mut node = Node::new(name);
+        node.set_translation(Vector3f::new_3(x, y, z));
+        node
+    }
+
+    /// 世界矩阵作用在原点上的结果
+    fn origin(m: &Mat4x4f) -> [f32; 3] {
+        let p = m.apply(&Vector4f::new_4(0.0, 0.0, 0.0, 1.0));
+        [p.x(), p.y(), p.z()]
+    }
+
+    #[test]
+    fn world_matrix_propagates_to_children() {
+        let mut scene = Scene::new();
+        let mut root = translated("root", 1.0, 0.0, 0.0);
+        root.set_scale(Vector3f::new_3(2.0, 2.0, 2.0));
+        let root = scene.add_node(root, None);
+        let child = scene.add_node(translated("child", 0.0, 2.0, 0.0), Some(root));
+        let grandchild = scene.add_node(translated("grandchild", 0.0, 0.0, 1.0), Some(child));
+
+        assert_eq!(origin(&scene.get_world_matrix(root)), [1.0, 0.0, 0.0]);
+        assert_eq!(origin(&scene.get_world_matrix(child)), [1.0, 4.0, 0.0]);
+        assert_eq!(origin(&scene.get_world_matrix(grandchild)), [1.0, 4.0, 2.0]);
+
+        //修改父节点后子节点立即跟着变化
+        scene.get_node_mut(root).set_scale(Vector3f::new_3(1.0, 1.0, 1.0));
+        scene.get_node_mut(child).set_translation(Vector3f::new_3(0.0, 3.0, 0.0));
+        assert_eq!(origin(&scene.get_world_matrix(grandchild)), [1.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn reparenting() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(translated("a", 1.0, 0.0, 0.0), None);
+        let b = scene.add_node(translated("b", 0.0, 5.0, 0.0), None);
+        let child = scene.add_node(translated("child", 0.0, 0.0, 1.0), Some(a));
+        assert_eq!(scene.get_node(a).get_children(), &vec![child]);
+        assert_eq!(origin(&scene.get_world_matrix(child)), [1.0, 0.0, 1.0]);
+
+        scene.set_parent(child, Some(b));
+        assert!(scene.get_node(a).get_children().is_empty());
+        assert_eq!(scene.get_node(b).get_children(), &vec![child]);
+        assert_eq!(scene.get_node(child).get_parent(), Some(b));
+        assert_eq!(origin(&scene.get_world_matrix(child)), [0.0, 5.0, 1.0]);
+
+        scene.set_parent(child, None);
+        assert!(scene.get_node(b).get_children().is_empty());
+        assert_eq!(scene.roots(), vec![a, b, child]);
+        assert_eq!(origin(&scene.get_world_matrix(child)), [0.0, 0.0, 1.0]);
+
+        //挂到原来子节点的兄弟下面是允许的
+        let d = scene.add_node(Node::new("d"), Some(a));
+        scene.set_parent(a, Some(b));
+        scene.set_parent(child, Some(d));
+        assert_eq!(origin(&scene.get_world_matrix(child)), [1.0, 5.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parent_can_not_be_descendant() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(Node::new("a"), None);
+        let b = scene.add_node(Node::new("b"), Some(a));
+        let c = scene.add_node(Node::new("c"), Some(b));
+        scene.set_parent(a, Some(c));
+    }
+
+    #[test]
+    #[should_panic]
+    fn parent_can_not_be_self() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(Node::new("a"), None);
+        scene.set_parent(a, Some(a));
+    }
+
+    #[test]
+    fn instances_use_world_matrix_and_material_override() {
+        let mut scene = Scene::new();
+        let mut mesh = Mesh::new(Vec::new(), Vec::new());
+        mesh.set_material(Some(1));
+        let mesh = scene.add_mesh(mesh);
+        let plain = scene.add_mesh(Mesh::new(Vec::new(), Vec::new()));
+
+        let root = scene.add_node(translated("root", 0.0, 1.0, 0.0), None);
+        let mut inherit = translated("inherit", 1.0, 0.0, 0.0);
+        inherit.set_mesh(Some(mesh));
+        let inherit = scene.add_node(inherit, Some(root));
+        let mut overridden = Node::new("override");
+        overridden.set_mesh(Some(mesh));
+        overridden.set_material(Some(4));
+        scene.add_node(overridden, Some(inherit));
+        let mut none = Node::new("none");
+        none.set_mesh(Some(plain));
+        scene.add_node(none, None);
+
+        let instances = scene.instances();
+        let summary: Vec<_> = instances.iter().map(|i| (origin(&i.world), i.material)).collect();
+        assert_eq!(summary, vec![
+            ([1.0, 1.0, 0.0], Some(1)),
+            ([1.0, 1.0, 0.0], Some(4)),
+            ([0.0, 0.0, 0.0], None),
+        ]);
+        assert!(std::ptr::eq(instances[0].mesh, &scene.meshes[mesh]));
+        assert!(std::ptr::eq(instances[2].mesh, &scene.meshes[plain]));
+        //instances和get_world_matrix的结果一致
+        assert_eq!(instances[0].world.m, scene.get_world_matrix(inherit).m);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::common::environment::view_to_world;
+use crate::common::light::{compute_light, PhongSurface};
+use crate::common::material::TextureSlot;
+use crate::common::pbr::{compute_ibl, compute_pbr_light, PbrSurface};
+use crate::common::triangle::Vertex;
+use crate::math::vector::{Vector2f, Vector3f, Vector4f};
+
+use super::program::{Fragment, FragmentShader, Uniforms, VertexOutput, VertexShader};
+
+/// DefaultVertexShader输出的varyings布局
+pub const VARYING_POSITION: usize = 0;
+pub const VARYING_NORMAL: usize = 3;
+pub const VARYING_TEX_COORDS: usize = 6;
+pub const VARYING_COLOR: usize = 8;
+pub const VARYING_TANGENT: usize = 11;
+pub const VARYING_COUNT: usize = 15;
+
+/// 输出相机空间坐标、法线、纹理坐标、顶点颜色和切线
+pub struct DefaultVertexShader;
+
+impl VertexShader for DefaultVertexShader {
+    fn shade(&self, vertex: &Vertex, uniforms: &Uniforms) -> VertexOutput {
+        let mv = uniforms.mv.apply(&vertex.origin_v);
+        let (n, t) = (&vertex.normal, &vertex.tangent);
+        let normal = uniforms.normal_matrix.apply(&Vector4f::new_4(n.x(), n.y(), n.z(), 0.0));
+        //切线跟着表面变换，w(副切线的方向)不变
+        let tangent = uniforms.mv.apply(&Vector4f::new_4(t.x(), t.y(), t.z(), 0.0));
+        let mut varyings = Vec::with_capacity(VARYING_COUNT);
+        varyings.extend_from_slice(&mv.v[0..3]);
+        varyings.extend_from_slice(&normal.v[0..3]);
+        varyings.extend_from_slice(&vertex.tex_coords.v);
+        varyings.extend_from_slice(&vertex.color.v);
+        varyings.extend_from_slice(&tangent.v[0..3]);
+        varyings.push(t.w());
+
+        VertexOutput {
+            position: uniforms.mvp.apply(&vertex.origin_v),
+            varyings,
+        }
+    }
+}
+
+/// 直接输出插值后的顶点颜色
+pub struct VertexColorShader;
+
+impl FragmentShader for VertexColorShader {
+    fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Option<Vector4f> {
+        let c = &fragment.varyings[VARYING_COLOR..VARYING_COLOR + 3];
+        Some(Vector4f::new_4(c[0], c[1], c[2], 1.0))
+    }
+}
+
+/// 不计算光照，输出albedo加上自发光，用于mtl的illum 0
+pub struct UnlitShader;
+
+impl FragmentShader for UnlitShader {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<Vector4f> {
+        let (uv, ddx, ddy) = tex_coords(fragment);
+        let albedo = albedo(fragment, uniforms, &uv, &ddx, &ddy);
+        let e = emissive(uniforms, &uv, &ddx, &ddy);
+        let color = Vector3f::new_3(albedo.r(), albedo.g(), albedo.b()).add(&e);
+        Some(Vector4f::new_4(color.r(), color.g(), color.b(), albedo.w() * opacity(uniforms, &uv, &ddx, &ddy)))
+    }
+}
+
+/// 使用材质参数的Blinn-Phong光照
+/// 漫反射颜色是albedo纹理乘材质的diffuse，没有纹理时直接用顶点颜色(obj加载时已经是diffuse)
+/// ambient/specular纹理乘材质的颜色，shininess纹理的R通道乘材质的shininess
+pub struct BlinnPhongShader;
+
+impl FragmentShader for BlinnPhongShader {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<Vector4f> {
+        let (pos, n) = position_normal(fragment);
+        let (uv, ddx, ddy) = tex_coords(fragment);
+        let n = perturb_normal(fragment, uniforms, &n, &uv, &ddx, &ddy);
+
+        let material = uniforms.material;
+        let albedo = albedo(fragment, uniforms, &uv, &ddx, &ddy);
+        let mut color = Vector3f::new_3(albedo.r(), albedo.g(), albedo.b());
+
+        let lit = !uniforms.lights.is_empty();
+        if lit {
+            let amb_light = match uniforms.environment {
+                Some(_) => Vector3f::new(),
+                None => *uniforms.ambient_light,
+            };
+            let shininess = match uniforms.sample(TextureSlot::Shininess, &uv, &ddx, &ddy) {
+                Some(t) => material.shininess * t.r(),
+                None => material.shininess,
+            };
+            let surface = PhongSurface {
+                ambient: texture_color(uniforms, TextureSlot::Ambient, &material.ambient, &uv, &ddx, &ddy),
+                diffuse: color,
+                specular: texture_color(uniforms, TextureSlot::Specular, &material.specular, &uv, &ddx, &ddy),
+                shininess,
+            };
+            color = compute_light(&pos, &n, uniforms.lights, &amb_light, &surface, &uniforms.view_eye_pos);
+        }
+
+        //环境贴图代替常量环境光：法线方向的辐照度 * 漫反射颜色
+        if let Some(env) = uniforms.environment {
+            let e = env.irradiance(&view_to_world(uniforms.view, &n));
+            let a = Vector3f::new_3(e.r() * albedo.r(), e.g() * albedo.g(), e.b() * albedo.b());
+            color = if lit { color.add(&a) } else { a };
+        }
+
+        let e = emissive(uniforms, &uv, &ddx, &ddy);
+        color = color.add(&e);
+
+        Some(Vector4f::new_4(color.r(), color.g(), color.b(), albedo.w() * opacity(uniforms, &uv, &ddx, &ddy)))
+    }
+}
+
+/// metallic/roughness工作流的Cook-Torrance光照
+/// roughness纹理取G通道，metallic纹理取B通道，occlusion纹理取R通道，都和材质参数相乘
+pub struct PbrShader;
+
+impl FragmentShader for PbrShader {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<Vector4f> {
+        let (pos, n) = position_normal(fragment);
+        let (uv, ddx, ddy) = tex_coords(fragment);
+        let n = perturb_normal(fragment, uniforms, &n, &uv, &ddx, &ddy);
+
+        let material = uniforms.material;
+        let albedo = albedo(fragment, uniforms, &uv, &ddx, &ddy);
+        let mut surface = PbrSurface {
+            base_color: Vector3f::new_3(albedo.r(), albedo.g(), albedo.b()),
+            metallic: material.metallic,
+            roughness: material.roughness,
+            ao: 1.0,
+        };
+        if let Some(t) = uniforms.sample(TextureSlot::Roughness, &uv, &ddx, &ddy) {
+            surface.roughness *= t.g();
+        }
+        if let Some(t) = uniforms.sample(TextureSlot::Metallic, &uv, &ddx, &ddy) {
+            surface.metallic *= t.b();
+        }
+        if let Some(t) = uniforms.sample(TextureSlot::Occlusion, &uv, &ddx, &ddy) {
+            surface.ao = t.r();
+        }
+
+        //没有环境贴图时环境光和Blinn-Phong一样乘材质的ambient
+        let color = match uniforms.environment {
+            Some(env) => {
+                let eye = &uniforms.view_eye_pos;
+                let direct = compute_pbr_light(&pos, &n, uniforms.lights, &Vector3f::new(), &surface, eye);
+                direct.add(&compute_ibl(&pos, &n, &surface, env, eye, uniforms.view))
+            }
+            None => {
+                let ka = texture_color(uniforms, TextureSlot::Ambient, &material.ambient, &uv, &ddx, &ddy);
+                let a = uniforms.ambient_light;
+                let ambient = Vector3f::new_3(a.r() * ka.r(), a.g() * ka.g(), a.b() * ka.b());
+                compute_pbr_light(&pos, &n, uniforms.lights, &ambient, &surface, &uniforms.view_eye_pos)
+            }
+        };
+
+        let e = emissive(uniforms, &uv, &ddx, &ddy);
+        let color = color.add(&e);
+
+        Some(Vector4f::new_4(color.r(), color.g(), color.b(), albedo.w() * opacity(uniforms, &uv, &ddx, &ddy)))
+    }
+}
+
+/// 相机空间的位置和插值后的法线，法线重新归一化(插值和法线矩阵都会改变长度)
+fn position_normal(fragment: &Fragment) -> (Vector3f, Vector3f) {
+    let v = fragment.varyings;
+    let pos = Vector3f::new_3(v[VARYING_POSITION], v[VARYING_POSITION + 1], v[VARYING_POSITION + 2]);
+    let mut n = Vector3f::new_3(v[VARYING_NORMAL], v[VARYING_NORMAL + 1], v[VARYING_NORMAL + 2]);
+    if n.length() > 0.0 {
+        n.normlize();
+    }
+    (pos, n)
+}
+
+/// 绑定了法线贴图并且有切线时，把切线空间的法线转换到法线所在的空间，否则返回原来的法线
+fn perturb_normal(fragment: &Fragment, uniforms: &Uniforms, n: &Vector3f, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Vector3f {
+    let t = &fragment.varyings[VARYING_TANGENT..VARYING_TANGENT + 4];
+    let map = match uniforms.sample(TextureSlot::Normal, uv, ddx, ddy) {
+        Some(map) if t[0] != 0.0 || t[1] != 0.0 || t[2] != 0.0 => map,
+        _ => return *n,
+    };
+
+    //插值后重新正交化，w插值后可能不是±1
+    let mut n = *n;
+    n.normlize();
+    let t = Vector3f::new_3(t[0], t[1], t[2]);
+    let mut t = t.sub(&scale(&n, n.dot_product(&t)));
+    t.normlize();
+    let sign = if fragment.varyings[VARYING_TANGENT + 3] < 0.0 { -1.0 } else { 1.0 };
+    let b = scale(&cross(&n, &t), sign);
+
+    let (x, y, z) = (map.r() * 2.0 - 1.0, map.g() * 2.0 - 1.0, map.b() * 2.0 - 1.0);
+    let mut r = scale(&t, x).add(&scale(&b, y)).add(&scale(&n, z));
+    r.normlize();
+    r
+}
+
+fn scale(v: &Vector3f, s: f32) -> Vector3f {
+    Vector3f::new_3(v.x() * s, v.y() * s, v.z() * s)
+}
+
+fn cross(a: &Vector3f, b: &Vector3f) -> Vector3f {
+    Vector3f::new_3(
+        a.y() * b.z() - a.z() * b.y(),
+        a.z() * b.x() - a.x() * b.z(),
+        a.x() * b.y() - a.y() * b.x(),
+    )
+}
+
+/// 纹理坐标和它在屏幕上的导数
+fn tex_coords(fragment: &Fragment) -> (Vector2f, Vector2f, Vector2f) {
+    //图片的v轴朝下，导数的符号也要翻转
+    let t = VARYING_TEX_COORDS;
+    let v = fragment.varyings;
+    let uv = Vector2f::new_2(v[t], 1.0 - v[t + 1]);
+    let ddx = Vector2f::new_2(fragment.ddx[t], -fragment.ddx[t + 1]);
+    let ddy = Vector2f::new_2(fragment.ddy[t], -fragment.ddy[t + 1]);
+    (uv, ddx, ddy)
+}
+
+/// albedo纹理乘材质的diffuse，没有纹理时用顶点颜色
+fn albedo(fragment: &Fragment, uniforms: &Uniforms, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Vector4f {
+    match uniforms.sample(TextureSlot::Albedo, uv, ddx, ddy) {
+        Some(t) => {
+            let d = &uniforms.material.diffuse;
+            Vector4f::new_4(t.r() * d.r(), t.g() * d.g(), t.b() * d.b(), t.w())
+        }
+        None => {
+            let c = &fragment.varyings[VARYING_COLOR..VARYING_COLOR + 3];
+            Vector4f::new_4(c[0], c[1], c[2], 1.0)
+        }
+    }
+}
+
+/// 材质的emissive乘emissive纹理
+fn emissive(uniforms: &Uniforms, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Vector3f {
+    texture_color(uniforms, TextureSlot::Emissive, &uniforms.material.emissive, uv, ddx, ddy)
+}
+
+/// color乘slot上纹理的rgb，没有绑定纹理时返回color
+fn texture_color(uniforms: &Uniforms, slot: TextureSlot, color: &Vector3f, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Vector3f {
+    match uniforms.sample(slot, uv, ddx, ddy) {
+        Some(t) => Vector3f::new_3(color.r() * t.r(), color.g() * t.g(), color.b() * t.b()),
+        None => *color,
+    }
+}
+
+/// 材质的opacity乘opacity纹理的R通道
+fn opacity(uniforms: &Uniforms, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> f32 {
+    let d = uniforms.material.opacity;
+    match uniforms.sample(TextureSlot::Opacity, uv, ddx, ddy) {
+        Some(t) => d * t.r(),
+        None => d,
+    }
+}
@@ -0,0 +1,91 @@
+use crate::common::environment::Environment;
+use crate::common::light::Light;
+use crate::common::material::{Material, TextureSlot, TEXTURE_SLOT_COUNT};
+use crate::common::sampler::Sampler;
+use crate::common::texture::Texture;
+use crate::common::triangle::{Triangle, Vertex};
+use crate::math::matrix::Mat4x4f;
+use crate::math::vector::{Vector2f, Vector3f, Vector4f};
+
+/// 着色器可以访问的全局数据
+pub struct Uniforms<'a> {
+    pub model: &'a Mat4x4f,
+    pub view: &'a Mat4x4f,
+    pub projection: &'a Mat4x4f,
+    pub mv: &'a Mat4x4f,
+    pub mvp: &'a Mat4x4f,
+    /// mv的逆转置，把法线变换到相机空间
+    pub normal_matrix: &'a Mat4x4f,
+    /// 相机空间到世界空间
+    pub view_inverse: &'a Mat4x4f,
+    pub lights: &'a Vec<Light>,
+    pub ambient_light: &'a Vector3f,
+    /// 基于图像的光照，设置时代替ambient_light
+    pub environment: Option<&'a Environment>,
+    /// 世界空间的相机位置
+    pub eye_pos: &'a Vector3f,
+    /// 相机空间的相机位置，光照和位置、法线、光源一样在相机空间计算
+    pub view_eye_pos: Vector3f,
+    pub textures: &'a Vec<Texture>,
+    /// 当前三角形的材质，没有设置时是Rasterizer的默认材质
+    pub material: &'a Material,
+    /// 材质各槽位绑定的纹理和采样方式，按TextureSlot::index排列，创建Uniforms时解析一次
+    pub bindings: [Option<(&'a Texture, &'a Sampler)>; TEXTURE_SLOT_COUNT],
+}
+
+impl<'a> Uniforms<'a> {
+    pub fn get_texture(&self, id: i32) -> Option<&'a Texture> {
+        self.textures.iter().find(|t| t.get_id() == id)
+    }
+
+    /// 采样材质上slot绑定的纹理，没有绑定时返回None
+    pub fn sample(&self, slot: TextureSlot, uv: &Vector2f, ddx: &Vector2f, ddy: &Vector2f) -> Option<Vector4f> {
+        let (texture, sampler) = self.bindings[slot.index()]?;
+        Some(texture.sample(sampler, uv, ddx, ddy))
+    }
+}
+
+pub struct VertexOutput {
+    /// 裁剪空间坐标
+    pub position: Vector4f,
+    /// 自定义的插值数据，光栅化时做透视校正插值
+    pub varyings: Vec<f32>,
+}
+
+pub struct Fragment<'a> {
+    pub x: i32,
+    pub y: i32,
+    pub z: f32,
+    pub varyings: &'a [f32],
+    /// varyings在屏幕x/y方向上到相邻像素的差，用于选择mipmap等级
+    pub ddx: &'a [f32],
+    pub ddy: &'a [f32],
+}
+
+pub trait VertexShader: Send + Sync {
+    fn shade(&self, vertex: &Vertex, uniforms: &Uniforms) -> VertexOutput;
+}
+
+pub trait FragmentShader: Send + Sync {
+    /// 返回rgba颜色(0~1)，返回None表示丢弃该片元
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<Vector4f>;
+}
+
+/// 用屏幕空间的重心坐标对varyings做透视校正插值
+pub fn interpolate_varyings(triangle: &Triangle, alpha: f32, beta: f32, gamma: f32) -> Vec<f32> {
+    let a = &triangle.vertexs[0];
+    let b = &triangle.vertexs[1];
+    let c = &triangle.vertexs[2];
+
+    let wa = alpha * a.rhw;
+    let wb = beta * b.rhw;
+    let wc = gamma * c.rhw;
+    let w = 1.0 / (wa + wb + wc);
+
+    let mut r = Vec::with_capacity(a.varyings.len());
+    for i in 0..a.varyings.len() {
+        r.push((wa * a.varyings[i] + wb * b.varyings[i] + wc * c.varyings[i]) * w);
+    }
+
+    r
+}